@@ -0,0 +1,150 @@
+use crate::evaluator::is_truthy;
+use crate::state::{DapState, StoredBreakpoint};
+
+// Что делать, дойдя до брейка. Stop собирает id всех брейков строки, на которых стоим
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BreakpointDecision {
+    Stop { ids: Vec<i64> },
+    Log { message: String },
+    Continue,
+}
+
+// Решение об остановке отдельно от pick_stop_location. Бэкенд со своими условиями
+// (например, вычисляемыми в самом debuggee) подменяет DapState.breakpoint_matcher.
+pub(crate) trait BreakpointMatcher: std::fmt::Debug {
+    // condition брейка; в hits идут только попадания с выполнившимся условием
+    fn condition_holds(&self, bp: &StoredBreakpoint, st: &DapState) -> bool;
+    // hits — уже с учётом этого попадания
+    fn decide(&self, bp: &StoredBreakpoint, hits: u64, st: &DapState) -> BreakpointDecision;
+}
+
+// condition через DapState.evaluator, hitCondition, logpoint'ы с {выражениями}
+#[derive(Debug, Default)]
+pub(crate) struct DefaultMatcher;
+
+impl BreakpointMatcher for DefaultMatcher {
+    fn condition_holds(&self, bp: &StoredBreakpoint, st: &DapState) -> bool {
+        match &bp.condition {
+            // Ошибка в условии — останавливаемся, чтобы пользователь увидел проблему
+            Some(cond) => st
+                .evaluator
+                .eval(cond, None, st)
+                .map(|v| is_truthy(&v))
+                .unwrap_or(true),
+            None => true,
+        }
+    }
+
+    fn decide(&self, bp: &StoredBreakpoint, hits: u64, st: &DapState) -> BreakpointDecision {
+        if !hit_condition_met(bp.hit_condition.as_deref(), hits) {
+            return BreakpointDecision::Continue;
+        }
+        match &bp.log_message {
+            Some(message) => BreakpointDecision::Log {
+                message: st.interpolate_log_message(message),
+            },
+            None => BreakpointDecision::Stop { ids: vec![bp.id] },
+        }
+    }
+}
+
+// hitCondition: "N" (с N-го попадания), "== N", ">= N", "> N", "<= N", "< N", "% N".
+// Непонятное условие — останавливаемся, как и при ошибке в condition.
+fn hit_condition_met(condition: Option<&str>, hits: u64) -> bool {
+    let Some(condition) = condition else {
+        return true;
+    };
+    let condition = condition.trim();
+    let (op, rest) = ["==", ">=", "<=", ">", "<", "%"]
+        .iter()
+        .find_map(|op| condition.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or((">=", condition));
+    let Ok(n) = rest.trim().parse::<u64>() else {
+        return true;
+    };
+    match op {
+        "==" => hits == n,
+        ">=" => hits >= n,
+        "<=" => hits <= n,
+        ">" => hits > n,
+        "<" => hits < n,
+        _ => n != 0 && hits.is_multiple_of(n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_hit_condition_always_stops() {
+        assert!(hit_condition_met(None, 0));
+        assert!(hit_condition_met(None, 7));
+    }
+
+    #[test]
+    fn bare_number_means_from_the_nth_hit() {
+        assert!(!hit_condition_met(Some("3"), 2));
+        assert!(hit_condition_met(Some(" 3 "), 3));
+        assert!(hit_condition_met(Some("3"), 4));
+    }
+
+    #[test]
+    fn comparison_operators() {
+        assert!(hit_condition_met(Some("== 2"), 2));
+        assert!(!hit_condition_met(Some("==2"), 3));
+        assert!(hit_condition_met(Some(">= 2"), 2));
+        assert!(!hit_condition_met(Some("> 2"), 2));
+        assert!(hit_condition_met(Some("<= 2"), 2));
+        assert!(!hit_condition_met(Some("< 2"), 2));
+    }
+
+    #[test]
+    fn modulo_stops_on_every_nth_hit() {
+        assert!(!hit_condition_met(Some("% 3"), 2));
+        assert!(hit_condition_met(Some("% 3"), 3));
+        assert!(hit_condition_met(Some("%3"), 6));
+        assert!(!hit_condition_met(Some("% 0"), 3));
+    }
+
+    #[test]
+    fn unparsable_condition_stops() {
+        assert!(hit_condition_met(Some("often"), 1));
+        assert!(hit_condition_met(Some("== x"), 1));
+        assert!(hit_condition_met(Some("-1"), 1));
+    }
+
+    fn breakpoint(hit_condition: Option<&str>, log_message: Option<&str>) -> StoredBreakpoint {
+        let bp = dap::types::SourceBreakpoint {
+            line: 3,
+            hit_condition: hit_condition.map(str::to_string),
+            log_message: log_message.map(str::to_string),
+            ..Default::default()
+        };
+        StoredBreakpoint::from_source(10_000, &bp)
+    }
+
+    #[test]
+    fn decisions_for_each_outcome() {
+        let st = DapState::new();
+        let decide = |bp: &StoredBreakpoint, hits| DefaultMatcher.decide(bp, hits, &st);
+        assert_eq!(
+            decide(&breakpoint(None, None), 1),
+            BreakpointDecision::Stop { ids: vec![10_000] }
+        );
+        assert_eq!(
+            decide(&breakpoint(None, Some("reached")), 1),
+            BreakpointDecision::Log {
+                message: "reached".to_string()
+            }
+        );
+        assert_eq!(
+            decide(&breakpoint(Some("== 2"), None), 1),
+            BreakpointDecision::Continue
+        );
+        assert_eq!(
+            decide(&breakpoint(Some("== 2"), Some("reached")), 1),
+            BreakpointDecision::Continue
+        );
+    }
+}
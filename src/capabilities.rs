@@ -0,0 +1,88 @@
+use dap::types::Capabilities;
+use serde_json::{json, Value};
+
+use crate::state::DapState;
+
+pub(crate) const PANIC_FILTER: &str = "panic";
+
+// ExceptionBreakpointsFilter из dap сериализуется в snake_case (supports_condition),
+// и клиент не предложил бы условие — собираем фильтры сами
+fn exception_filters() -> Value {
+    json!([{
+        "filter": PANIC_FILTER,
+        "label": "Panics",
+        "description": "Break when the program panics",
+        "default": false,
+        "supportsCondition": true,
+        "conditionDescription": "Expression evaluated when the panic occurs",
+    }])
+}
+
+// Тело ответа на initialize: capabilities плюс фильтры исключений в camelCase
+pub(crate) fn capabilities_body(caps: &Capabilities) -> Value {
+    let mut body = serde_json::to_value(caps).unwrap_or_else(|_| json!({}));
+    body["exceptionBreakpointFilters"] = exception_filters();
+    body
+}
+
+// Всё, что адаптер объявляет клиенту на initialize, — в одном месте.
+// Новый обработчик = новая строка здесь, иначе клиент его не вызовет.
+pub(crate) fn build_capabilities(st: &DapState) -> Capabilities {
+    let triggers = &st.completion_trigger_characters;
+
+    Capabilities {
+        supports_configuration_done_request: Some(true),
+        supports_set_variable: Some(true),
+        supports_step_back: Some(false),
+        supports_restart_request: Some(true),
+        support_terminate_debuggee: Some(true),
+        support_suspend_debuggee: Some(true),
+        supports_restart_frame: Some(false),
+        supports_goto_targets_request: Some(false),
+        supports_function_breakpoints: Some(true),
+        supports_data_breakpoints: Some(true),
+        supports_conditional_breakpoints: Some(true),
+        supports_breakpoint_locations_request: Some(true),
+        supports_log_points: Some(true),
+        supports_hit_conditional_breakpoints: Some(true),
+        supports_terminate_request: Some(false),
+        supports_evaluate_for_hovers: Some(true),
+        supports_clipboard_context: Some(true),
+        // exceptionBreakpointFilters — в capabilities_body
+        supports_exception_filter_options: Some(true),
+        supports_exception_info_request: Some(true),
+        supports_stepping_granularity: Some(true),
+        supports_completions_request: Some(true),
+        supports_modules_request: Some(true),
+        supports_value_formatting_options: Some(true),
+        supports_single_thread_execution_requests: Some(true),
+        // Пустой список с --completion-triggers= — не объявлять вовсе, пусть клиент решает сам
+        completion_trigger_characters: (!triggers.is_empty()).then(|| triggers.clone()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exception_filters_are_camel_case() {
+        let body = capabilities_body(&build_capabilities(&DapState::new()));
+        let filter = &body["exceptionBreakpointFilters"][0];
+        assert_eq!(filter["filter"], PANIC_FILTER);
+        assert_eq!(filter["supportsCondition"], true);
+        assert!(filter["conditionDescription"].is_string());
+        assert!(filter.get("supports_condition").is_none());
+        assert_eq!(body["supportsExceptionFilterOptions"], true);
+    }
+
+    #[test]
+    fn empty_completion_triggers_are_not_advertised() {
+        let mut st = DapState::new();
+        st.completion_trigger_characters.clear();
+        assert!(build_capabilities(&st)
+            .completion_trigger_characters
+            .is_none());
+    }
+}
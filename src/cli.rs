@@ -0,0 +1,247 @@
+use std::time::Duration;
+
+use crate::completions::default_trigger_characters;
+use crate::log::LogLevel;
+use crate::source_text::LineEndings;
+use crate::state::{DEFAULT_MAX_VARIABLE_DEPTH, DEFAULT_THREADS_CACHE_TTL};
+use crate::variables_cache::DEFAULT_VARIABLES_CACHE_SIZE;
+
+// Запасной источник для --adapter-id, когда клиент запускает адаптер без наших флагов
+pub(crate) const ADAPTER_ID_ENV: &str = "RUST_DAP_ADAPTER_ID";
+
+pub(crate) const USAGE: &str = "\
+Usage: rust-dap-adapter [OPTIONS]
+
+Speaks the Debug Adapter Protocol over stdin/stdout.
+
+Options:
+  --log-level <LEVEL>  Console log threshold: trace, debug, info, warn, error [default: debug]
+  --completion-triggers <LIST>
+                       Comma-separated REPL completion triggers [default: .,:,->]
+  --metrics            Record per-command timings (see `$metrics` in the REPL)
+  --sort-variables     List variables alphabetically, `__`-prefixed ones last
+  --variables-cache <N>
+                       Keep the last N expanded variable lists, 0 to disable [default: 64]
+  --max-variable-depth <N>
+                       Stop expanding nested variables below depth N [default: 32]
+  --threads-cache-ttl <MS>
+                       Reuse the threads list for MS milliseconds, 0 to disable [default: 200]
+  --line-endings <MODE>
+                       Line endings in served source content: keep or lf [default: keep]
+  --unknown-stack-depth
+                       Leave totalFrames out of stackTrace; clients page until a short page
+  --no-telemetry       Do not send telemetry output events
+  --adapter-id <ID>    Adapter id reported to the client on initialize
+                       [default: $RUST_DAP_ADAPTER_ID or rust-dap-adapter]
+  --record <FILE>      Save every inbound byte to FILE for later replay
+  --replay <FILE>      Read requests from a recorded FILE instead of stdin
+  --version            Print version and exit
+  --help               Print this help and exit";
+
+// Что делать после разбора аргументов: обычный DAP-цикл или ответить и выйти
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CliAction {
+    Run,
+    PrintVersion,
+    PrintHelp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CliOptions {
+    pub(crate) action: CliAction,
+    pub(crate) log_level: LogLevel,
+    pub(crate) completion_trigger_characters: Vec<String>,
+    pub(crate) metrics: bool,
+    pub(crate) sort_variables: bool,
+    pub(crate) variables_cache_size: usize,
+    pub(crate) max_variable_depth: usize,
+    pub(crate) threads_cache_ttl: Duration,
+    pub(crate) line_endings: LineEndings,
+    pub(crate) stack_depth_known: bool,
+    pub(crate) telemetry: bool,
+    pub(crate) adapter_id: Option<String>,
+    pub(crate) record: Option<String>,
+    pub(crate) replay: Option<String>,
+}
+
+impl Default for CliOptions {
+    fn default() -> Self {
+        Self {
+            action: CliAction::Run,
+            log_level: LogLevel::Debug,
+            completion_trigger_characters: default_trigger_characters(),
+            metrics: false,
+            sort_variables: false,
+            variables_cache_size: DEFAULT_VARIABLES_CACHE_SIZE,
+            max_variable_depth: DEFAULT_MAX_VARIABLE_DEPTH,
+            threads_cache_ttl: DEFAULT_THREADS_CACHE_TTL,
+            line_endings: LineEndings::Keep,
+            stack_depth_known: true,
+            telemetry: true,
+            adapter_id: None,
+            record: None,
+            replay: None,
+        }
+    }
+}
+
+pub(crate) fn version_string() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+// Значение флага: из `--flag=value` или следующим аргументом
+fn take_value<I: Iterator<Item = String>>(
+    args: &mut I,
+    flag: &str,
+    inline_value: Option<String>,
+) -> Result<String, String> {
+    inline_value
+        .or_else(|| args.next())
+        .ok_or_else(|| format!("{flag} requires a value"))
+}
+
+// Принимает и `--log-level=warn`, и `--log-level warn`
+pub(crate) fn parse_cli_args<I: IntoIterator<Item = String>>(
+    args: I,
+) -> Result<CliOptions, String> {
+    let mut opts = CliOptions::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        let mut value = || take_value(&mut args, &name, inline_value.clone());
+        match name.as_str() {
+            "--version" | "-V" => opts.action = CliAction::PrintVersion,
+            "--help" | "-h" => opts.action = CliAction::PrintHelp,
+            "--metrics" => opts.metrics = true,
+            "--sort-variables" => opts.sort_variables = true,
+            "--unknown-stack-depth" => opts.stack_depth_known = false,
+            "--no-telemetry" => opts.telemetry = false,
+            "--log-level" => opts.log_level = value()?.parse()?,
+            "--variables-cache" => {
+                let value = value()?;
+                opts.variables_cache_size = value
+                    .parse()
+                    .map_err(|_| format!("Invalid --variables-cache value '{value}'"))?;
+            }
+            "--max-variable-depth" => {
+                let value = value()?;
+                opts.max_variable_depth = value
+                    .parse()
+                    .map_err(|_| format!("Invalid --max-variable-depth value '{value}'"))?;
+            }
+            "--threads-cache-ttl" => {
+                let value = value()?;
+                let millis: u64 = value
+                    .parse()
+                    .map_err(|_| format!("Invalid --threads-cache-ttl value '{value}'"))?;
+                opts.threads_cache_ttl = Duration::from_millis(millis);
+            }
+            "--line-endings" => opts.line_endings = value()?.parse()?,
+            "--adapter-id" => opts.adapter_id = Some(value()?),
+            "--record" => opts.record = Some(value()?),
+            "--replay" => opts.replay = Some(value()?),
+            "--completion-triggers" => {
+                opts.completion_trigger_characters = value()?
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            _ => return Err(format!("Unknown argument '{arg}'")),
+        }
+    }
+
+    Ok(opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliOptions, String> {
+        parse_cli_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_give_defaults() {
+        assert_eq!(parse(&[]).unwrap(), CliOptions::default());
+    }
+
+    #[test]
+    fn values_are_taken_inline_or_from_the_next_argument() {
+        let inline = parse(&["--log-level=warn", "--adapter-id=rast"]).unwrap();
+        let separate = parse(&["--log-level", "warn", "--adapter-id", "rast"]).unwrap();
+        assert_eq!(inline, separate);
+        assert_eq!(inline.log_level, LogLevel::Warn);
+        assert_eq!(inline.adapter_id.as_deref(), Some("rast"));
+    }
+
+    #[test]
+    fn missing_value_names_the_flag() {
+        for flag in ["--log-level", "--variables-cache", "--record", "--replay"] {
+            assert_eq!(parse(&[flag]), Err(format!("{flag} requires a value")));
+        }
+    }
+
+    #[test]
+    fn numeric_flags_reject_garbage() {
+        assert_eq!(
+            parse(&["--variables-cache", "many"]),
+            Err("Invalid --variables-cache value 'many'".to_string())
+        );
+        assert!(parse(&["--max-variable-depth=-1"]).is_err());
+        assert!(parse(&["--threads-cache-ttl", "1.5"]).is_err());
+    }
+
+    #[test]
+    fn flags_and_lists() {
+        let opts = parse(&[
+            "--metrics",
+            "--sort-variables",
+            "--unknown-stack-depth",
+            "--no-telemetry",
+            "--threads-cache-ttl=0",
+            "--completion-triggers= . ,,::",
+            "--line-endings",
+            "lf",
+        ])
+        .unwrap();
+        assert!(opts.metrics && opts.sort_variables);
+        assert!(!opts.stack_depth_known && !opts.telemetry);
+        assert_eq!(opts.threads_cache_ttl, Duration::ZERO);
+        assert_eq!(opts.completion_trigger_characters, vec![".", "::"]);
+        assert_eq!(opts.line_endings, LineEndings::Lf);
+    }
+
+    #[test]
+    fn version_help_and_unknown_arguments() {
+        assert_eq!(parse(&["-V"]).unwrap().action, CliAction::PrintVersion);
+        assert_eq!(parse(&["--help"]).unwrap().action, CliAction::PrintHelp);
+        assert_eq!(
+            parse(&["--verbose"]),
+            Err("Unknown argument '--verbose'".to_string())
+        );
+    }
+
+    #[test]
+    fn version_and_help_answer_before_the_dap_loop() {
+        let opts = parse(&["--log-level", "warn", "--version"]).unwrap();
+        assert_eq!(opts.action, CliAction::PrintVersion);
+        assert_eq!(parse(&["-h"]).unwrap().action, CliAction::PrintHelp);
+        assert_eq!(
+            version_string(),
+            format!("rust-dap-adapter {}", env!("CARGO_PKG_VERSION"))
+        );
+        for flag in ["--version", "--help", "--log-level", "--record", "--replay"] {
+            assert!(
+                USAGE.contains(flag),
+                "{flag} is missing from the usage text"
+            );
+        }
+    }
+}
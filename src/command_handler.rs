@@ -1,370 +1,4186 @@
-use std::io::{Stdin, Stdout};
+use std::collections::BTreeMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
 
-use dap::base_message::Sendable;
-use dap::events::Event;
+use dap::events::{BreakpointEventBody, Event, InvalidatedEventBody, ThreadEventBody};
 use dap::requests::{
-    AttachRequestArguments, Command, ContinueArguments, DisconnectArguments, InitializeArguments,
-    LaunchRequestArguments, PauseArguments, Request, RestartArguments, ScopesArguments,
-    SetBreakpointsArguments, SetExceptionBreakpointsArguments, StackTraceArguments,
+    AttachRequestArguments, BreakpointLocationsArguments, Command, CompletionsArguments,
+    ContinueArguments, DataBreakpointInfoArguments, DisconnectArguments, EvaluateArguments,
+    ExceptionInfoArguments, InitializeArguments, LaunchRequestArguments, ModulesArguments,
+    PathFormat, PauseArguments, Request, RestartArguments, ScopesArguments,
+    SetBreakpointsArguments, SetDataBreakpointsArguments, SetExceptionBreakpointsArguments,
+    SetFunctionBreakpointsArguments, SetVariableArguments, SourceArguments, StackTraceArguments,
     VariablesArguments,
 };
 use dap::responses::{
-    ContinueResponse, Response, ResponseBody, ResponseMessage, ScopesResponse,
-    SetBreakpointsResponse, SetExceptionBreakpointsResponse, StackTraceResponse, ThreadsResponse,
-    VariablesResponse,
+    BreakpointLocationsResponse, CompletionsResponse, ContinueResponse, DataBreakpointInfoResponse,
+    EvaluateResponse, ExceptionInfoResponse, LoadedSourcesResponse, ModulesResponse, Response,
+    ResponseBody, ScopesResponse, SetBreakpointsResponse, SetDataBreakpointsResponse,
+    SetExceptionBreakpointsResponse, SetFunctionBreakpointsResponse, SetVariableResponse,
+    SourceResponse, StackTraceResponse, ThreadsResponse, VariablesResponse,
 };
-use dap::server::Server;
 use dap::types::{
-    Breakpoint, Capabilities, Scope, Source, StackFrame, StoppedEventReason, Thread, Variable,
+    Breakpoint, BreakpointEventReason, BreakpointLocation, DataBreakpointAccessType,
+    EvaluateArgumentsContext, ExceptionBreakMode, InvalidatedAreas, ModuleId, Scope,
+    ScopePresentationhint, StackFrame, StackFrameModuleid, StackFramePresentationhint,
+    SteppingGranularity, StoppedEventReason, ThreadEventReason, VariablesArgumentsFilter,
 };
+use serde_json::{json, Value};
 
-use crate::log::dap_log;
-use crate::state::DapState;
-use crate::types::DynResult;
-use crate::utils::extract_port_from_args;
+use crate::capabilities::{build_capabilities, capabilities_body, PANIC_FILTER};
+use crate::completions::completion_items;
+use crate::custom_request::{CustomHandler, CustomRequest, SPEC_COMMANDS};
+use crate::error::{HandlerError, HandlerResult};
+use crate::evaluator::{is_truthy, value_to_string};
+use crate::formatter::{frame_name, render_value};
+use crate::launch_config::LaunchConfig;
+use crate::log::{console_output, dap_debug, dap_log, dap_log_at, diagnostic_output, LogLevel};
+use crate::modules::{
+    load_module, modules_page, program_module, reload_program_module, unload_all_modules,
+};
+use crate::source_text::{breakpoint_columns, SourceText};
+use crate::state::{
+    is_index_variable, is_persistent_data_id, is_thread_id, DapState, SessionPhase, SourceKey,
+    StoredBreakpoint, StoredFunctionBreakpoint, RESTART_SEPARATOR,
+};
+use crate::telemetry::{report_first_stop, send_telemetry};
+use crate::trace::{trace_log, trace_write};
+use crate::types::{DapServer, DynResult};
+use crate::utils::{command_name, format_address, is_hover_safe};
 
 // --------------------
 // ROUTER
 // --------------------
-pub(crate) fn handle(
-    req: Request,
-    server: &mut Server<Stdin, Stdout>,
+pub(crate) fn handle(req: Request, server: &mut DapServer, state: &mut DapState) -> DynResult<()> {
+    let seq = req.seq;
+    let name = command_name(&req.command);
+    run_guarded(seq, &name, server, state, |server, state| {
+        dispatch(req, server, state)
+    })
+}
+
+// Запрос, который dap не смог разобрать (см. custom_request): команда из
+// DapState.custom_commands или ошибка. Тело успешного ответа пустое — в dap ResponseBody
+// нет варианта для произвольного JSON. Сюда же попадают стандартные запросы с битыми
+// аргументами: раньше они роняли весь цикл, теперь клиент получает ошибку с тем,
+// что не так в аргументах (parse_error — разбор от dap).
+pub(crate) fn handle_custom(
+    frame: &str,
+    parse_error: &str,
+    server: &mut DapServer,
+    state: &mut DapState,
+) -> DynResult<()> {
+    let Some(req) = CustomRequest::parse(frame) else {
+        dap_log_at(
+            server,
+            state.log_level,
+            LogLevel::Warn,
+            format!("Ignoring an unparsable message: {frame}"),
+        );
+        return Ok(());
+    };
+    dap_debug(
+        server,
+        state.log_level,
+        format!("Custom request '{}' (seq {})", req.command, req.seq),
+    );
+    run_guarded(req.seq, &req.command, server, state, |server, state| {
+        dispatch_custom(&req, parse_error, server, state)
+    })
+}
+
+fn dispatch_custom(
+    req: &CustomRequest,
+    parse_error: &str,
+    server: &mut DapServer,
+    state: &mut DapState,
+) -> HandlerResult {
+    state.apply_entry_continue();
+
+    if let Err(message) = check_phase(&req.command, state) {
+        dap_log_at(server, state.log_level, LogLevel::Warn, &message);
+        return Err(HandlerError::InvalidArgument(message));
+    }
+
+    if let Some(handler) = state.custom_commands.get(req.command.as_str()).copied() {
+        handler(req.arguments.as_ref(), server, state)?;
+        return respond_custom(req, server, state);
+    }
+    if SPEC_COMMANDS.contains(&req.command.as_str()) {
+        return Err(HandlerError::InvalidArgument(
+            "Invalid arguments for '{command}': {error}".to_string(),
+        )
+        .with_variables([
+            ("command", req.command.clone()),
+            ("error", parse_error.to_string()),
+        ]));
+    }
+    dap_log_at(
+        server,
+        state.log_level,
+        LogLevel::Warn,
+        format!("Unsupported command: {}", req.command),
+    );
+    Err(
+        HandlerError::Unsupported("Unsupported command: '{command}'".to_string())
+            .with_variables([("command", req.command.clone())]),
+    )
+}
+
+// Команды адаптера сверх спецификации; клиент шлёт их как обычные запросы
+pub(crate) fn custom_commands() -> BTreeMap<&'static str, CustomHandler> {
+    [
+        ("rast/reloadSymbols", reload_symbols as CustomHandler),
+        ("rast/panic", report_panic),
+    ]
+    .into_iter()
+    .collect()
+}
+
+// rast/reloadSymbols: перечитать символы программы (например, после пересборки) —
+// функциональные брейки, которые теперь нашлись, становятся verified
+fn reload_symbols(
+    _arguments: Option<&Value>,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    if st.phase != SessionPhase::Running {
+        return Err(HandlerError::InvalidArgument(
+            "No symbols to reload: launch or attach first".to_string(),
+        ));
+    }
+    let module = program_module(st.launch_config.as_ref());
+    for bp in st.load_symbols(DapState::demo_symbols(&module.name)) {
+        st.events.push(
+            server,
+            Event::Breakpoint(BreakpointEventBody {
+                reason: BreakpointEventReason::Changed,
+                breakpoint: function_breakpoint(st, &bp),
+            }),
+        )?;
+    }
+    Ok(())
+}
+
+// rast/panic: бэкенд сообщает о панике в debuggee — { message, causes: [..], unhandled }.
+// unhandled — паника вышла из потока, её никто не поймает. Остановимся или нет,
+// решает stop_on_panic
+fn report_panic(
+    arguments: Option<&Value>,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    if st.phase != SessionPhase::Running {
+        return Err(HandlerError::InvalidArgument(
+            "No debuggee to panic: launch or attach first".to_string(),
+        ));
+    }
+    let message = arguments
+        .and_then(|a| a.get("message"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| {
+            HandlerError::InvalidArgument("rast/panic needs a \"message\"".to_string())
+        })?;
+    let causes = arguments
+        .and_then(|a| a.get("causes"))
+        .and_then(Value::as_array)
+        .map(|causes| causes.iter().filter_map(Value::as_str).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let unhandled = arguments
+        .and_then(|a| a.get("unhandled"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let messages = std::iter::once(message).chain(causes).collect::<Vec<_>>();
+    stop_on_panic(server, st, &messages, unhandled)
+}
+
+// Общий путь любого запроса, стандартного и custom: ответ ровно один, события после
+// него, метрики. Паника в обработчике не должна ронять всю сессию: клиент получает
+// ошибку на запрос, цикл продолжает работать. DapState после паники может быть
+// недообновлён — это осознанный компромисс, потерять сессию хуже.
+fn run_guarded(
+    seq: i64,
+    name: &str,
+    server: &mut DapServer,
     state: &mut DapState,
+    run: impl FnOnce(&mut DapServer, &mut DapState) -> HandlerResult,
+) -> DynResult<()> {
+    // Дамп состояния на каждый запрос — только на trace, по умолчанию его нет
+    if state.log_level <= LogLevel::Trace {
+        dap_log(server, "--- New DAP Request Received ---");
+        dap_log(server, format!("DAP STATE: {state:?}"));
+        dap_log(server, "----------------------------------");
+    }
+
+    // Замер только при включённых метриках или трассировке (для итогов на disconnect) —
+    // иначе никакой лишней работы
+    let started = (state.metrics.enabled || state.tracing).then(Instant::now);
+    state.answered = false;
+    let result = match catch_unwind(AssertUnwindSafe(|| run(server, state))) {
+        Ok(Ok(())) => Ok(()),
+        // Обработчик вернул ошибку — отвечаем за него; Transport уходит наверх
+        Ok(Err(e)) => respond_error(seq, name, e, server, state),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            trace_log(format!("panic in '{name}' handler: {message}"));
+            respond_error(
+                seq,
+                name,
+                HandlerError::BackendError(format!("Internal error in '{name}': {message}")),
+                server,
+                state,
+            )
+            .and(Err(
+                format!("Handler for '{name}' panicked: {message}").into()
+            ))
+        }
+    };
+    if result.is_ok() && !state.answered {
+        dap_log_at(
+            server,
+            state.log_level,
+            LogLevel::Warn,
+            format!("Request seq {seq} finished without a response"),
+        );
+    }
+    // События, накопленные обработчиком, — строго после его ответа
+    let drained = state.events.drain(server);
+    if let Some(started) = started {
+        state.metrics.record(name, started.elapsed());
+    }
+    result.and(drained)
+}
+
+// Все ответы — только через эти хелперы: они отмечают, что запрос закрыт,
+// и ловят нарушение "ровно один ответ на запрос" (см. проверку в run_guarded).
+fn respond_success(
+    req: Request,
+    body: ResponseBody,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    mark_answered(req.seq, &command_name(&req.command), server, st);
+    server.respond(req.success(body))?;
+    Ok(())
+}
+
+// У ответа без body dap не пишет `command` — его подставляем сами
+fn respond_custom(req: &CustomRequest, server: &mut DapServer, st: &mut DapState) -> HandlerResult {
+    mark_answered(req.seq, &req.command, server, st);
+    st.response_patches
+        .set(req.seq, "command", Value::String(req.command.clone()));
+    server.respond(Response {
+        request_seq: req.seq,
+        success: true,
+        ..Default::default()
+    })?;
+    Ok(())
+}
+
+fn respond_error(
+    seq: i64,
+    name: &str,
+    err: HandlerError,
+    server: &mut DapServer,
+    st: &mut DapState,
 ) -> DynResult<()> {
-    dap_log(server, "--- New DAP Request Received ---");
-    dap_log(server, format!("DAP STATE: {state:?}"));
-    dap_log(server, "----------------------------------");
+    mark_answered(seq, name, server, st);
+    server.respond(error_response(err, seq, name, st)?)?;
+    Ok(())
+}
+
+// dap кладёт Message в `error` рядом с body, а клиент ищет его в body.error, и
+// `command` у ответа без body не пишет — оба поля через ResponsePatches
+fn error_response(err: HandlerError, seq: i64, name: &str, st: &DapState) -> DynResult<Response> {
+    let mut response = err.into_response(seq)?;
+    st.response_patches
+        .set(seq, "command", Value::String(name.to_string()));
+    if let Some(error) = response.error.take() {
+        st.response_patches
+            .set(seq, "body", json!({ "error": error }));
+    }
+    Ok(response)
+}
+
+fn mark_answered(seq: i64, name: &str, server: &mut DapServer, st: &mut DapState) {
+    if st.answered {
+        dap_log_at(
+            server,
+            st.log_level,
+            LogLevel::Warn,
+            format!("Second response to '{name}' (seq {seq})"),
+        );
+    }
+    st.answered = true;
+    dap_log_at(
+        server,
+        st.log_level,
+        LogLevel::Trace,
+        format!("Responding to '{name}' (seq {seq})"),
+    );
+}
+
+fn dispatch(req: Request, server: &mut DapServer, state: &mut DapState) -> HandlerResult {
+    state.apply_entry_continue();
+
+    if let Err(message) = check_phase(&command_name(&req.command), state) {
+        dap_log_at(server, state.log_level, LogLevel::Warn, &message);
+        return Err(HandlerError::InvalidArgument(message));
+    }
+
     match &req.command {
-        Command::Initialize(args) => handle_initialize(req.clone(), args, server),
+        Command::Initialize(args) => handle_initialize(req.clone(), args, server, state),
         Command::Launch(args) => handle_launch(req.clone(), args, server, state),
-        Command::Restart(args) => handle_restart(req.clone(), args, server),
-        Command::Attach(args) => handle_attach(req.clone(), args, server),
-        Command::ConfigurationDone => handle_configuration_done(req.clone(), server),
+        Command::Restart(args) => handle_restart(req.clone(), args, server, state),
+        Command::Attach(args) => handle_attach(req.clone(), args, server, state),
+        Command::ConfigurationDone => handle_configuration_done(req.clone(), server, state),
         Command::SetBreakpoints(args) => handle_set_breakpoints(req.clone(), args, server, state),
+        Command::BreakpointLocations(args) => {
+            handle_breakpoint_locations(req.clone(), args, server, state)
+        }
+        Command::SetFunctionBreakpoints(args) => {
+            handle_set_function_breakpoints(req.clone(), args, server, state)
+        }
+        Command::DataBreakpointInfo(args) => {
+            handle_data_breakpoint_info(req.clone(), args, server, state)
+        }
+        Command::SetDataBreakpoints(args) => {
+            handle_set_data_breakpoints(req.clone(), args, server, state)
+        }
         Command::SetExceptionBreakpoints(args) => {
-            handle_set_exception_breakpoints(req.clone(), args, server)
+            handle_set_exception_breakpoints(req.clone(), args, server, state)
         }
+        Command::ExceptionInfo(args) => handle_exception_info(req.clone(), args, server, state),
+        Command::Source(args) => handle_source(req.clone(), args, server, state),
         Command::Threads => handle_threads(req.clone(), server, state),
         Command::Pause(args) => handle_pause(req.clone(), args, server, state),
         Command::Continue(args) => handle_continue(req.clone(), args, server, state),
+        Command::Next(args) => handle_step(
+            req.clone(),
+            ResponseBody::Next,
+            args.thread_id,
+            args.single_thread.unwrap_or(false),
+            args.granularity.as_ref(),
+            server,
+            state,
+        ),
+        Command::StepIn(args) => handle_step(
+            req.clone(),
+            ResponseBody::StepIn,
+            args.thread_id,
+            args.single_thread.unwrap_or(false),
+            args.granularity.as_ref(),
+            server,
+            state,
+        ),
+        Command::StepOut(args) => handle_step(
+            req.clone(),
+            ResponseBody::StepOut,
+            args.thread_id,
+            args.single_thread.unwrap_or(false),
+            args.granularity.as_ref(),
+            server,
+            state,
+        ),
         Command::StackTrace(args) => handle_stack_trace(req.clone(), args, server, state),
         Command::Scopes(args) => handle_scopes(req.clone(), args, server, state),
         Command::Variables(args) => handle_variables(req.clone(), args, server, state),
-        Command::Disconnect(args) => handle_disconnect(req.clone(), args, server),
-        _ => handle_unsupported(req, server),
+        Command::SetVariable(args) => handle_set_variable(req.clone(), args, server, state),
+        Command::Modules(args) => handle_modules(req.clone(), args, server, state),
+        Command::Completions(args) => handle_completions(req.clone(), args, server, state),
+        Command::Evaluate(args) => handle_evaluate(req.clone(), args, server, state),
+        Command::Disconnect(args) => handle_disconnect(req.clone(), args, server, state),
+        _ => handle_unsupported(req, server, state),
+    }
+}
+
+// Запрос не по порядку — баг клиента, отвечаем ошибкой, а не молча выполняем.
+// Повторный initialize отдельно ловит handle_initialize.
+// По имени, а не по Command: custom-запросы проходят ту же проверку
+fn check_phase(name: &str, st: &DapState) -> Result<(), String> {
+    match (name, st.phase) {
+        ("initialize" | "disconnect", _) => Ok(()),
+        (_, SessionPhase::Uninitialized) => Err(format!("'{name}' received before initialize")),
+        // По configured, а не по фазе: после launch фаза уже Running
+        ("configurationDone", _) if st.configured => {
+            Err("configurationDone received twice".to_string())
+        }
+        ("launch" | "attach", SessionPhase::Running) => Err(format!(
+            "'{name}' received but the debuggee is already running"
+        )),
+        // Перезапускать нечего: без launch/attach нет ни конфига, ни debuggee
+        ("restart", phase) if phase != SessionPhase::Running => {
+            Err("'restart' received before launch or attach".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+// threadId не из диапазона потоков — не поток вовсе (скорее перепутанный id фрейма
+// или брейка); регистрировать такой через ensure_thread нельзя
+fn check_thread_id(thread_id: i64) -> HandlerResult {
+    if is_thread_id(thread_id) {
+        return Ok(());
     }
+    Err(HandlerError::NotFound(format!(
+        "Unknown threadId {thread_id}"
+    )))
 }
 
 // --------------------
 // HANDLERS
 // --------------------
+// arguments текущего запроса до разбора dap — для полей, которых в dap нет
+fn raw_arguments(st: &DapState) -> Option<Value> {
+    let mut frame: Value = serde_json::from_str(st.request_frame.as_deref()?).ok()?;
+    Some(frame.get_mut("arguments")?.take())
+}
+
 fn handle_initialize(
     req: Request,
     args: &InitializeArguments,
-    server: &mut Server<Stdin, Stdout>,
-) -> DynResult<()> {
-    dap_log(server, format!("Initialize: {args:?}"));
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Initialize: {args:?}"));
+
+    // Второй initialize в той же сессии — ошибка клиента; повторный Initialized сбил бы его с толку
+    if st.phase != SessionPhase::Uninitialized {
+        dap_log_at(
+            server,
+            st.log_level,
+            LogLevel::Warn,
+            "Duplicate initialize request rejected",
+        );
+        return Err(HandlerError::InvalidArgument(
+            "Debug adapter is already initialized".to_string(),
+        ));
+    }
+    st.phase = SessionPhase::Initialized;
+    st.client = Some(args.clone());
+    st.path_format = args.path_format.clone().unwrap_or(PathFormat::Path);
+    st.locale = args.locale.clone();
+    // supportsANSIStyling в InitializeArguments из dap 0.4.1 нет — поле теряется при разборе,
+    // так что пока считаем, что клиент ANSI не поддерживает, и вырезаем коды.
+    st.ansi_styling = false;
+    st.client_telemetry = raw_arguments(st)
+        .and_then(|a| a.get("supportsTelemetry").and_then(Value::as_bool))
+        .unwrap_or(false);
 
     // Минимальные capabilities чтобы VS Code начал слать стандартные запросы.
-    // Если у тебя в crate `dap` другие поля/имена — замени по аналогии.
-    let caps = Capabilities {
-        supports_configuration_done_request: Some(true),
-        supports_set_variable: Some(false),
-        supports_step_back: Some(false),
-        supports_restart_frame: Some(false),
-        supports_goto_targets_request: Some(false),
-        supports_conditional_breakpoints: Some(false),
-        supports_hit_conditional_breakpoints: Some(false),
-        supports_terminate_request: Some(false),
-        supports_evaluate_for_hovers: Some(false),
-        ..Default::default()
-    };
+    let caps = build_capabilities(st);
+    st.response_patches
+        .set(req.seq, "body", capabilities_body(&caps));
+
+    respond_success(req, ResponseBody::Initialize(caps), server, st)?;
+    st.events.push(server, Event::Initialized)?;
+
+    // В capabilities поля для id адаптера нет — сообщаем его telemetry-событием
+    send_telemetry(
+        server,
+        st,
+        "initialize",
+        json!({
+            "adapterId": st.adapter_id,
+            "clientAdapterId": args.adapter_id,
+            "version": env!("CARGO_PKG_VERSION"),
+        }),
+    )?;
+    Ok(())
+}
+
+fn handle_configuration_done(
+    req: Request,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, "ConfigurationDone");
+    // launch мог уже прийти — тогда остаёмся в Running
+    if st.phase == SessionPhase::Initialized {
+        st.phase = SessionPhase::Configured;
+    }
+    st.configured = true;
+    respond_success(req, ResponseBody::ConfigurationDone, server, st)?;
+    for event in std::mem::take(&mut st.deferred_run_events) {
+        st.events.push(server, event)?;
+    }
+    stop_on_entry(server, st)
+}
+
+// Stopped/Continued до configurationDone клиент применил бы к ещё не настроенной сессии
+// (брейки не расставлены, UI не готов) — придерживаем их до ответа на configurationDone
+fn send_run_event(server: &mut DapServer, st: &mut DapState, event: Event) -> DynResult<()> {
+    if !st.configured {
+        st.deferred_run_events.push(event);
+        return Ok(());
+    }
+    st.events.push(server, event)
+}
 
-    server.respond(req.success(ResponseBody::Initialize(caps)))?;
-    server.send_event(Event::Initialized)?;
+// Все Stopped собираются здесь. preserveFocusHint: шаг с preserveFocusOnStep не уводит
+// фокус редактора, остальные остановки (брейк, entry, pause, паника) — показывают место
+fn stopped_event(
+    st: &DapState,
+    reason: StoppedEventReason,
+    description: &str,
+    thread_id: i64,
+    all_threads_stopped: bool,
+    text: Option<String>,
+) -> Event {
+    let preserve_focus = matches!(reason, StoppedEventReason::Step) && st.preserve_focus_on_step();
+    Event::Stopped(dap::events::StoppedEventBody {
+        reason,
+        description: Some(description.to_string()),
+        thread_id: Some(thread_id),
+        preserve_focus_hint: Some(preserve_focus),
+        text,
+        all_threads_stopped: Some(all_threads_stopped),
+        hit_breakpoint_ids: (!st.stopped_breakpoint_ids.is_empty())
+            .then(|| st.stopped_breakpoint_ids.clone()),
+    })
+}
+
+// Entry-остановка — только когда есть и launch, и configurationDone (в любом порядке),
+// иначе клиент ещё не расставил брейки и не готов к Stopped.
+fn stop_on_entry(server: &mut DapServer, st: &mut DapState) -> HandlerResult {
+    if !st.entry_stop_pending || !st.configured || st.phase != SessionPhase::Running {
+        return Ok(());
+    }
+    st.entry_stop_pending = false;
+    st.begin_stop();
+    report_first_stop(server, st)?;
+    st.ensure_thread(st.main_thread_id);
+    st.set_all_running(false);
+    st.events.push(
+        server,
+        stopped_event(
+            st,
+            StoppedEventReason::Entry,
+            "Stopped on entry",
+            st.main_thread_id,
+            true,
+            None,
+        ),
+    )?;
+
+    // Главный цикл блокируется на чтении stdin, поэтому таймер пишет Continued сам —
+    // через тот же mutex Server, что и все остальные сообщения. Состояние потоков
+    // догоняет apply_entry_continue на следующем запросе.
+    if let Some(delay) = st.entry_continue_after {
+        // Сначала отдать Stopped — Continued не должен его обогнать
+        st.events.drain(server)?;
+        st.entry_continue_at = Some(Instant::now() + delay);
+        let output = server.output.clone();
+        let thread_id = st.main_thread_id;
+        let epoch = Arc::clone(&st.entry_timer_epoch);
+        let started_in = epoch.load(Ordering::SeqCst);
+        std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            // Поколение сверяем под mutex'ом: если Continued и уйдёт, то раньше
+            // любого сообщения сессии, сменившей поколение
+            if let Ok(mut output) = output.lock() {
+                if epoch.load(Ordering::SeqCst) != started_in {
+                    return;
+                }
+                let _ = output.send_event(Event::Continued(dap::events::ContinuedEventBody {
+                    thread_id,
+                    all_threads_continued: Some(true),
+                }));
+            }
+        });
+    }
+    Ok(())
+}
+
+// Паника в debuggee: сюда её отдаёт бэкенд, messages — паника и её причины.
+// На непойманной останавливаемся всегда — после неё потока уже не будет. На остальных —
+// только если включён фильтр panic и выполнилось его condition; ошибка в условии —
+// останавливаемся, как и у брейков
+fn stop_on_panic(
+    server: &mut DapServer,
+    st: &mut DapState,
+    messages: &[&str],
+    unhandled: bool,
+) -> HandlerResult {
+    let filter = st.exception_filters.iter().find(|(f, _)| f == PANIC_FILTER);
+    if !unhandled {
+        let Some((_, condition)) = filter else {
+            return Ok(());
+        };
+        if let Some(condition) = condition {
+            let holds = st
+                .evaluator
+                .eval(condition, None, st)
+                .map(|v| is_truthy(&v))
+                .unwrap_or(true);
+            if !holds {
+                return Ok(());
+            }
+        }
+    }
+    st.raise_panic(messages);
+    report_first_stop(server, st)?;
+    st.ensure_thread(st.main_thread_id);
+    st.set_all_running(false);
+    let event = stopped_event(
+        st,
+        StoppedEventReason::Exception,
+        "Paused on panic",
+        st.main_thread_id,
+        true,
+        messages.first().map(|m| m.to_string()),
+    );
+    send_run_event(server, st, event)?;
     Ok(())
 }
 
-fn handle_configuration_done(req: Request, server: &mut Server<Stdin, Stdout>) -> DynResult<()> {
-    dap_log(server, "ConfigurationDone");
-    server.respond(req.success(ResponseBody::ConfigurationDone))?;
+// Вся цепочка причин — в details.innerException, клиент показывает её под описанием
+fn handle_exception_info(
+    req: Request,
+    args: &ExceptionInfoArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("ExceptionInfo: {args:?}"));
+
+    let Some(exception) = &st.last_exception else {
+        return Err(HandlerError::NotFound(format!(
+            "No exception on thread {}",
+            args.thread_id
+        )));
+    };
+    // С включённым фильтром panic остановились бы в любом случае, без него — только на непойманной
+    let break_mode = if st.exception_filters.iter().any(|(f, _)| f == PANIC_FILTER) {
+        ExceptionBreakMode::Always
+    } else {
+        ExceptionBreakMode::Unhandled
+    };
+    let body = ExceptionInfoResponse {
+        exception_id: exception.type_name.clone(),
+        description: Some(exception.message.clone()),
+        break_mode,
+        details: Some(exception.to_details()),
+    };
+
+    respond_success(req, ResponseBody::ExceptionInfo(body), server, st)?;
     Ok(())
 }
 
 fn handle_launch(
     req: Request,
     args: &LaunchRequestArguments,
-    server: &mut Server<Stdin, Stdout>,
-    _st: &mut DapState,
-) -> DynResult<()> {
-    dap_log(server, format!("Launch: {args:?}"));
-    let port = extract_port_from_args(args);
-    dap_log(server, format!("Running on port: {port:?}"));
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Launch: {args:?}"));
+    let config = LaunchConfig::parse(args.additional_data.as_ref())?;
+    // Без program запускать нечего: успешный ответ оставил бы клиента с мёртвой сессией
+    if config
+        .program
+        .as_deref()
+        .is_none_or(|p| p.trim().is_empty())
+    {
+        let err = HandlerError::InvalidArgument(
+            "No program to debug: set \"{field}\" in the launch configuration \
+             to the path of the executable"
+                .to_string(),
+        )
+        .with_variables([("field", "program".to_string())]);
+        dap_log_at(server, st.log_level, LogLevel::Error, err.to_string());
+        return Err(err);
+    }
+    let port = config.port();
+    dap_debug(server, st.log_level, format!("Running on port: {port:?}"));
 
-    server.respond(req.success(ResponseBody::Launch))?;
-    Ok(())
+    // Ошибку в envFile лучше показать сразу на launch, чем потом в запущенной программе
+    let env = config.resolve_env()?;
+    dap_debug(
+        server,
+        st.log_level,
+        format!("Launch environment: {} variables", env.len()),
+    );
+
+    st.set_main_thread_name(config.thread_name());
+    st.set_path_mappings(config.path_mappings());
+    st.entry_stop_pending = config.stop_on_entry;
+    st.entry_continue_after = config.entry_continue_after();
+    send_telemetry(
+        server,
+        st,
+        "launch",
+        json!({
+            "hasProgram": config.program.is_some(),
+            "args": config.args.len(),
+            "stopOnEntry": config.stop_on_entry,
+            "pathMappings": st.path_mappings.len(),
+            "env": config.env.len(),
+        }),
+    )?;
+    // Запомнить конфиг, чтобы restart мог перезапустить с ним же
+    st.launch_config = Some(config);
+    st.phase = SessionPhase::Running;
+    st.launched_at = Some(Instant::now());
+
+    respond_success(req, ResponseBody::Launch, server, st)?;
+    let module = program_module(st.launch_config.as_ref());
+    let symbols = DapState::demo_symbols(&module.name);
+    load_module(server, st, module)?;
+
+    // Символы появились — функциональные брейки, поставленные до launch, теперь разрешаются
+    for bp in st.load_symbols(symbols) {
+        st.events.push(
+            server,
+            Event::Breakpoint(BreakpointEventBody {
+                reason: BreakpointEventReason::Changed,
+                breakpoint: function_breakpoint(st, &bp),
+            }),
+        )?;
+    }
+    stop_on_entry(server, st)
 }
 
 fn handle_restart(
     req: Request,
     args: &RestartArguments,
-    server: &mut Server<Stdin, Stdout>,
-) -> DynResult<()> {
-    dap_log(server, format!("Restart: {args:?}"));
-    server.respond(req.success(ResponseBody::Restart))?;
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Restart: {args:?}"));
+
+    // Клиент может прислать обновлённый launch-конфиг; битый — отказ, старый остаётся
+    let new_config = args
+        .arguments
+        .as_ref()
+        .map(|new_args| LaunchConfig::parse(new_args.additional_data.as_ref()))
+        .transpose()?;
+    restart_session(req, new_config, server, st)
+}
+
+fn restart_session(
+    req: Request,
+    new_config: Option<LaunchConfig>,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    let previous_module = program_module(st.launch_config.as_ref());
+    if new_config.is_some() {
+        st.launch_config = new_config;
+    }
+
+    if st.attached {
+        return reattach(req, server, st);
+    }
+
+    for event in thread_events(st, ThreadEventReason::Exited) {
+        st.events.push(server, event)?;
+    }
+
+    if let Some(config) = &st.launch_config {
+        let name = config.thread_name();
+        let mappings = config.path_mappings();
+        st.entry_stop_pending = config.stop_on_entry;
+        st.entry_continue_after = config.entry_continue_after();
+        st.set_path_mappings(mappings);
+        st.set_main_thread_name(name);
+    }
+
+    // Брейкпоинты остаются в DapState — после reset они снова действуют
+    st.reset_session();
+    st.phase = SessionPhase::Running;
+    st.launched_at = Some(Instant::now());
+
+    // ВАЖНО: на Restart РОВНО ОДИН ответ
+    respond_success(req, ResponseBody::Restart, server, st)?;
+
+    for event in thread_events(st, ThreadEventReason::Started) {
+        st.events.push(server, event)?;
+    }
+    replay_console(server, st)?;
+    reload_program_module(server, st, previous_module)?;
+    stop_on_entry(server, st)
+}
+
+// retainConsoleOnRestart: то, что было в консоли до restart, плюс разделитель — дальше
+// вывод нового запуска. Разделитель тоже в истории, чтобы следующий restart показал границы
+fn replay_console(server: &mut DapServer, st: &mut DapState) -> DynResult<()> {
+    if !st.retain_console() {
+        return Ok(());
+    }
+    for line in &st.console_history {
+        console_output(server, st, line)?;
+    }
+    console_output(server, st, RESTART_SEPARATOR)?;
+    st.remember_output(RESTART_SEPARATOR);
+    Ok(())
+}
+
+// По событию на каждый поток; у однопоточной цели (singleThreaded) поток один и
+// заранее известен, его started/exited — лишний трафик
+fn thread_events(st: &DapState, reason: ThreadEventReason) -> Vec<Event> {
+    if st.single_thread_mode() {
+        return Vec::new();
+    }
+    st.threads
+        .keys()
+        .map(|thread_id| {
+            Event::Thread(ThreadEventBody {
+                reason: reason.clone(),
+                thread_id: *thread_id,
+            })
+        })
+        .collect()
+}
+
+// Restart attach-сессии: процесс мы не запускали и перезапустить не можем — только
+// переподключаемся к нему же. Потоки и модули живут дальше, exited/started не шлём
+fn reattach(req: Request, server: &mut DapServer, st: &mut DapState) -> HandlerResult {
+    dap_debug(server, st.log_level, "Re-attaching to the running debuggee");
+    if let Some(config) = &st.launch_config {
+        let name = config.thread_name();
+        let mappings = config.path_mappings();
+        st.set_path_mappings(mappings);
+        st.set_main_thread_name(name);
+    }
+    st.forget_stop();
+    st.cancel_entry_continue();
+    st.phase = SessionPhase::Running;
+    respond_success(req, ResponseBody::Restart, server, st)?;
     Ok(())
 }
 
 fn handle_attach(
     req: Request,
     args: &AttachRequestArguments,
-    server: &mut Server<Stdin, Stdout>,
-) -> DynResult<()> {
-    dap_log(server, format!("Attach: {args:?}"));
-    server.respond(req.success(ResponseBody::Attach))?;
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Attach: {args:?}"));
+    let config = LaunchConfig::parse(args.additional_data.as_ref())?;
+    st.set_main_thread_name(config.thread_name());
+    st.set_path_mappings(config.path_mappings());
+    // Для restart: переподключиться с тем же конфигом
+    st.launch_config = Some(config);
+    st.attached = true;
+    st.phase = SessionPhase::Running;
+    respond_success(req, ResponseBody::Attach, server, st)?;
     Ok(())
 }
 
 fn handle_set_breakpoints(
     req: Request,
     args: &SetBreakpointsArguments,
-    server: &mut Server<Stdin, Stdout>,
+    server: &mut DapServer,
     st: &mut DapState,
-) -> DynResult<()> {
-    dap_log(server, format!("SetBreakpoints: {args:?}"));
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("SetBreakpoints: {args:?}"));
 
-    // Запомнить source чтобы потом отдать stackTrace с тем же source/path
-    st.current_source = Some(args.source.clone());
-
-    // Сохранить линии брейков по path
-    if let Some(path) = args.source.path.clone() {
-        let mut lines = Vec::new();
-        if let Some(source_breakpoints) = &args.breakpoints {
-            for bp in source_breakpoints {
-                lines.push(bp.line);
-            }
+    let requested = args.breakpoints.clone().unwrap_or_default();
+    // Строки с 1: нулевая/отрицательная — баг клиента, а не брейк, который можно не подтвердить
+    if let Some(bp) = requested.iter().find(|bp| bp.line < 1) {
+        let path = args
+            .source
+            .path
+            .clone()
+            .or_else(|| args.source.name.clone())
+            .unwrap_or_default();
+        return Err(HandlerError::InvalidArgument(
+            "Invalid breakpoint line {line} in '{path}'".to_string(),
+        )
+        .with_variables([("line", bp.line.to_string()), ("path", path)]));
+    }
+
+    // Пустой (или отсутствующий) список — снять все брейки файла. current_source не трогаем:
+    // файл без брейков не должен становиться местом следующей остановки
+    if requested.is_empty() {
+        let remote = st.to_remote(args.source.clone());
+        let source = st.to_client(remote.clone());
+        let removed = SourceKey::of(&remote)
+            .map(|key| st.clear_breakpoints(&key))
+            .unwrap_or_default();
+        dap_debug(
+            server,
+            st.log_level,
+            format!(
+                "Cleared {} breakpoint(s) in {:?}",
+                removed.len(),
+                source.path
+            ),
+        );
+        for bp in removed {
+            st.events.push(
+                server,
+                Event::Breakpoint(BreakpointEventBody {
+                    reason: BreakpointEventReason::Removed,
+                    breakpoint: Breakpoint {
+                        id: Some(bp.id),
+                        verified: false,
+                        source: Some(source.clone()),
+                        line: Some(bp.line),
+                        column: bp.column,
+                        ..Default::default()
+                    },
+                }),
+            )?;
         }
-        st.breakpoints_by_path.insert(path, lines);
+        respond_success(
+            req,
+            ResponseBody::SetBreakpoints(SetBreakpointsResponse {
+                breakpoints: Vec::new(),
+            }),
+            server,
+            st,
+        )?;
+        return Ok(());
     }
 
+    // Запомнить source чтобы потом отдать stackTrace с тем же source/path
+    let remote = st.to_remote(args.source.clone());
+    st.remember_source(&remote);
+    st.current_source = Some(remote.clone());
+    let source = st.to_client(remote.clone());
+
+    let line_count = remote
+        .path
+        .clone()
+        .and_then(|path| st.source_text(&path).map(SourceText::line_count));
+
+    // Сохранить брейки по path или sourceReference; id стабильны, пока брейк живёт
+    let stored = match SourceKey::of(&remote) {
+        Some(key) => st.replace_breakpoints(&key, &requested),
+        None => requested
+            .iter()
+            .map(|bp| StoredBreakpoint::from_source(st.alloc_breakpoint_id(), bp))
+            .collect(),
+    };
+
+    // stored идёт в порядке запроса: i-й брейк ответа — про i-й запрошенный
     let mut breakpoints = Vec::new();
-    if let Some(source_breakpoints) = &args.breakpoints {
-        for (i, src_bp) in source_breakpoints.iter().enumerate() {
-            breakpoints.push(Breakpoint {
-                id: Some(i as i64 + 1),
-                verified: true,
-                message: None,
-                source: Some(args.source.clone()),
-                line: Some(src_bp.line),
-                column: src_bp.column,
-                end_line: None,
-                end_column: None,
-                instruction_reference: None,
-                offset: None,
-            });
+    for bp in stored {
+        // Файл не прочитать — проверять не по чему, верим клиенту
+        let past_end = line_count.filter(|&count| bp.line > count as i64);
+        breakpoints.push(Breakpoint {
+            id: Some(bp.id),
+            verified: past_end.is_none(),
+            message: past_end.map(|count| {
+                format!(
+                    "Line {} is past the end of the file ({count} lines)",
+                    bp.line
+                )
+            }),
+            source: Some(source.clone()),
+            line: Some(bp.line),
+            column: bp.column,
+            end_line: None,
+            end_column: None,
+            instruction_reference: None,
+            offset: None,
+        });
 
-            dap_log(server, format!("Set breakpoint at line {}", src_bp.line));
-        }
+        dap_debug(
+            server,
+            st.log_level,
+            format!("Set breakpoint at line {}", bp.line),
+        );
     }
 
     // ВАЖНО: на SetBreakpoints должен быть РОВНО ОДИН ответ SetBreakpointsResponse
-    server.respond(
-        req.success(ResponseBody::SetBreakpoints(SetBreakpointsResponse {
-            breakpoints,
-        })),
+    respond_success(
+        req,
+        ResponseBody::SetBreakpoints(SetBreakpointsResponse { breakpoints }),
+        server,
+        st,
     )?;
     Ok(())
 }
 
-fn handle_set_exception_breakpoints(
+// Все места в диапазоне строк, куда можно поставить брейк; в строке их может быть несколько
+// (цепочка вызовов) — так клиент показывает inline-брейки. Файл не прочитать — пустой список.
+fn handle_breakpoint_locations(
     req: Request,
-    args: &SetExceptionBreakpointsArguments,
-    server: &mut Server<Stdin, Stdout>,
-) -> DynResult<()> {
-    dap_log(server, format!("SetExceptionBreakpoints: {args:?}"));
+    args: &BreakpointLocationsArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(
+        server,
+        st.log_level,
+        format!("BreakpointLocations: {args:?}"),
+    );
+
+    let base = st
+        .client
+        .as_ref()
+        .and_then(|c| c.columns_start_at1)
+        .unwrap_or(true) as i64;
+    let end_line = args.end_line.unwrap_or(args.line).max(args.line);
+    let path = st.to_remote(args.source.clone()).path;
+
+    let mut breakpoints = Vec::new();
+    if let Some(text) = path.and_then(|path| st.source_text(&path)) {
+        for line in args.line.max(1)..=end_line {
+            let Some(content) = text.line(line as usize) else {
+                break;
+            };
+            for column in breakpoint_columns(content) {
+                let column = column + base;
+                // column/endColumn ограничивают только крайние строки диапазона
+                if line == args.line && args.column.is_some_and(|start| column < start) {
+                    continue;
+                }
+                if line == end_line && args.end_column.is_some_and(|end| column > end) {
+                    continue;
+                }
+                breakpoints.push(BreakpointLocation {
+                    line,
+                    column: Some(column),
+                    end_line: None,
+                    end_column: None,
+                });
+            }
+        }
+    }
 
-    server.respond(req.success(ResponseBody::SetExceptionBreakpoints(
-        SetExceptionBreakpointsResponse { breakpoints: None },
-    )))?;
+    respond_success(
+        req,
+        ResponseBody::BreakpointLocations(BreakpointLocationsResponse { breakpoints }),
+        server,
+        st,
+    )?;
     Ok(())
 }
 
-fn handle_threads(
+// Содержимое по path; своих sourceReference адаптер пока не выдаёт
+fn handle_source(
     req: Request,
-    server: &mut Server<Stdin, Stdout>,
+    args: &SourceArguments,
+    server: &mut DapServer,
     st: &mut DapState,
-) -> DynResult<()> {
-    dap_log(server, "Threads request received");
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Source: {args:?}"));
 
-    let threads = vec![Thread {
-        id: st.main_thread_id,
-        name: "Main Thread".to_string(),
-    }];
+    let path = args
+        .source
+        .clone()
+        .and_then(|source| st.to_remote(source).path);
+    let Some(path) = path else {
+        return Err(HandlerError::NotFound(format!(
+            "No content for sourceReference {}",
+            args.source_reference
+        )));
+    };
+    let endings = st.line_endings;
+    let content = st
+        .source_text(&path)
+        .map(|text| text.content(endings))
+        .ok_or_else(|| HandlerError::NotFound(format!("Cannot read source '{path}'")))?;
 
-    server.respond(req.success(ResponseBody::Threads(ThreadsResponse { threads })))?;
+    respond_success(
+        req,
+        ResponseBody::Source(SourceResponse {
+            content,
+            mime_type: None,
+        }),
+        server,
+        st,
+    )?;
     Ok(())
 }
 
-fn handle_pause(
+fn function_breakpoint(st: &DapState, bp: &StoredFunctionBreakpoint) -> Breakpoint {
+    let verified = st.symbols.contains_key(&bp.name);
+    Breakpoint {
+        id: Some(bp.id),
+        verified,
+        message: (!verified).then(|| format!("Function '{}' not found", bp.name)),
+        source: None,
+        line: None,
+        column: None,
+        end_line: None,
+        end_column: None,
+        instruction_reference: None,
+        offset: None,
+    }
+}
+
+fn handle_set_function_breakpoints(
     req: Request,
-    args: &PauseArguments,
-    server: &mut Server<Stdin, Stdout>,
+    args: &SetFunctionBreakpointsArguments,
+    server: &mut DapServer,
     st: &mut DapState,
-) -> DynResult<()> {
-    dap_log(server, format!("Pause: {args:?}"));
-
-    server.respond(req.success(ResponseBody::Pause))?;
+) -> HandlerResult {
+    dap_debug(
+        server,
+        st.log_level,
+        format!("SetFunctionBreakpoints: {args:?}"),
+    );
 
-    // выбрать линию, куда “остановились” (для демо — первый брейкпоинт или 1)
-    st.pick_stop_location();
-
-    // ВАЖНО: после PauseResponse нужно послать Stopped event
-    server.send_event(Event::Stopped(dap::events::StoppedEventBody {
-        reason: StoppedEventReason::Pause,
-        description: Some("Paused".to_string()),
-        thread_id: Some(st.main_thread_id),
-        preserve_focus_hint: Some(false),
-        text: None,
-        all_threads_stopped: Some(true),
-        hit_breakpoint_ids: None,
-    }))?;
+    let stored = st.replace_function_breakpoints(&args.breakpoints);
+    let breakpoints = stored
+        .iter()
+        .map(|bp| function_breakpoint(st, bp))
+        .collect();
 
+    respond_success(
+        req,
+        ResponseBody::SetFunctionBreakpoints(SetFunctionBreakpointsResponse { breakpoints }),
+        server,
+        st,
+    )?;
     Ok(())
 }
 
-fn handle_continue(
+fn handle_data_breakpoint_info(
     req: Request,
-    args: &ContinueArguments,
-    server: &mut Server<Stdin, Stdout>,
+    args: &DataBreakpointInfoArguments,
+    server: &mut DapServer,
     st: &mut DapState,
-) -> DynResult<()> {
-    dap_log(server, format!("Continue: {args:?}"));
-
-    server.respond(req.success(ResponseBody::Continue(ContinueResponse {
-        all_threads_continued: Some(true),
-    })))?;
+) -> HandlerResult {
+    dap_debug(
+        server,
+        st.log_level,
+        format!("DataBreakpointInfo: {args:?}"),
+    );
 
-    server.send_event(Event::Continued(dap::events::ContinuedEventBody {
-        thread_id: st.main_thread_id,
-        all_threads_continued: Some(true),
-    }))?;
+    let data_id = st.data_id_for(&args.name, args.variables_reference, args.frame_id);
+    let body = match data_id {
+        Some(data_id) => DataBreakpointInfoResponse {
+            description: format!("Break when '{}' changes", args.name),
+            access_types: Some(vec![
+                DataBreakpointAccessType::Write,
+                DataBreakpointAccessType::Read,
+                DataBreakpointAccessType::ReadWrite,
+            ]),
+            // canPersist: клиент сохранит брейк между сессиями только для имён, не адресов
+            can_persist: Some(is_persistent_data_id(&data_id)),
+            data_id: Some(data_id),
+        },
+        None => DataBreakpointInfoResponse {
+            data_id: None,
+            description: format!("Cannot watch '{}'", args.name),
+            access_types: None,
+            can_persist: None,
+        },
+    };
 
+    respond_success(req, ResponseBody::DataBreakpointInfo(body), server, st)?;
     Ok(())
 }
 
-fn handle_stack_trace(
+fn handle_set_data_breakpoints(
     req: Request,
-    args: &StackTraceArguments,
-    server: &mut Server<Stdin, Stdout>,
+    args: &SetDataBreakpointsArguments,
+    server: &mut DapServer,
     st: &mut DapState,
-) -> DynResult<()> {
-    dap_log(server, format!("StackTrace: {args:?}"));
-
-    let source = st.current_source.clone().unwrap_or(Source {
-        name: Some("unknown".to_string()),
-        path: None,
-        source_reference: None,
-        presentation_hint: None,
-        origin: None,
-        sources: None,
-        adapter_data: None,
-        checksums: None,
-    });
-
-    let frames = vec![StackFrame {
-        id: 1,
-        name: "main".to_string(),
-        source: Some(source),
-        line: st.stopped_line,
-        column: st.stopped_column,
-        end_line: None,
-        end_column: None,
-        can_restart: None,
-        instruction_pointer_reference: None,
-        module_id: None,
-        presentation_hint: None,
-    }];
+) -> HandlerResult {
+    dap_debug(
+        server,
+        st.log_level,
+        format!("SetDataBreakpoints: {args:?}"),
+    );
 
-    server.respond(req.success(ResponseBody::StackTrace(StackTraceResponse {
-        stack_frames: frames,
-        total_frames: Some(1),
-    })))?;
+    let breakpoints = st
+        .replace_data_breakpoints(&args.breakpoints)
+        .iter()
+        .map(|bp| Breakpoint {
+            id: Some(bp.id),
+            verified: true,
+            message: None,
+            source: None,
+            line: None,
+            column: None,
+            end_line: None,
+            end_column: None,
+            instruction_reference: None,
+            offset: None,
+        })
+        .collect();
 
+    respond_success(
+        req,
+        ResponseBody::SetDataBreakpoints(SetDataBreakpointsResponse { breakpoints }),
+        server,
+        st,
+    )?;
     Ok(())
 }
 
-fn handle_scopes(
+fn handle_set_exception_breakpoints(
     req: Request,
-    args: &ScopesArguments,
-    server: &mut Server<Stdin, Stdout>,
+    args: &SetExceptionBreakpointsArguments,
+    server: &mut DapServer,
     st: &mut DapState,
-) -> DynResult<()> {
-    dap_log(server, format!("Scopes: {args:?}"));
-
-    let scopes = vec![Scope {
-        name: "Locals".to_string(),
-        presentation_hint: None,
-        variables_reference: st.vars_ref,
-        named_variables: None,
-        indexed_variables: None,
-        expensive: false,
-        source: None,
-        line: None,
-        column: None,
-        end_line: None,
-        end_column: None,
-    }];
+) -> HandlerResult {
+    dap_debug(
+        server,
+        st.log_level,
+        format!("SetExceptionBreakpoints: {args:?}"),
+    );
+
+    // Фильтры без условия и filterOptions (с условием) — одним списком filter -> condition
+    st.exception_filters = args.filters.iter().map(|f| (f.clone(), None)).collect();
+    for opt in args.filter_options.iter().flatten() {
+        let condition = opt.condition.clone().filter(|c| !c.trim().is_empty());
+        match st
+            .exception_filters
+            .iter_mut()
+            .find(|(id, _)| *id == opt.filter_id)
+        {
+            Some(entry) => entry.1 = condition,
+            None => st
+                .exception_filters
+                .push((opt.filter_id.clone(), condition)),
+        }
+    }
+
+    let breakpoints = st
+        .exception_filters
+        .iter()
+        .map(|(id, condition)| {
+            let known = id == PANIC_FILTER;
+            Breakpoint {
+                id: None,
+                verified: known,
+                message: match (known, condition) {
+                    (false, _) => Some(format!("Unknown exception filter '{id}'")),
+                    (true, Some(cond)) => Some(format!("Stops only when {cond}")),
+                    (true, None) => None,
+                },
+                source: None,
+                line: None,
+                column: None,
+                end_line: None,
+                end_column: None,
+                instruction_reference: None,
+                offset: None,
+            }
+        })
+        .collect();
 
-    server.respond(req.success(ResponseBody::Scopes(ScopesResponse { scopes })))?;
+    respond_success(
+        req,
+        ResponseBody::SetExceptionBreakpoints(SetExceptionBreakpointsResponse {
+            breakpoints: Some(breakpoints),
+        }),
+        server,
+        st,
+    )?;
     Ok(())
 }
 
-fn handle_variables(
-    req: Request,
-    args: &VariablesArguments,
-    server: &mut Server<Stdin, Stdout>,
-    _st: &mut DapState,
-) -> DynResult<()> {
-    dap_log(server, format!("Variables: {args:?}"));
-
-    let variables = vec![Variable {
-        name: "demo".to_string(),
-        value: "1".to_string(),
-        type_field: Some("i32".to_string()),
-        presentation_hint: None,
-        evaluate_name: Some("demo".to_string()),
-        variables_reference: 0,
-        named_variables: None,
-        indexed_variables: None,
-        memory_reference: None,
-    }];
-
-    server.respond(req.success(ResponseBody::Variables(VariablesResponse { variables })))?;
+fn handle_threads(req: Request, server: &mut DapServer, st: &mut DapState) -> HandlerResult {
+    dap_debug(server, st.log_level, "Threads request received");
+
+    if st.threads_cache_fresh() {
+        dap_debug(server, st.log_level, "Threads: reusing the cached list");
+    }
+    let threads = st.thread_list();
+
+    respond_success(
+        req,
+        ResponseBody::Threads(ThreadsResponse { threads }),
+        server,
+        st,
+    )?;
     Ok(())
 }
 
-fn handle_disconnect(
+fn handle_pause(
     req: Request,
-    args: &DisconnectArguments,
-    server: &mut Server<Stdin, Stdout>,
-) -> DynResult<()> {
-    dap_log(server, format!("Disconnect: {args:?}"));
-    server.respond(req.success(ResponseBody::Disconnect))?;
+    args: &PauseArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Pause: {args:?}"));
+    check_thread_id(args.thread_id)?;
+
+    respond_success(req, ResponseBody::Pause, server, st)?;
+
+    // Поток уже стоит — второй Stopped только собьёт UI, просто подтверждаем pause
+    if !st.is_thread_running(args.thread_id) {
+        dap_debug(
+            server,
+            st.log_level,
+            "Thread already stopped, no Stopped event",
+        );
+        return Ok(());
+    }
+
+    // выбрать линию, куда “остановились” (для демо — первый брейкпоинт или 1)
+    // и сбросить переменные/фреймы прошлой остановки
+    let single_thread = st.single_thread_resume;
+    st.begin_stop();
+    st.single_thread_resume = single_thread;
+    report_first_stop(server, st)?;
+    let logs = st.pick_stop_location();
+    // Logpoint'ы ведут в консоли на свою строку
+    let source = st.current_source.clone().map(|s| st.to_client(s));
+    for (message, line) in logs {
+        st.remember_output(&message);
+        diagnostic_output(server, st, message, source.clone(), line)?;
+    }
+
+    // ВАЖНО: после PauseResponse нужно послать Stopped event.
+    // Один event на запрошенный поток; allThreadsStopped — остальные стоят вместе с ним,
+    // кроме single-thread режима: там они продолжают идти.
+    // Незнакомый поток не подменяем главным, а регистрируем: Stopped уходит в тот
+    // поток, который просил клиент
+    let thread_id = args.thread_id;
+    st.ensure_thread(thread_id);
+    if st.single_thread_resume {
+        st.set_thread_running(thread_id, false);
+    } else {
+        st.set_all_running(false);
+    }
+    let all_threads_stopped = !st.threads.values().any(|t| t.running);
+    let event = stopped_event(
+        st,
+        StoppedEventReason::Pause,
+        "Paused by user",
+        thread_id,
+        all_threads_stopped,
+        None,
+    );
+    send_run_event(server, st, event)?;
+
     Ok(())
 }
 
-fn handle_unsupported(req: Request, server: &mut Server<Stdin, Stdout>) -> DynResult<()> {
-    dap_log(server, format!("Unsupported command: {:?}", req.command));
+fn handle_continue(
+    req: Request,
+    args: &ContinueArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Continue: {args:?}"));
+    check_thread_id(args.thread_id)?;
+
+    // singleThread: продолжить только args.thread_id, остальные остаются стоять
+    let all_threads = !args.single_thread.unwrap_or(false);
+    st.single_thread_resume = !all_threads;
+    if all_threads {
+        st.set_all_running(true);
+    } else {
+        st.set_thread_running(args.thread_id, true);
+    }
+
+    // ContinueResponse из dap уходит как all_threads_continued — клиент ключа не найдёт
+    // и сочтёт, что продолжились все потоки. Тело — сами, в camelCase
+    st.response_patches.set(
+        req.seq,
+        "body",
+        json!({ "allThreadsContinued": all_threads }),
+    );
+    respond_success(
+        req,
+        ResponseBody::Continue(ContinueResponse {
+            all_threads_continued: Some(all_threads),
+        }),
+        server,
+        st,
+    )?;
+
+    send_run_event(
+        server,
+        st,
+        Event::Continued(dap::events::ContinuedEventBody {
+            thread_id: args.thread_id,
+            all_threads_continued: Some(all_threads),
+        }),
+    )?;
 
-    server.send(Sendable::Response(Response {
-        request_seq: req.seq,
-        success: false,
-        message: Some(ResponseMessage::Error(format!(
-            "Unsupported command: {:?}",
-            req.command
-        ))),
-        body: None,
-        error: None,
-    }))?;
     Ok(())
 }
+
+// next/stepIn/stepOut: в демо все три — шаг в пределах верхнего фрейма.
+// singleThread: шагает только thread_id, остальные потоки своё состояние не меняют.
+fn handle_step(
+    req: Request,
+    body: ResponseBody,
+    thread_id: i64,
+    single_thread: bool,
+    granularity: Option<&SteppingGranularity>,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(
+        server,
+        st.log_level,
+        format!(
+            "{body:?}: thread {thread_id}, single thread {single_thread}, granularity {granularity:?}"
+        ),
+    );
+    check_thread_id(thread_id)?;
+
+    respond_success(req, body, server, st)?;
+
+    st.step(granularity);
+    st.begin_stop();
+    st.single_thread_resume = single_thread;
+    report_first_stop(server, st)?;
+    st.ensure_thread(thread_id);
+    if single_thread {
+        st.set_thread_running(thread_id, false);
+    } else {
+        st.set_all_running(false);
+    }
+    let all_threads_stopped = !st.threads.values().any(|t| t.running);
+
+    let event = stopped_event(
+        st,
+        StoppedEventReason::Step,
+        "Step",
+        thread_id,
+        all_threads_stopped,
+        None,
+    );
+    send_run_event(server, st, event)?;
+
+    Ok(())
+}
+
+fn handle_stack_trace(
+    req: Request,
+    args: &StackTraceArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("StackTrace: {args:?}"));
+
+    // startFrame/levels: страница стека; levels 0 или нет — до конца
+    let start = args.start_frame.unwrap_or(0).max(0) as usize;
+    let levels = match args.levels {
+        Some(n) if n > 0 => n as usize,
+        _ => usize::MAX,
+    };
+
+    let frames: Vec<StackFrame> = st
+        .call_stack
+        .iter()
+        .skip(start)
+        .take(levels)
+        .map(|frame| {
+            // Без известного source кадр показывается просто подписью (subtle), а не "unknown"-файлом
+            let source = frame.source.clone().map(|s| st.to_client(s));
+            let presentation_hint = source
+                .is_none()
+                .then_some(StackFramePresentationhint::Subtle);
+            // Только модуль, о котором клиент уже знает из module event'ов.
+            // ModuleId::Number в dap без значения — такой id не передать
+            let module_id = frame
+                .module
+                .as_deref()
+                .and_then(|name| st.modules.iter().find(|m| m.name == name))
+                .and_then(|m| match &m.id {
+                    ModuleId::String(id) => Some(StackFrameModuleid::String(id.clone())),
+                    ModuleId::Number => None,
+                });
+            StackFrame {
+                id: frame.id,
+                name: frame_name(frame, args.format.as_ref(), st),
+                source,
+                line: frame.line,
+                column: frame.column,
+                end_line: None,
+                end_column: None,
+                can_restart: None,
+                instruction_pointer_reference: frame.pc.map(format_address),
+                module_id,
+                presentation_hint,
+            }
+        })
+        .collect();
+    // Неизвестная глубина — None, а не число: по заниженному totalFrames клиент перестал бы
+    // подгружать стек раньше времени
+    let total_frames = st.stack_depth_known.then_some(st.call_stack.len() as i64);
+
+    respond_success(
+        req,
+        ResponseBody::StackTrace(StackTraceResponse {
+            stack_frames: frames,
+            total_frames,
+        }),
+        server,
+        st,
+    )?;
+
+    Ok(())
+}
+
+fn handle_scopes(
+    req: Request,
+    args: &ScopesArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Scopes: {args:?}"));
+
+    // Фрейм с прошлой остановки — ошибка, а не пустой список: клиент должен перезапросить стек
+    let frame = st.frame(args.frame_id).ok_or_else(|| {
+        HandlerError::NotFound(format!(
+            "Unknown frameId {}: it belongs to a previous stop",
+            args.frame_id
+        ))
+    })?;
+    let mut scopes = Vec::new();
+    for (name, hint, reference) in [
+        (
+            "Arguments",
+            ScopePresentationhint::Arguments,
+            frame.arguments_ref,
+        ),
+        ("Locals", ScopePresentationhint::Locals, frame.locals_ref),
+    ] {
+        scopes.push(Scope {
+            name: name.to_string(),
+            presentation_hint: Some(hint),
+            variables_reference: reference,
+            named_variables: st.variables.get(&reference).map(|v| v.len() as i64),
+            indexed_variables: None,
+            expensive: false,
+            source: None,
+            line: None,
+            column: None,
+            end_line: None,
+            end_column: None,
+        });
+    }
+
+    respond_success(
+        req,
+        ResponseBody::Scopes(ScopesResponse { scopes }),
+        server,
+        st,
+    )?;
+    Ok(())
+}
+
+fn handle_variables(
+    req: Request,
+    args: &VariablesArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Variables: {args:?}"));
+
+    // Устаревшая ссылка (с прошлой остановки) — ошибка, а не чужие переменные
+    if st.frame_of_ref(args.variables_reference).is_none() {
+        return Err(HandlerError::NotFound(format!(
+            "Unknown variablesReference {}: it belongs to a previous stop",
+            args.variables_reference
+        )));
+    }
+    let mut variables = st.cached_variables(args.variables_reference, args.format.as_ref());
+    // filter: named — только поля, indexed — только элементы массива
+    match args.filter {
+        Some(VariablesArgumentsFilter::Named) => variables.retain(|v| !is_index_variable(v)),
+        Some(VariablesArgumentsFilter::Indexed) => variables.retain(is_index_variable),
+        None => {}
+    }
+
+    respond_success(
+        req,
+        ResponseBody::Variables(VariablesResponse { variables }),
+        server,
+        st,
+    )?;
+    Ok(())
+}
+
+fn handle_set_variable(
+    req: Request,
+    args: &SetVariableArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("SetVariable: {args:?}"));
+
+    let var = st.set_variable(args.variables_reference, &args.name, &args.value)?;
+
+    // Контейнер получил новую ссылку — клиент перераскроет узел по ней
+    let body = SetVariableResponse {
+        value: var.value,
+        type_field: var.type_field,
+        variables_reference: Some(var.variables_reference),
+        named_variables: var.named_variables.map(|n| n as i32),
+        indexed_variables: var.indexed_variables.map(|n| n as i32),
+    };
+    respond_success(req, ResponseBody::SetVariable(body), server, st)?;
+
+    // От изменённой переменной могут зависеть другие в том же фрейме — пусть клиент
+    // перечитает фрейм целиком. Без supportsInvalidatedEvent событие ему непонятно
+    let supports_invalidated = st
+        .client
+        .as_ref()
+        .and_then(|c| c.supports_invalidated_event)
+        .unwrap_or(false);
+    let frame_id = st
+        .frame_of_ref(args.variables_reference)
+        .filter(|_| supports_invalidated);
+    if let Some(frame_id) = frame_id {
+        st.events.push(
+            server,
+            Event::Invalidated(InvalidatedEventBody {
+                areas: Some(vec![InvalidatedAreas::Variables]),
+                thread_id: None,
+                stack_frame_id: Some(frame_id),
+            }),
+        )?;
+    }
+    Ok(())
+}
+
+fn handle_modules(
+    req: Request,
+    args: &ModulesArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Modules: {args:?}"));
+
+    let modules = modules_page(st, args.start_module, args.module_count);
+    let body = ModulesResponse {
+        modules,
+        total_modules: Some(st.modules.len() as i64),
+    };
+
+    respond_success(req, ResponseBody::Modules(body), server, st)?;
+    Ok(())
+}
+
+fn handle_evaluate(
+    req: Request,
+    args: &EvaluateArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Evaluate: {args:?}"));
+
+    let expression = args.expression.trim();
+
+    if matches!(args.context, Some(EvaluateArgumentsContext::Hover)) && !is_hover_safe(expression) {
+        return Err(HandlerError::InvalidArgument(
+            "Hover evaluation skipped: expression may have side effects".to_string(),
+        ));
+    }
+
+    // Служебные REPL-команды адаптера
+    if expression == "$metrics" {
+        respond_success(
+            req,
+            ResponseBody::Evaluate(EvaluateResponse {
+                result: st.metrics.summary(),
+                variables_reference: 0,
+                ..Default::default()
+            }),
+            server,
+            st,
+        )?;
+        return Ok(());
+    }
+
+    if expression == "$state" || expression == "$state full" {
+        let result = if expression == "$state" {
+            st.state_summary()
+        } else {
+            st.verbose_debug()
+        };
+        respond_success(
+            req,
+            ResponseBody::Evaluate(EvaluateResponse {
+                result,
+                variables_reference: 0,
+                ..Default::default()
+            }),
+            server,
+            st,
+        )?;
+        return Ok(());
+    }
+
+    if let Some(query) = expression
+        .strip_prefix("$sym")
+        .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+    {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(HandlerError::InvalidArgument(
+                "Usage: $sym <name>".to_string(),
+            ));
+        }
+        let matches = st.find_symbols(query);
+        let result = if matches.is_empty() {
+            format!("No symbols matching '{query}'")
+        } else {
+            matches
+                .iter()
+                .map(|(name, sym)| {
+                    format!("{name}  {}  ({})", format_address(sym.address), sym.module)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        respond_success(
+            req,
+            ResponseBody::Evaluate(EvaluateResponse {
+                result,
+                variables_reference: 0,
+                ..Default::default()
+            }),
+            server,
+            st,
+        )?;
+        return Ok(());
+    }
+
+    // Имя переменной — отдаём её как есть (с детьми), иначе считаем выражение
+    let body = match st.find_variable_in_frame(expression, args.frame_id) {
+        Some(var) => {
+            // clipboard: полное значение целиком, без variables_reference — только для копирования
+            if matches!(args.context, Some(EvaluateArgumentsContext::Clipboard)) {
+                EvaluateResponse {
+                    result: st.expand_value(var),
+                    type_field: var.type_field.clone(),
+                    variables_reference: 0,
+                    ..Default::default()
+                }
+            } else {
+                EvaluateResponse {
+                    result: var.value.clone(),
+                    type_field: var.type_field.clone(),
+                    variables_reference: var.variables_reference,
+                    named_variables: var.named_variables,
+                    indexed_variables: var.indexed_variables,
+                    ..Default::default()
+                }
+            }
+        }
+        None => match st.evaluator.eval(expression, args.frame_id, st) {
+            Ok(value) => EvaluateResponse {
+                // Тип результата известен только для чисел — hex применим к ним
+                result: render_value(
+                    &value_to_string(&value),
+                    value.is_i64().then_some("i64"),
+                    args.format.as_ref(),
+                ),
+                variables_reference: 0,
+                ..Default::default()
+            },
+            Err(e) => {
+                return Err(HandlerError::BackendError(format!(
+                    "Cannot evaluate '{expression}': {e}"
+                )));
+            }
+        },
+    };
+
+    respond_success(req, ResponseBody::Evaluate(body), server, st)?;
+    Ok(())
+}
+
+fn handle_completions(
+    req: Request,
+    args: &CompletionsArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Completions: {args:?}"));
+
+    let targets = completion_items(st, &args.text, args.column, args.frame_id);
+
+    respond_success(
+        req,
+        ResponseBody::Completions(CompletionsResponse { targets }),
+        server,
+        st,
+    )?;
+    Ok(())
+}
+
+fn handle_disconnect(
+    req: Request,
+    args: &DisconnectArguments,
+    server: &mut DapServer,
+    st: &mut DapState,
+) -> HandlerResult {
+    dap_debug(server, st.log_level, format!("Disconnect: {args:?}"));
+    if st.tracing {
+        trace_write(st.session_summary());
+    }
+    // restart: клиент сразу переподключится, debuggee и брейки не трогаем
+    if args.restart.unwrap_or(false) {
+        st.events.drain(server)?;
+        respond_success(req, ResponseBody::Disconnect, server, st)?;
+        st.prepare_reconnect();
+        return Ok(());
+    }
+
+    // terminateDebuggee не указан — как в спецификации: launch завершаем, от attach отцепляемся
+    let terminate = args.terminate_debuggee.unwrap_or(!st.attached);
+    if terminate {
+        dap_debug(server, st.log_level, "Terminating the debuggee");
+        for event in thread_events(st, ThreadEventReason::Exited) {
+            st.events.push(server, event)?;
+        }
+    } else if args.suspend_debuggee.unwrap_or(false) {
+        // suspendDebuggee: отцепиться, оставив потоки стоять — кто-то подключится позже
+        dap_debug(
+            server,
+            st.log_level,
+            "Detaching, the debuggee stays suspended",
+        );
+    } else {
+        // Detach: процесс и его потоки продолжают работать без нас, exited не шлём
+        dap_debug(
+            server,
+            st.log_level,
+            "Detaching, the debuggee keeps running",
+        );
+    }
+
+    send_telemetry(
+        server,
+        st,
+        "disconnect",
+        json!({
+            "terminate": terminate,
+            "attached": st.attached,
+            "sessionMs": st.started_at.elapsed().as_millis() as u64,
+        }),
+    )?;
+
+    // Всё, что относится к сессии (module removed и т.п.), уходит ДО ответа:
+    // после disconnect клиент закрывает поток и хвост вывода теряется.
+    // Server сам флашит BufWriter после каждого сообщения, отдельный flush не нужен.
+    unload_all_modules(server, st)?;
+    st.events.drain(server)?;
+    respond_success(req, ResponseBody::Disconnect, server, st)?;
+    st.teardown();
+    Ok(())
+}
+
+// Пустое, но допустимое по спецификации тело для "мягко" неподдержанных запросов
+fn empty_body(command: &Command) -> Option<ResponseBody> {
+    match command {
+        Command::LoadedSources => Some(ResponseBody::LoadedSources(LoadedSourcesResponse {
+            sources: Vec::new(),
+        })),
+        _ => None,
+    }
+}
+
+fn handle_unsupported(req: Request, server: &mut DapServer, st: &mut DapState) -> HandlerResult {
+    if st.soft_unsupported.contains(&command_name(&req.command)) {
+        if let Some(body) = empty_body(&req.command) {
+            dap_debug(
+                server,
+                st.log_level,
+                format!("Soft-unsupported: {:?}", req.command),
+            );
+            respond_success(req, body, server, st)?;
+            return Ok(());
+        }
+    }
+
+    dap_log_at(
+        server,
+        st.log_level,
+        LogLevel::Warn,
+        format!("Unsupported command: {:?}", req.command),
+    );
+
+    Err(HandlerError::Unsupported(format!(
+        "Unsupported command: {:?}",
+        req.command
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, BufWriter, Read, Write};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use dap::errors::ServerError;
+    use dap::server::Server;
+
+    use super::*;
+    use crate::custom_request::{FrameCapture, RawFrames};
+    use crate::trace::{take_captured, FrameSplitter, TraceReader, TraceWriter};
+    use crate::transport::{PatchWriter, ResponsePatches, RetryReader, SnakeCaseReader};
+    use crate::utils::{error_detail, is_broken_pipe};
+
+    // Байты между клиентом и адаптером: запросы в одну сторону, всё написанное — в другую.
+    // closed — клиент закрыл поток: запись дальше падает с BrokenPipe
+    #[derive(Clone, Default)]
+    struct Wire {
+        bytes: Arc<Mutex<Vec<u8>>>,
+        closed: Arc<AtomicBool>,
+    }
+
+    impl Write for Wire {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.closed.load(Ordering::SeqCst) {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            self.bytes.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for Wire {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut bytes = self.bytes.lock().unwrap();
+            let n = buf.len().min(bytes.len());
+            buf[..n].copy_from_slice(&bytes[..n]);
+            bytes.drain(..n);
+            Ok(n)
+        }
+    }
+
+    struct Session {
+        server: DapServer,
+        st: DapState,
+        input: Wire,
+        frames: RawFrames,
+        wire: Wire,
+        seq: i64,
+    }
+
+    impl Session {
+        fn new() -> Self {
+            Self::with_tracing(false)
+        }
+
+        // tracing — как с RUST_DAP_TRACE=1: reader и writer разбирают каждый фрейм
+        fn with_tracing(tracing: bool) -> Self {
+            let input = Wire::default();
+            let frames = RawFrames::default();
+            let wire = Wire::default();
+            let patches = ResponsePatches::default();
+            let reader: Box<dyn Read> = Box::new(input.clone());
+            let reader = SnakeCaseReader::new(FrameCapture::new(
+                TraceReader::with_tracing(RetryReader::new(reader), tracing),
+                frames.clone(),
+            ));
+            let output: Box<dyn Write + Send> = Box::new(wire.clone());
+            let output =
+                PatchWriter::new(TraceWriter::with_tracing(output, tracing), patches.clone());
+            let mut st = DapState::new();
+            st.log_level = LogLevel::Error;
+            st.telemetry = false;
+            st.tracing = tracing;
+            st.response_patches = patches;
+            st.custom_commands = custom_commands();
+            Self {
+                server: Server::new(BufReader::new(reader), BufWriter::new(output)),
+                st,
+                input,
+                frames,
+                wire,
+                seq: 0,
+            }
+        }
+
+        // initialize, launch и configurationDone — сессия, в которой уже можно останавливаться
+        fn launched() -> Self {
+            let mut session = Self::new();
+            session.request("initialize", json!({ "adapterID": "rast" }));
+            session.request("launch", json!({ "program": "/tmp/demo" }));
+            session.request("configurationDone", Value::Null);
+            session
+        }
+
+        fn next_message(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let mut message = json!({ "seq": self.seq, "type": "request", "command": command });
+            if !arguments.is_null() {
+                message["arguments"] = arguments;
+            }
+            message
+        }
+
+        // Все сообщения, которые адаптер отправил в ответ на запрос. Запрос идёт по тому же
+        // пути, что и в main: байтами через reader'ы, poll_request, handle или handle_custom
+        fn request(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+            self.dispatch(command, arguments).unwrap();
+            self.sent()
+        }
+
+        // Как request, но ошибку, которую main только залогирует, отдаёт тесту
+        fn dispatch(&mut self, command: &str, arguments: Value) -> DynResult<()> {
+            let frame = self.next_message(command, arguments).to_string();
+            write!(self.input, "Content-Length: {}\r\n\r\n{frame}", frame.len()).unwrap();
+            let polled = self.server.poll_request();
+            let frame = self.frames.next();
+            match polled {
+                Ok(Some(req)) => {
+                    self.st.request_frame = frame;
+                    handle(req, &mut self.server, &mut self.st)
+                }
+                Err(ServerError::ParseError(e)) => handle_custom(
+                    &frame.unwrap(),
+                    &error_detail(&e),
+                    &mut self.server,
+                    &mut self.st,
+                ),
+                other => panic!("request was not read: {other:?}"),
+            }
+        }
+
+        fn response(&mut self, command: &str, arguments: Value) -> Value {
+            let messages = self.request(command, arguments);
+            response_in(&messages)
+        }
+
+        fn sent(&mut self) -> Vec<Value> {
+            let bytes = std::mem::take(&mut *self.wire.bytes.lock().unwrap());
+            FrameSplitter::default()
+                .push(&bytes)
+                .iter()
+                .map(|frame| serde_json::from_str(frame).unwrap())
+                .collect()
+        }
+    }
+
+    fn response_in(messages: &[Value]) -> Value {
+        let responses: Vec<&Value> = messages
+            .iter()
+            .filter(|m| m["type"] == "response")
+            .collect();
+        assert_eq!(responses.len(), 1, "exactly one response: {messages:?}");
+        responses[0].clone()
+    }
+
+    fn events<'a>(messages: &'a [Value], name: &str) -> Vec<&'a Value> {
+        messages.iter().filter(|m| m["event"] == name).collect()
+    }
+
+    // Порядок сообщений: имя события или "response"
+    fn kinds(messages: &[Value]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|m| match m["event"].as_str() {
+                Some(event) => event.to_string(),
+                None => "response".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn initialized_follows_the_initialize_response() {
+        let mut session = Session::new();
+        let messages = session.request("initialize", json!({ "adapterID": "rast" }));
+        assert_eq!(kinds(&messages), ["response", "initialized"]);
+    }
+
+    #[test]
+    fn restart_events_follow_the_response_in_order() {
+        let mut session = Session::new();
+        session.request("initialize", json!({ "adapterID": "rast" }));
+        session.request(
+            "launch",
+            json!({ "program": "/tmp/demo", "retainConsoleOnRestart": true }),
+        );
+        session.request("configurationDone", Value::Null);
+        session.st.remember_output("hello");
+        let messages = session.request("restart", json!({}));
+        assert_eq!(
+            kinds(&messages),
+            ["response", "thread", "thread", "output", "output", "module"]
+        );
+        let reasons: Vec<&Value> = events(&messages, "thread")
+            .iter()
+            .map(|m| &m["body"]["reason"])
+            .collect();
+        assert_eq!(reasons, ["exited", "started"]);
+        let outputs: Vec<&str> = events(&messages, "output")
+            .iter()
+            .map(|m| m["body"]["output"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            outputs,
+            ["hello\n", format!("{RESTART_SEPARATOR}\n").as_str()]
+        );
+    }
+
+    #[test]
+    fn back_to_back_restarts_both_run() {
+        let mut session = Session::launched();
+        for _ in 0..2 {
+            let response = session.response("restart", json!({}));
+            assert_eq!(response["success"], true, "{response}");
+        }
+    }
+
+    #[test]
+    fn requests_before_initialize_are_rejected() {
+        let mut session = Session::new();
+        let response = session.response(
+            "setBreakpoints",
+            json!({ "source": { "path": "/tmp/a.rs" }, "breakpoints": [{ "line": 1 }] }),
+        );
+        assert_eq!(response["success"], false);
+        assert_eq!(
+            response["message"],
+            "'setBreakpoints' received before initialize"
+        );
+    }
+
+    #[test]
+    fn error_message_goes_into_the_body() {
+        let mut session = Session::new();
+        let response = session.response(
+            "setBreakpoints",
+            json!({ "source": { "path": "/tmp/a.rs" }, "breakpoints": [{ "line": 1 }] }),
+        );
+        let error = &response["body"]["error"];
+        assert_eq!(error["id"], 1001, "{response}");
+        assert_eq!(
+            error["format"],
+            "'setBreakpoints' received before initialize"
+        );
+        assert!(response["error"].is_null(), "{response}");
+    }
+
+    #[test]
+    fn custom_response_carries_its_command() {
+        let mut session = Session::launched();
+        let messages = session.request("rast/reloadSymbols", Value::Null);
+        let response = response_in(&messages);
+        assert_eq!(response["success"], true, "{response}");
+        assert_eq!(response["command"], "rast/reloadSymbols");
+        assert_eq!(kinds(&messages)[0], "response");
+    }
+
+    #[test]
+    fn custom_requests_go_through_the_phase_check() {
+        let mut session = Session::new();
+        let response = response_in(&session.request("rast/reloadSymbols", Value::Null));
+        assert_eq!(response["success"], false);
+        assert_eq!(response["command"], "rast/reloadSymbols");
+        assert_eq!(
+            response["message"],
+            "'rast/reloadSymbols' received before initialize"
+        );
+    }
+
+    #[test]
+    fn unknown_custom_command_is_unsupported() {
+        let mut session = Session::launched();
+        let response = response_in(&session.request("rast/nope", json!({})));
+        assert_eq!(response["success"], false);
+        assert_eq!(response["command"], "rast/nope");
+        assert_eq!(response["body"]["error"]["id"], 1004);
+        assert_eq!(response["message"], "Unsupported command: 'rast/nope'");
+    }
+
+    #[test]
+    fn malformed_standard_request_reports_its_arguments() {
+        let mut session = Session::launched();
+        let response = response_in(&session.request("scopes", json!({ "frameId": "top" })));
+        assert_eq!(response["success"], false);
+        assert_eq!(response["command"], "scopes");
+        let error = &response["body"]["error"];
+        assert_eq!(error["id"], 1001, "{response}");
+        assert_eq!(
+            error["format"],
+            "Invalid arguments for '{command}': {error}"
+        );
+        let message = response["message"].as_str().unwrap();
+        assert!(
+            message.starts_with("Invalid arguments for 'scopes': "),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn error_response_carries_its_command() {
+        let mut session = Session::launched();
+        let response = session.response("threads", Value::Null);
+        assert_eq!(response["success"], true);
+        let response = session.response("exceptionInfo", json!({ "threadId": 1 }));
+        assert_eq!(response["success"], false);
+        assert_eq!(response["command"], "exceptionInfo");
+    }
+
+    #[test]
+    fn configuration_done_twice_is_rejected_after_launch() {
+        let mut session = Session::launched();
+        assert_eq!(session.st.phase, SessionPhase::Running);
+        let response = session.response("configurationDone", Value::Null);
+        assert_eq!(response["success"], false);
+        assert_eq!(response["message"], "configurationDone received twice");
+    }
+
+    #[test]
+    fn configuration_done_before_launch_then_launch() {
+        let mut session = Session::new();
+        session.request("initialize", json!({ "adapterID": "rast" }));
+        let done = session.response("configurationDone", Value::Null);
+        assert_eq!(done["success"], true);
+        assert_eq!(session.st.phase, SessionPhase::Configured);
+        let launch = session.response("launch", json!({ "program": "/tmp/demo" }));
+        assert_eq!(launch["success"], true);
+        assert_eq!(session.st.phase, SessionPhase::Running);
+        let again = session.response("launch", json!({ "program": "/tmp/demo" }));
+        assert_eq!(again["success"], false);
+    }
+
+    fn entry_timer_session() -> Session {
+        let mut session = Session::new();
+        session.request("initialize", json!({ "adapterID": "rast" }));
+        session.request(
+            "launch",
+            json!({ "program": "/tmp/demo", "stopOnEntry": true, "entryContinueAfter": 20 }),
+        );
+        let messages = session.request("configurationDone", Value::Null);
+        assert_eq!(events(&messages, "stopped").len(), 1, "{messages:?}");
+        session
+    }
+
+    #[test]
+    fn entry_timer_sends_continued() {
+        let mut session = entry_timer_session();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(events(&session.sent(), "continued").len(), 1);
+    }
+
+    #[test]
+    fn entry_timer_is_cancelled_by_disconnect() {
+        let mut session = entry_timer_session();
+        session.request("disconnect", json!({}));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(events(&session.sent(), "continued").is_empty());
+    }
+
+    // Сессия, остановленная в compute (верхний фрейм) <- main
+    fn paused() -> Session {
+        let mut session = Session::launched();
+        let thread_id = session.st.main_thread_id;
+        session.request("pause", json!({ "threadId": thread_id }));
+        session
+    }
+
+    // Паника от бэкенда: первое сообщение — сама паника, остальные — причины
+    fn panic(session: &mut Session, messages: &[&str]) -> Vec<Value> {
+        let (message, causes) = messages.split_first().unwrap();
+        session.request(
+            "rast/panic",
+            json!({ "message": message, "causes": causes }),
+        )
+    }
+
+    #[test]
+    fn panic_report_needs_a_running_debuggee_and_a_message() {
+        let mut session = Session::new();
+        session.request("initialize", json!({ "adapterID": "rast" }));
+        let response = response_in(&panic(&mut session, &["boom"]));
+        assert_eq!(response["success"], false, "{response}");
+
+        let mut session = Session::launched();
+        let response = response_in(&session.request("rast/panic", json!({})));
+        assert_eq!(response["success"], false, "{response}");
+        assert!(response["message"].as_str().unwrap().contains("message"));
+    }
+
+    #[test]
+    fn panic_without_the_filter_does_not_stop() {
+        let mut session = Session::launched();
+        session.request("setExceptionBreakpoints", json!({ "filters": [] }));
+        assert!(events(&panic(&mut session, &["boom"]), "stopped").is_empty());
+        assert!(session.st.last_exception.is_none());
+    }
+
+    #[test]
+    fn panic_filter_stops_with_the_cause_chain() {
+        let mut session = Session::launched();
+        session.request("setExceptionBreakpoints", json!({ "filters": ["panic"] }));
+        let messages = panic(&mut session, &["boom", "io error"]);
+        let stopped = events(&messages, "stopped");
+        assert_eq!(stopped.len(), 1, "{messages:?}");
+        assert_eq!(stopped[0]["body"]["reason"], "exception");
+
+        let thread_id = session.st.main_thread_id;
+        let info = session.response("exceptionInfo", json!({ "threadId": thread_id }));
+        assert_eq!(info["body"]["breakMode"], "always");
+        assert_eq!(info["body"]["description"], "boom");
+        let inner = &info["body"]["details"]["innerException"];
+        assert_eq!(inner[0]["message"], "io error");
+    }
+
+    #[test]
+    fn unhandled_panic_stops_even_without_the_filter() {
+        let mut session = Session::launched();
+        session.request("setExceptionBreakpoints", json!({ "filters": [] }));
+        let thread_id = session.st.main_thread_id;
+        let messages = session.request(
+            "rast/panic",
+            json!({ "message": "boom", "causes": ["io error"], "unhandled": true }),
+        );
+        assert_eq!(events(&messages, "stopped").len(), 1, "{messages:?}");
+        let info = session.response("exceptionInfo", json!({ "threadId": thread_id }));
+        assert_eq!(info["body"]["breakMode"], "unhandled");
+        assert_eq!(
+            info["body"]["details"]["innerException"][0]["message"],
+            "io error"
+        );
+
+        // Условие фильтра непойманную панику не отменяет
+        session.request("continue", json!({ "threadId": thread_id }));
+        session.request(
+            "setExceptionBreakpoints",
+            json!({
+                "filters": [],
+                "filterOptions": [{ "filterId": "panic", "condition": "1 == 2" }],
+            }),
+        );
+        let messages = session.request(
+            "rast/panic",
+            json!({ "message": "boom", "unhandled": true }),
+        );
+        assert_eq!(events(&messages, "stopped").len(), 1, "{messages:?}");
+        let info = session.response("exceptionInfo", json!({ "threadId": thread_id }));
+        assert_eq!(info["body"]["breakMode"], "always");
+    }
+
+    #[test]
+    fn panic_filter_condition_is_evaluated() {
+        let mut session = Session::launched();
+        let set = |session: &mut Session, condition: &str| {
+            session.request(
+                "setExceptionBreakpoints",
+                json!({
+                    "filters": [],
+                    "filterOptions": [{ "filterId": "panic", "condition": condition }],
+                }),
+            );
+        };
+        set(&mut session, "1 == 2");
+        assert!(events(&panic(&mut session, &["boom"]), "stopped").is_empty());
+        set(&mut session, "1 == 1");
+        assert_eq!(events(&panic(&mut session, &["boom"]), "stopped").len(), 1);
+    }
+
+    #[test]
+    fn continue_body_is_camel_case() {
+        let mut session = paused();
+        let thread_id = session.st.main_thread_id;
+        let response = session.response(
+            "continue",
+            json!({ "threadId": thread_id, "singleThread": true }),
+        );
+        assert_eq!(
+            response["body"],
+            json!({ "allThreadsContinued": false }),
+            "{response}"
+        );
+
+        session.request("pause", json!({ "threadId": thread_id }));
+        let response = session.response("continue", json!({ "threadId": thread_id }));
+        assert_eq!(response["body"], json!({ "allThreadsContinued": true }));
+    }
+
+    #[test]
+    fn single_thread_step_leaves_other_threads_alone() {
+        let mut session = paused();
+        let main = session.st.main_thread_id;
+        session.st.ensure_thread(42);
+        session.request("continue", json!({ "threadId": 42, "singleThread": true }));
+        assert!(session.st.is_thread_running(42));
+
+        let messages = session.request("next", json!({ "threadId": main, "singleThread": true }));
+        let stopped = events(&messages, "stopped");
+        assert_eq!(stopped[0]["body"]["threadId"], main);
+        assert_eq!(stopped[0]["body"]["allThreadsStopped"], false);
+        assert!(session.st.is_thread_running(42));
+        assert!(!session.st.is_thread_running(main));
+    }
+
+    #[test]
+    fn all_threads_resume_after_a_single_thread_step_pauses_everyone() {
+        let mut session = paused();
+        let main = session.st.main_thread_id;
+        session.st.ensure_thread(42);
+        session.request("next", json!({ "threadId": main, "singleThread": true }));
+        session.request("continue", json!({ "threadId": main }));
+        assert!(session.st.is_thread_running(42));
+
+        let messages = session.request("pause", json!({ "threadId": main }));
+        let stopped = events(&messages, "stopped");
+        assert_eq!(
+            stopped[0]["body"]["allThreadsStopped"], true,
+            "{messages:?}"
+        );
+        assert!(!session.st.is_thread_running(42));
+    }
+
+    #[test]
+    fn a_stop_of_every_thread_ends_single_thread_mode() {
+        let mut session = paused();
+        let main = session.st.main_thread_id;
+        session.st.ensure_thread(42);
+        session.request("continue", json!({ "threadId": 42, "singleThread": true }));
+        session.request("setExceptionBreakpoints", json!({ "filters": ["panic"] }));
+        panic(&mut session, &["boom"]);
+        assert!(!session.st.single_thread_resume);
+        assert!(!session.st.is_thread_running(42));
+        assert!(!session.st.is_thread_running(main));
+    }
+
+    #[test]
+    fn pause_registers_an_unknown_thread() {
+        let mut session = Session::launched();
+        let messages = session.request("pause", json!({ "threadId": 42 }));
+        let stopped = events(&messages, "stopped");
+        assert_eq!(stopped.len(), 1, "{messages:?}");
+        assert_eq!(stopped[0]["body"]["threadId"], 42);
+
+        let response = session.response("threads", Value::Null);
+        let ids: Vec<&Value> = response["body"]["threads"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| &t["id"])
+            .collect();
+        assert!(ids.contains(&&json!(42)), "{ids:?}");
+    }
+
+    #[test]
+    fn references_from_a_previous_stop_are_rejected() {
+        let mut session = paused();
+        let stale_frame = session.st.call_stack[0].id;
+        let stale_locals = session.st.call_stack[0].locals_ref;
+        let thread_id = session.st.main_thread_id;
+        session.request("continue", json!({ "threadId": thread_id }));
+        session.request("pause", json!({ "threadId": thread_id }));
+        let current_frame = session.st.call_stack[0].id;
+        let current_locals = session.st.call_stack[0].locals_ref;
+        assert_ne!(stale_frame, current_frame);
+        assert_ne!(stale_locals, current_locals);
+
+        let response = session.response("scopes", json!({ "frameId": stale_frame }));
+        assert_eq!(response["success"], false, "{response}");
+        assert!(response["message"]
+            .as_str()
+            .unwrap()
+            .contains("previous stop"));
+        let response = session.response("variables", json!({ "variablesReference": stale_locals }));
+        assert_eq!(response["success"], false, "{response}");
+        assert!(response["message"]
+            .as_str()
+            .unwrap()
+            .contains("previous stop"));
+
+        let response = session.response("scopes", json!({ "frameId": current_frame }));
+        assert_eq!(response["body"]["scopes"].as_array().unwrap().len(), 2);
+        let response =
+            session.response("variables", json!({ "variablesReference": current_locals }));
+        assert_eq!(response["success"], true, "{response}");
+    }
+
+    #[test]
+    fn set_variable_evaluates_in_the_owning_frame() {
+        let mut session = paused();
+        let main_locals = session.st.call_stack[1].locals_ref;
+        let compute_locals = session.st.call_stack[0].locals_ref;
+
+        // demo есть только у compute: в main его не видно
+        let response = session.response(
+            "setVariable",
+            json!({ "variablesReference": main_locals, "name": "total", "value": "demo + 1" }),
+        );
+        assert_eq!(response["success"], false, "{response}");
+
+        let response = session.response(
+            "setVariable",
+            json!({ "variablesReference": main_locals, "name": "total", "value": "5" }),
+        );
+        assert_eq!(response["success"], true, "{response}");
+        assert_eq!(response["body"]["value"], "5");
+
+        let response = session.response(
+            "setVariable",
+            json!({ "variablesReference": compute_locals, "name": "demo", "value": "factor + 1" }),
+        );
+        assert_eq!(response["success"], true, "{response}");
+        assert_eq!(response["body"]["value"], "5");
+    }
+
+    #[test]
+    fn single_thread_continue_resumes_only_that_thread() {
+        let mut session = paused();
+        let main = session.st.main_thread_id;
+        session.st.ensure_thread(42);
+        let messages = session.request(
+            "continue",
+            json!({ "threadId": main, "singleThread": true }),
+        );
+        let continued = events(&messages, "continued");
+        assert_eq!(continued[0]["body"]["threadId"], main);
+        assert_eq!(continued[0]["body"]["allThreadsContinued"], false);
+        assert!(session.st.is_thread_running(main));
+        assert!(!session.st.is_thread_running(42));
+
+        session.request("continue", json!({ "threadId": main }));
+        assert!(session.st.is_thread_running(42));
+    }
+
+    #[test]
+    fn clipboard_evaluate_expands_the_whole_value() {
+        let mut session = Session::new();
+        let response = session.response("initialize", json!({ "adapterID": "rast" }));
+        assert_eq!(response["body"]["supportsClipboardContext"], true);
+        session.request("launch", json!({ "program": "/tmp/demo" }));
+        session.request("configurationDone", Value::Null);
+        let thread_id = session.st.main_thread_id;
+        session.request("pause", json!({ "threadId": thread_id }));
+
+        let response = session.response(
+            "evaluate",
+            json!({ "expression": "point", "context": "clipboard" }),
+        );
+        assert_eq!(response["body"]["result"], "{ x: 3, y: 4 }", "{response}");
+        assert_eq!(response["body"]["variablesReference"], 0);
+
+        let response = session.response(
+            "evaluate",
+            json!({ "expression": "point", "context": "repl" }),
+        );
+        assert_ne!(response["body"]["variablesReference"], 0, "{response}");
+    }
+
+    // id брейков из ответа setBreakpoints, по порядку
+    fn set_breakpoints(session: &mut Session, source: Value, lines: &[i64]) -> Vec<i64> {
+        let breakpoints: Vec<Value> = lines.iter().map(|line| json!({ "line": line })).collect();
+        let response = session.response(
+            "setBreakpoints",
+            json!({ "source": source, "breakpoints": breakpoints }),
+        );
+        assert_eq!(response["success"], true, "{response}");
+        response["body"]["breakpoints"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bp| bp["id"].as_i64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn breakpoint_ids_survive_restart_and_never_repeat() {
+        let mut session = Session::launched();
+        let a = json!({ "path": "/tmp/a.rs" });
+        let first = set_breakpoints(&mut session, a.clone(), &[3, 5]);
+        let other = set_breakpoints(&mut session, json!({ "path": "/tmp/b.rs" }), &[3]);
+        assert!(!first.contains(&other[0]), "{first:?} {other:?}");
+
+        session.request("restart", json!({}));
+        let again = set_breakpoints(&mut session, a, &[3, 7]);
+        assert_eq!(again[0], first[0]);
+        assert!(![first[1], other[0]].contains(&again[1]), "{again:?}");
+    }
+
+    #[test]
+    fn breakpoints_by_source_reference_without_a_path() {
+        let mut session = Session::launched();
+        let source = json!({ "name": "generated", "sourceReference": 7 });
+        let response = session.response(
+            "setBreakpoints",
+            json!({ "source": source, "breakpoints": [{ "line": 2 }] }),
+        );
+        let bp = &response["body"]["breakpoints"][0];
+        assert_eq!(bp["verified"], true, "{response}");
+        assert_eq!(bp["source"]["sourceReference"], 7, "{response}");
+        assert_eq!(session.st.breakpoints_by_ref[&7].len(), 1);
+
+        let again = set_breakpoints(&mut session, source, &[2]);
+        assert_eq!(json!(again[0]), bp["id"]);
+    }
+
+    #[test]
+    fn restart_resets_the_debuggee_state() {
+        let mut session = paused();
+        let main = session.st.main_thread_id;
+        let frame_id = session.st.call_stack[0].id;
+        session.st.ensure_thread(42);
+
+        let messages = session.request("restart", json!({}));
+        let exited: Vec<&Value> = events(&messages, "thread")
+            .into_iter()
+            .filter(|m| m["body"]["reason"] == "exited")
+            .collect();
+        assert_eq!(exited.len(), 2, "{messages:?}");
+        assert!(session.st.is_thread_running(main));
+        assert!(!session.st.threads.contains_key(&42));
+        assert!(session.st.call_stack.is_empty());
+
+        let response = session.response("scopes", json!({ "frameId": frame_id }));
+        assert_eq!(response["success"], false, "{response}");
+    }
+
+    // Бэкенд со своим языком выражений: всё, что не переменная, — длина выражения
+    #[derive(Debug)]
+    struct LengthEvaluator;
+
+    impl crate::evaluator::ExpressionEvaluator for LengthEvaluator {
+        fn eval(&self, expr: &str, _frame_id: Option<i64>, _st: &DapState) -> DynResult<Value> {
+            Ok(json!(expr.len()))
+        }
+    }
+
+    #[test]
+    fn evaluate_goes_through_the_installed_evaluator() {
+        let mut session = paused();
+        let response = session.response("evaluate", json!({ "expression": "1 + 2" }));
+        assert_eq!(response["body"]["result"], "3", "{response}");
+
+        session.st.evaluator = Box::new(LengthEvaluator);
+        let response = session.response("evaluate", json!({ "expression": "1 + 2" }));
+        assert_eq!(response["body"]["result"], "5", "{response}");
+        // Переменные по-прежнему ищет адаптер
+        let response = session.response("evaluate", json!({ "expression": "demo" }));
+        assert_eq!(response["body"]["result"], "1", "{response}");
+    }
+
+    #[test]
+    fn adapter_data_comes_back_unchanged() {
+        let mut session = Session::launched();
+        let data = json!({ "build": [1, 2], "source_reference": "opaque" });
+        let response = session.response(
+            "setBreakpoints",
+            json!({
+                "source": { "path": "/tmp/adapter-data.rs", "adapterData": data },
+                "breakpoints": [{ "line": 1 }],
+            }),
+        );
+        assert_eq!(
+            response["body"]["breakpoints"][0]["source"]["adapterData"], data,
+            "{response}"
+        );
+
+        let thread_id = session.st.main_thread_id;
+        session.request("pause", json!({ "threadId": thread_id }));
+        let response = session.response("stackTrace", json!({ "threadId": thread_id }));
+        let frame = &response["body"]["stackFrames"][0];
+        assert_eq!(frame["source"]["adapterData"], data, "{response}");
+    }
+
+    #[test]
+    fn completions_use_the_configured_trigger_characters() {
+        let mut session = Session::new();
+        session.st.completion_trigger_characters = vec!["::".to_string()];
+        let response = session.response("initialize", json!({ "adapterID": "rast" }));
+        assert_eq!(
+            response["body"]["completionTriggerCharacters"],
+            json!(["::"])
+        );
+        session.request("launch", json!({ "program": "/tmp/demo" }));
+        session.request("configurationDone", Value::Null);
+        let thread_id = session.st.main_thread_id;
+        session.request("pause", json!({ "threadId": thread_id }));
+        let frame_id = session.st.call_stack[0].id;
+
+        let complete = |session: &mut Session, text: &str| {
+            let column = text.len() as i64 + 1;
+            let response = session.response(
+                "completions",
+                json!({ "text": text, "column": column, "frameId": frame_id }),
+            );
+            let targets = response["body"]["targets"].as_array().unwrap().clone();
+            targets
+                .iter()
+                .map(|t| t["label"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(complete(&mut session, "point::"), ["x", "y"]);
+        // "." теперь не триггер: подсказки — снова переменные фрейма, а не поля point
+        assert!(!complete(&mut session, "point.").contains(&"x".to_string()));
+    }
+
+    #[test]
+    fn evaluate_looks_up_names_in_the_requested_frame() {
+        let mut session = paused();
+        let main = session.st.call_stack[1].id;
+        let response = session.response(
+            "evaluate",
+            json!({ "expression": "total", "frameId": main }),
+        );
+        assert_eq!(response["body"]["result"], "12", "{response}");
+        let response =
+            session.response("evaluate", json!({ "expression": "demo", "frameId": main }));
+        assert_eq!(response["success"], false, "{response}");
+        let response = session.response("evaluate", json!({ "expression": "demo" }));
+        assert_eq!(response["body"]["result"], "1", "{response}");
+    }
+
+    fn names(variables: &Value) -> Vec<&str> {
+        variables
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["name"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn arguments_scope_comes_before_locals() {
+        let mut session = paused();
+        let frame_id = session.st.call_stack[0].id;
+        let response = session.response("scopes", json!({ "frameId": frame_id }));
+        let scopes = &response["body"]["scopes"];
+        assert_eq!(names(scopes), ["Arguments", "Locals"]);
+        assert_eq!(scopes[0]["presentationHint"], "arguments");
+        assert_eq!(scopes[0]["namedVariables"], 1);
+
+        let arguments = scopes[0]["variablesReference"].clone();
+        let response = session.response("variables", json!({ "variablesReference": arguments }));
+        assert_eq!(names(&response["body"]["variables"]), ["factor"]);
+    }
+
+    fn top_frame(session: &mut Session) -> Value {
+        let thread_id = session.st.main_thread_id;
+        let response = session.response("stackTrace", json!({ "threadId": thread_id }));
+        response["body"]["stackFrames"][0].clone()
+    }
+
+    #[test]
+    fn step_granularity_picks_instruction_or_line() {
+        let mut session = paused();
+        let thread_id = session.st.main_thread_id;
+        let line = top_frame(&mut session)["line"].as_i64().unwrap();
+
+        session.request(
+            "next",
+            json!({ "threadId": thread_id, "granularity": "instruction" }),
+        );
+        let frame = top_frame(&mut session);
+        assert_eq!(frame["line"], line);
+        assert_eq!(frame["instructionPointerReference"], "0x0000000000001044");
+
+        session.request(
+            "stepIn",
+            json!({ "threadId": thread_id, "granularity": "line" }),
+        );
+        assert_eq!(top_frame(&mut session)["line"], line + 1);
+        session.request("stepOut", json!({ "threadId": thread_id }));
+        assert_eq!(top_frame(&mut session)["line"], line + 2);
+    }
+
+    #[test]
+    fn tracing_does_not_change_the_protocol_stream() {
+        let script = [
+            ("initialize", json!({ "adapterID": "rast" })),
+            (
+                "launch",
+                json!({ "program": "/tmp/demo", "stopOnEntry": true }),
+            ),
+            ("configurationDone", Value::Null),
+            ("threads", Value::Null),
+            ("rast/reloadSymbols", Value::Null),
+        ];
+        let mut plain = Session::new();
+        let mut traced = Session::with_tracing(true);
+        for (command, arguments) in script {
+            let expected = plain.request(command, arguments.clone());
+            assert_eq!(traced.request(command, arguments), expected, "{command}");
+        }
+    }
+
+    #[test]
+    fn initialize_advertises_capabilities_from_the_state() {
+        let mut session = Session::new();
+        session.st.completion_trigger_characters = vec![":".to_string()];
+        let body = session.response("initialize", json!({ "adapterID": "rast" }))["body"].clone();
+        assert_eq!(body["completionTriggerCharacters"], json!([":"]), "{body}");
+        assert_eq!(body["supportsConfigurationDoneRequest"], true);
+
+        let mut session = Session::new();
+        session.st.completion_trigger_characters.clear();
+        let body = session.response("initialize", json!({ "adapterID": "rast" }))["body"].clone();
+        assert!(body.get("completionTriggerCharacters").is_none(), "{body}");
+    }
+
+    #[test]
+    fn module_events_follow_load_and_unload() {
+        let module_events = |messages: &[Value]| {
+            events(messages, "module")
+                .iter()
+                .map(|e| {
+                    let body = &e["body"];
+                    format!(
+                        "{} {}",
+                        body["reason"].as_str().unwrap(),
+                        body["module"]["id"]
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut session = Session::new();
+        session.request("initialize", json!({ "adapterID": "rast" }));
+        let launched = session.request("launch", json!({ "program": "/tmp/demo" }));
+        assert_eq!(module_events(&launched), ["new \"/tmp/demo\""]);
+
+        let restarted = session.request(
+            "restart",
+            json!({ "arguments": { "program": "/tmp/other" } }),
+        );
+        assert_eq!(
+            module_events(&restarted),
+            ["removed \"/tmp/demo\"", "new \"/tmp/other\""]
+        );
+        let modules = session.response("modules", json!({}));
+        assert_eq!(modules["body"]["modules"][0]["id"], "/tmp/other");
+        assert_eq!(modules["body"]["totalModules"], 1);
+
+        let disconnected = session.request("disconnect", json!({}));
+        assert_eq!(module_events(&disconnected), ["removed \"/tmp/other\""]);
+        assert!(session.st.modules.is_empty());
+    }
+
+    #[test]
+    fn ansi_codes_reach_only_a_client_that_styles_them() {
+        let logpoint_output = |ansi_styling: bool| {
+            let mut session = Session::launched();
+            session.st.ansi_styling = ansi_styling;
+            session.request(
+                "setBreakpoints",
+                json!({
+                    "source": { "path": "/tmp/main.rs" },
+                    "breakpoints": [{ "line": 3, "logMessage": "\u{1b}[31mred\u{1b}[0m" }],
+                }),
+            );
+            let thread_id = session.st.main_thread_id;
+            let messages = session.request("pause", json!({ "threadId": thread_id }));
+            events(&messages, "output")
+                .iter()
+                .map(|e| e["body"]["output"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(logpoint_output(false), ["red\n"]);
+        assert_eq!(logpoint_output(true), ["\u{1b}[31mred\u{1b}[0m\n"]);
+    }
+
+    #[test]
+    fn queued_output_goes_out_before_the_disconnect_response() {
+        let mut session = Session::launched();
+        console_output(&mut session.server, &session.st, "last words").unwrap();
+        let messages = session.request("disconnect", json!({}));
+        let kinds = kinds(&messages);
+        assert_eq!(kinds.last().unwrap(), "response", "{kinds:?}");
+        let output = events(&messages, "output");
+        assert_eq!(output.len(), 1, "{messages:?}");
+        assert_eq!(output[0]["body"]["output"], "last words\n");
+    }
+
+    // Подменить locals верхнего фрейма переменными с этими именами; вернуть их ссылку
+    fn replace_locals(session: &mut Session, names: &[&str]) -> i64 {
+        let locals = session.st.call_stack[0].locals_ref;
+        let vars = names
+            .iter()
+            .map(|name| dap::types::Variable {
+                name: name.to_string(),
+                value: "0".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        session.st.variables.insert(locals, vars);
+        locals
+    }
+
+    #[test]
+    fn sort_variables_orders_names_but_not_indices() {
+        let listed = |sort: bool, names_in: &[&str]| {
+            let mut session = paused();
+            session.st.sort_variables = sort;
+            let locals = replace_locals(&mut session, names_in);
+            let response = session.response("variables", json!({ "variablesReference": locals }));
+            names(&response["body"]["variables"])
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        let named = ["zeta", "__hidden", "Alpha", "beta"];
+        assert_eq!(listed(false, &named), named);
+        assert_eq!(listed(true, &named), ["Alpha", "beta", "zeta", "__hidden"]);
+        assert_eq!(listed(true, &["[1]", "[0]"]), ["[1]", "[0]"]);
+    }
+
+    #[test]
+    fn second_initialize_is_rejected_until_a_reconnect() {
+        let mut session = Session::launched();
+        let messages = session.request("initialize", json!({ "adapterID": "rast" }));
+        assert!(events(&messages, "initialized").is_empty(), "{messages:?}");
+        let response = response_in(&messages);
+        assert_eq!(response["success"], false);
+        assert_eq!(response["message"], "Debug adapter is already initialized");
+
+        session.request("disconnect", json!({ "restart": true }));
+        let messages = session.request("initialize", json!({ "adapterID": "rast" }));
+        assert_eq!(response_in(&messages)["success"], true);
+        assert_eq!(events(&messages, "initialized").len(), 1);
+    }
+
+    #[test]
+    fn frames_without_a_source_are_subtle_labels() {
+        let mut session = paused();
+        let frame = top_frame(&mut session);
+        assert!(frame.get("source").is_none(), "{frame}");
+        assert_eq!(frame["presentationHint"], "subtle");
+
+        set_breakpoints(&mut session, json!({ "path": "/tmp/main.rs" }), &[3]);
+        let thread_id = session.st.main_thread_id;
+        session.request("continue", json!({ "threadId": thread_id }));
+        session.request("pause", json!({ "threadId": thread_id }));
+        let frame = top_frame(&mut session);
+        assert_eq!(frame["source"]["path"], "/tmp/main.rs", "{frame}");
+        assert!(frame.get("presentationHint").is_none());
+    }
+
+    #[test]
+    fn thread_name_comes_from_the_launch_config() {
+        let thread_names = |launch: Value| {
+            let mut session = Session::new();
+            session.request("initialize", json!({ "adapterID": "rast" }));
+            session.request("launch", launch);
+            let response = session.response("threads", Value::Null);
+            response["body"]["threads"][0]["name"].clone()
+        };
+        assert_eq!(
+            thread_names(json!({ "program": "/tmp/demo", "threadName": "tokio-main" })),
+            "tokio-main"
+        );
+        assert_eq!(
+            thread_names(json!({ "program": "/tmp/demo" })),
+            "Main Thread"
+        );
+    }
+
+    #[test]
+    fn soft_unsupported_commands_get_an_empty_success() {
+        let mut session = Session::launched();
+        let response = session.response("loadedSources", json!({}));
+        assert_eq!(response["success"], true, "{response}");
+        assert_eq!(response["body"]["sources"], json!([]));
+
+        session.st.soft_unsupported.clear();
+        let response = session.response("loadedSources", json!({}));
+        assert_eq!(response["success"], false, "{response}");
+        assert_eq!(response["body"]["error"]["id"], 1004);
+    }
+
+    #[test]
+    fn pausing_a_stopped_thread_only_acknowledges() {
+        let mut session = paused();
+        let thread_id = session.st.main_thread_id;
+        let messages = session.request("pause", json!({ "threadId": thread_id }));
+        assert_eq!(kinds(&messages), ["response"]);
+        assert_eq!(response_in(&messages)["success"], true);
+
+        session.request("continue", json!({ "threadId": thread_id }));
+        let messages = session.request("pause", json!({ "threadId": thread_id }));
+        assert_eq!(events(&messages, "stopped").len(), 1, "{messages:?}");
+    }
+
+    #[test]
+    fn hit_conditions_re_arm_after_restart() {
+        // pause из работы и снова continue: Stopped несёт сработавшие брейки
+        let stop = |session: &mut Session| {
+            let thread_id = session.st.main_thread_id;
+            let messages = session.request("pause", json!({ "threadId": thread_id }));
+            session.request("continue", json!({ "threadId": thread_id }));
+            events(&messages, "stopped")[0]["body"]["hitBreakpointIds"].clone()
+        };
+        let mut session = Session::launched();
+        let response = session.response(
+            "setBreakpoints",
+            json!({
+                "source": { "path": "/tmp/main.rs" },
+                "breakpoints": [{ "line": 3, "hitCondition": "== 2" }],
+            }),
+        );
+        let id = response["body"]["breakpoints"][0]["id"].clone();
+        assert_eq!(stop(&mut session), Value::Null);
+        assert_eq!(stop(&mut session), json!([id]));
+        assert_eq!(stop(&mut session), Value::Null);
+
+        session.request("restart", json!({}));
+        assert_eq!(stop(&mut session), Value::Null);
+        assert_eq!(stop(&mut session), json!([id]));
+    }
+
+    #[test]
+    fn a_new_thread_shows_up_in_the_next_threads_request() {
+        let mut session = Session::launched();
+        let thread_ids = |session: &mut Session| {
+            let response = session.response("threads", Value::Null);
+            response["body"]["threads"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|t| t["id"].as_i64().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let main = session.st.main_thread_id;
+        assert_eq!(thread_ids(&mut session), [main]);
+        session.request("pause", json!({ "threadId": 7 }));
+        assert!(thread_ids(&mut session).contains(&7));
+    }
+
+    #[test]
+    fn logpoint_output_links_to_its_line() {
+        let mut session = Session::launched();
+        session.request(
+            "setBreakpoints",
+            json!({
+                "source": { "path": "/tmp/main.rs" },
+                "breakpoints": [{ "line": 3, "logMessage": "here" }],
+            }),
+        );
+        let thread_id = session.st.main_thread_id;
+        let messages = session.request("pause", json!({ "threadId": thread_id }));
+        let output = &events(&messages, "output")[0]["body"];
+        assert_eq!(output["output"], "here\n");
+        assert_eq!(output["source"]["path"], "/tmp/main.rs", "{output}");
+        assert_eq!(output["line"], 3);
+        assert_eq!(output["column"], 1);
+    }
+
+    #[test]
+    fn a_panicking_handler_answers_with_an_error_and_the_session_goes_on() {
+        fn explode(_: Option<&Value>, _: &mut DapServer, _: &mut DapState) -> HandlerResult {
+            panic!("boom")
+        }
+        let mut session = Session::launched();
+        session.st.custom_commands.insert("test/explode", explode);
+        let err = session.dispatch("test/explode", Value::Null).unwrap_err();
+        assert_eq!(err.to_string(), "Handler for 'test/explode' panicked: boom");
+        let response = response_in(&session.sent());
+        assert_eq!(response["success"], false);
+        assert_eq!(response["command"], "test/explode");
+        assert_eq!(
+            response["message"],
+            "Internal error in 'test/explode': boom"
+        );
+
+        let response = session.response("threads", Value::Null);
+        assert_eq!(response["success"], true, "{response}");
+    }
+
+    #[test]
+    fn function_breakpoints_are_verified_by_known_symbols() {
+        let mut session = Session::launched();
+        let response = session.response(
+            "setFunctionBreakpoints",
+            json!({ "breakpoints": [{ "name": "compute" }, { "name": "nope" }] }),
+        );
+        let breakpoints = &response["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true, "{response}");
+        assert!(breakpoints[0].get("message").is_none());
+        assert_eq!(breakpoints[1]["verified"], false);
+        assert_eq!(breakpoints[1]["message"], "Function 'nope' not found");
+
+        // До launch символов нет. Потом символы пропали (пересборка), и
+        // reloadSymbols находит их заново — брейк становится verified событием
+        let mut session = Session::new();
+        session.request("initialize", json!({ "adapterID": "rast" }));
+        let response = session.response(
+            "setFunctionBreakpoints",
+            json!({ "breakpoints": [{ "name": "compute" }] }),
+        );
+        assert_eq!(response["body"]["breakpoints"][0]["verified"], false);
+        session.request("launch", json!({ "program": "/tmp/demo" }));
+        session.st.symbols.clear();
+        let messages = session.request("rast/reloadSymbols", Value::Null);
+        let changed = events(&messages, "breakpoint");
+        assert_eq!(changed.len(), 1, "{messages:?}");
+        assert_eq!(changed[0]["body"]["reason"], "changed");
+        assert_eq!(changed[0]["body"]["breakpoint"]["verified"], true);
+    }
+
+    #[test]
+    fn variables_filter_splits_named_and_indexed() {
+        let mut session = paused();
+        let locals = replace_locals(&mut session, &["len", "[0]", "[1]", "cap"]);
+        let listed = |session: &mut Session, filter: Value| {
+            let response = session.response(
+                "variables",
+                json!({ "variablesReference": locals, "filter": filter }),
+            );
+            names(&response["body"]["variables"])
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(listed(&mut session, json!("named")), ["len", "cap"]);
+        assert_eq!(listed(&mut session, json!("indexed")), ["[0]", "[1]"]);
+        assert_eq!(listed(&mut session, Value::Null).len(), 4);
+    }
+
+    #[test]
+    fn path_mappings_translate_between_client_and_debuggee() {
+        let mut session = Session::new();
+        session.request("initialize", json!({ "adapterID": "rast" }));
+        session.request(
+            "launch",
+            json!({ "program": "/tmp/demo", "pathMappings": { "/app": "/home/me/proj" } }),
+        );
+        session.request("configurationDone", Value::Null);
+        set_breakpoints(
+            &mut session,
+            json!({ "path": "/home/me/proj/main.rs" }),
+            &[3],
+        );
+        let thread_id = session.st.main_thread_id;
+        session.request("pause", json!({ "threadId": thread_id }));
+
+        let debuggee = session.st.current_source.clone().unwrap();
+        assert_eq!(debuggee.path.as_deref(), Some("/app/main.rs"));
+        let frame = top_frame(&mut session);
+        assert_eq!(frame["source"]["path"], "/home/me/proj/main.rs", "{frame}");
+    }
+
+    #[test]
+    fn differently_spelled_paths_share_breakpoints_where_the_platform_does() {
+        let mut session = Session::launched();
+        set_breakpoints(&mut session, json!({ "path": r"C:\Src\Main.rs" }), &[3]);
+        set_breakpoints(&mut session, json!({ "path": r"c:\src\main.rs" }), &[5]);
+        let files = session.st.breakpoints_by_path.len();
+        let case_insensitive = cfg!(any(windows, target_os = "macos"));
+        assert_eq!(files, if case_insensitive { 1 } else { 2 });
+
+        // В ответах — путь так, как его прислал клиент
+        let thread_id = session.st.main_thread_id;
+        session.request("pause", json!({ "threadId": thread_id }));
+        assert_eq!(top_frame(&mut session)["source"]["path"], r"c:\src\main.rs");
+    }
+
+    #[test]
+    fn sym_searches_the_symbol_table() {
+        let mut session = Session::launched();
+        session.st.symbols = [
+            ("parse_args", 0x2000),
+            ("Parser::new", 0x2040),
+            ("main", 0x1000),
+        ]
+        .into_iter()
+        .map(|(name, address)| {
+            let module = "libdemo.so".to_string();
+            (name.to_string(), crate::state::Symbol { address, module })
+        })
+        .collect();
+        let evaluate = |session: &mut Session, expression: &str| {
+            session.response(
+                "evaluate",
+                json!({ "expression": expression, "context": "repl" }),
+            )
+        };
+        let response = evaluate(&mut session, "$sym pars");
+        assert_eq!(
+            response["body"]["result"],
+            "Parser::new  0x0000000000002040  (libdemo.so)\n\
+             parse_args  0x0000000000002000  (libdemo.so)"
+        );
+        let response = evaluate(&mut session, "$sym nothing");
+        assert_eq!(response["body"]["result"], "No symbols matching 'nothing'");
+        let response = evaluate(&mut session, "$sym");
+        assert_eq!(response["success"], false);
+        assert_eq!(response["message"], "Usage: $sym <name>");
+    }
+
+    #[test]
+    fn a_closed_output_is_reported_as_a_broken_pipe() {
+        let mut session = Session::launched();
+        assert_eq!(session.response("threads", Value::Null)["success"], true);
+        session.wire.closed.store(true, Ordering::SeqCst);
+        let err = session.dispatch("threads", Value::Null).unwrap_err();
+        assert!(is_broken_pipe(err.as_ref()), "{err}");
+    }
+
+    #[test]
+    fn disconnect_for_restart_keeps_the_session_normal_disconnect_ends_it() {
+        let mut session = Session::launched();
+        set_breakpoints(&mut session, json!({ "path": "/tmp/main.rs" }), &[3]);
+        let messages = session.request("disconnect", json!({ "restart": true }));
+        assert!(events(&messages, "thread").is_empty(), "{messages:?}");
+        assert!(!session.st.disconnected);
+        assert!(session.st.launch_config.is_some());
+        assert_eq!(session.st.breakpoints_by_path.len(), 1);
+
+        let mut session = Session::launched();
+        set_breakpoints(&mut session, json!({ "path": "/tmp/main.rs" }), &[3]);
+        let messages = session.request("disconnect", json!({}));
+        assert_eq!(events(&messages, "thread")[0]["body"]["reason"], "exited");
+        assert!(session.st.disconnected);
+        assert!(session.st.launch_config.is_none());
+        assert!(session.st.breakpoints_by_path.is_empty());
+    }
+
+    #[test]
+    fn stack_trace_serializes_every_frame_and_pages() {
+        let mut session = paused();
+        let thread_id = session.st.main_thread_id;
+        let response = session.response("stackTrace", json!({ "threadId": thread_id }));
+        let frames = &response["body"]["stackFrames"];
+        assert_eq!(names(frames), ["compute", "main"], "{response}");
+        assert_eq!(frames[0]["id"], session.st.call_stack[0].id);
+        assert_eq!(frames[1]["id"], session.st.call_stack[1].id);
+        assert_ne!(frames[0]["id"], frames[1]["id"]);
+        assert_eq!(response["body"]["totalFrames"], 2);
+
+        let response = session.response(
+            "stackTrace",
+            json!({ "threadId": thread_id, "startFrame": 1, "levels": 1 }),
+        );
+        assert_eq!(names(&response["body"]["stackFrames"]), ["main"]);
+        assert_eq!(response["body"]["totalFrames"], 2);
+    }
+
+    #[test]
+    fn thread_ids_outside_the_thread_range_are_rejected() {
+        let mut session = paused();
+        let frame_id = session.st.call_stack[0].id;
+        for command in ["pause", "continue", "next"] {
+            let response = session.response(command, json!({ "threadId": frame_id }));
+            assert_eq!(response["success"], false, "{command}: {response}");
+            assert_eq!(response["message"], format!("Unknown threadId {frame_id}"));
+        }
+        assert!(!session.st.threads.contains_key(&frame_id));
+    }
+
+    #[test]
+    fn frame_ids_are_never_reused_across_stops() {
+        let mut session = paused();
+        let thread_id = session.st.main_thread_id;
+        let first = session.st.call_stack[0].id;
+        let mut seen = std::collections::HashSet::from([first]);
+        for _ in 0..100 {
+            session.request("continue", json!({ "threadId": thread_id }));
+            session.request("pause", json!({ "threadId": thread_id }));
+            assert!(seen.insert(session.st.call_stack[0].id));
+        }
+        let response = session.response("scopes", json!({ "frameId": first }));
+        assert_eq!(response["success"], false, "{response}");
+    }
+
+    #[test]
+    fn no_two_kinds_of_id_coincide() {
+        let mut session = Session::launched();
+        let breakpoints = set_breakpoints(&mut session, json!({ "path": "/tmp/main.rs" }), &[3, 5]);
+        let thread_id = session.st.main_thread_id;
+        session.request("pause", json!({ "threadId": thread_id }));
+        let kinds = [
+            breakpoints,
+            vec![thread_id],
+            session.st.call_stack.iter().map(|f| f.id).collect(),
+            session.st.ref_frames.keys().copied().collect(),
+        ];
+        for (i, a) in kinds.iter().enumerate() {
+            for b in &kinds[i + 1..] {
+                assert!(a.iter().all(|id| !b.contains(id)), "{a:?} {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn user_pause_stops_all_threads_with_a_description() {
+        let mut session = Session::launched();
+        let thread_id = session.st.main_thread_id;
+        let messages = session.request("pause", json!({ "threadId": thread_id }));
+        let stopped = events(&messages, "stopped");
+        assert_eq!(stopped.len(), 1, "{messages:?}");
+        let body = &stopped[0]["body"];
+        assert_eq!(body["reason"], "pause");
+        assert_eq!(body["description"], "Paused by user");
+        assert_eq!(body["threadId"], thread_id);
+        assert_eq!(body["allThreadsStopped"], true);
+    }
+
+    #[test]
+    fn state_dump_is_only_logged_at_trace() {
+        let dumps = |level: LogLevel| {
+            let mut session = Session::new();
+            session.st.log_level = level;
+            let messages = session.request("initialize", json!({ "adapterID": "rast" }));
+            events(&messages, "output")
+                .iter()
+                .filter(|e| {
+                    e["body"]["output"]
+                        .as_str()
+                        .unwrap()
+                        .starts_with("DAP STATE")
+                })
+                .count()
+        };
+        assert_eq!(dumps(crate::cli::CliOptions::default().log_level), 0);
+        assert_eq!(dumps(LogLevel::Trace), 1);
+    }
+
+    #[test]
+    fn state_debug_is_a_compact_summary() {
+        let mut session = Session::launched();
+        set_breakpoints(&mut session, json!({ "path": "/tmp/main.rs" }), &[3, 5]);
+        let thread_id = session.st.main_thread_id;
+        session.request("pause", json!({ "threadId": thread_id }));
+        let compact = format!("{:?}", session.st);
+        for part in [
+            "phase: Running",
+            "threads: 1",
+            "breakpoints: 2 across 1 files",
+            "stopped at /tmp/main.rs:3:1",
+            "frames: 2",
+        ] {
+            assert!(compact.contains(part), "{part}: {compact}");
+        }
+        assert!(!compact.contains("breakpoints_by_path"));
+        assert!(session.st.verbose_debug().contains("breakpoints_by_path"));
+    }
+
+    #[test]
+    fn hover_answers_identifiers_and_refuses_calls() {
+        let mut session = paused();
+        let hover = |session: &mut Session, expression: &str| {
+            session.response(
+                "evaluate",
+                json!({ "expression": expression, "context": "hover" }),
+            )
+        };
+        let response = hover(&mut session, "demo");
+        assert_eq!(response["success"], true, "{response}");
+        assert_eq!(response["body"]["result"], "1");
+        let response = hover(&mut session, "compute()");
+        assert_eq!(response["success"], false);
+        assert_eq!(
+            response["message"],
+            "Hover evaluation skipped: expression may have side effects"
+        );
+    }
+
+    #[test]
+    fn stack_frame_names_follow_the_requested_format() {
+        let mut session = paused();
+        let thread_id = session.st.main_thread_id;
+        let mut name = |format: Value| {
+            let response = session.response(
+                "stackTrace",
+                json!({ "threadId": thread_id, "format": format }),
+            );
+            response["body"]["stackFrames"][0]["name"].clone()
+        };
+        assert_eq!(name(json!({})), "compute");
+        assert_eq!(name(json!({ "parameters": true })), "compute(factor = 4)");
+        assert_eq!(
+            name(json!({ "parameters": true, "parameterTypes": true })),
+            "compute(factor: i32 = 4)"
+        );
+        assert_eq!(
+            name(json!({ "parameters": true, "parameterNames": false, "parameterTypes": true })),
+            "compute(i32 = 4)"
+        );
+        assert_eq!(
+            name(json!({ "parameters": true, "parameterValues": false })),
+            "compute(factor)"
+        );
+        assert_eq!(name(json!({ "line": true })), "compute Line 1");
+        assert_eq!(name(json!({ "module": true })), "demo!compute");
+        assert_eq!(
+            name(json!({ "parameters": true, "line": true, "module": true })),
+            "demo!compute(factor = 4) Line 1"
+        );
+    }
+
+    #[test]
+    fn disconnect_traces_a_session_summary_only_when_tracing() {
+        let trace_summary = |tracing: bool| {
+            let mut session = Session::with_tracing(tracing);
+            session.request("initialize", json!({ "adapterID": "rast" }));
+            session.request("launch", json!({ "program": "/tmp/demo" }));
+            session.request("configurationDone", Value::Null);
+            set_breakpoints(&mut session, json!({ "path": "/tmp/main.rs" }), &[3]);
+            let thread_id = session.st.main_thread_id;
+            session.request("pause", json!({ "threadId": thread_id }));
+            take_captured();
+            session.request("disconnect", json!({}));
+            take_captured()
+                .into_iter()
+                .find(|line| line.contains("Session summary"))
+        };
+        let summary = trace_summary(true).expect("no summary in the trace log");
+        assert!(summary.contains("launch: 1 calls"), "{summary}");
+        assert!(
+            summary.contains("breakpoint /tmp/main.rs:3: 1 hits"),
+            "{summary}"
+        );
+        assert!(trace_summary(false).is_none());
+    }
+
+    #[test]
+    fn restart_is_advertised_and_needs_a_launch() {
+        let mut session = Session::new();
+        let response = session.response("initialize", json!({ "adapterID": "rast" }));
+        assert_eq!(response["body"]["supportsRestartRequest"], true);
+
+        let response = session.response("restart", json!({}));
+        assert_eq!(response["success"], false);
+        assert_eq!(
+            response["message"],
+            "'restart' received before launch or attach"
+        );
+
+        session.request("launch", json!({ "program": "/tmp/demo" }));
+        session.request("configurationDone", Value::Null);
+        assert_eq!(session.response("restart", json!({}))["success"], true);
+    }
+
+    #[test]
+    fn only_name_based_data_breakpoints_persist() {
+        let mut session = paused();
+        let frame_id = top_frame(&mut session)["id"].clone();
+        let info = |session: &mut Session, name: &str| {
+            let response = session.response(
+                "dataBreakpointInfo",
+                json!({ "name": name, "frameId": frame_id }),
+            );
+            response["body"].clone()
+        };
+        let by_name = info(&mut session, "demo");
+        assert_eq!(by_name["dataId"], "var:demo");
+        assert_eq!(by_name["canPersist"], true);
+        let by_address = info(&mut session, "0x1f");
+        assert_eq!(by_address["dataId"], "addr:0x000000000000001f");
+        assert_eq!(by_address["canPersist"], false);
+        assert!(info(&mut session, "missing")["dataId"].is_null());
+
+        session.request(
+            "setDataBreakpoints",
+            json!({ "breakpoints": [
+                { "dataId": by_name["dataId"] },
+                { "dataId": by_address["dataId"] },
+            ] }),
+        );
+        session.request("restart", json!({}));
+        let kept: Vec<&str> = session
+            .st
+            .data_breakpoints
+            .iter()
+            .map(|bp| bp.data_id.as_str())
+            .collect();
+        assert_eq!(kept, ["var:demo"]);
+    }
+
+    #[test]
+    fn state_in_the_repl_is_a_short_summary() {
+        let mut session = paused();
+        set_breakpoints(&mut session, json!({ "path": "/tmp/main.rs" }), &[3, 7]);
+        let evaluate = |session: &mut Session, expression: &str| {
+            let response = session.response(
+                "evaluate",
+                json!({ "expression": expression, "context": "repl" }),
+            );
+            response["body"]["result"].as_str().unwrap().to_string()
+        };
+        let summary = evaluate(&mut session, "$state");
+        assert!(
+            summary.starts_with("Phase: Running (configured)"),
+            "{summary}"
+        );
+        assert!(summary.contains("Stopped in compute"), "{summary}");
+        assert!(
+            summary.contains("Breakpoints in 1 files:\n  /tmp/main.rs: 2"),
+            "{summary}"
+        );
+        assert!(!summary.contains("DapState {"), "{summary}");
+        assert!(evaluate(&mut session, "$state full").starts_with("DapState {"));
+    }
+
+    // Сессия с debug-логом: что disconnect сделал с debuggee, видно по output
+    fn disconnect_in(start: &str, args: Value) -> Vec<Value> {
+        let mut session = Session::new();
+        let response = session.response("initialize", json!({ "adapterID": "rast" }));
+        assert_eq!(response["body"]["supportTerminateDebuggee"], true);
+        session.request(start, json!({ "program": "/tmp/demo" }));
+        session.request("configurationDone", Value::Null);
+        session.st.log_level = LogLevel::Debug;
+        session.request("disconnect", args)
+    }
+
+    fn logged(messages: &[Value], text: &str) -> bool {
+        events(messages, "output")
+            .iter()
+            .any(|e| e["body"]["output"].as_str().unwrap_or("").contains(text))
+    }
+
+    #[test]
+    fn disconnect_terminates_a_launch_and_detaches_from_an_attach() {
+        let messages = disconnect_in("launch", json!({}));
+        assert!(logged(&messages, "Terminating the debuggee"));
+        assert!(!events(&messages, "thread").is_empty());
+
+        for (start, args) in [
+            ("attach", json!({})),
+            ("launch", json!({ "terminateDebuggee": false })),
+        ] {
+            let messages = disconnect_in(start, args);
+            assert!(logged(&messages, "the debuggee keeps running"), "{start}");
+            assert!(events(&messages, "thread").is_empty(), "{start}");
+        }
+
+        let messages = disconnect_in("attach", json!({ "terminateDebuggee": true }));
+        assert!(logged(&messages, "Terminating the debuggee"));
+    }
+
+    #[test]
+    fn suspend_debuggee_detaches_without_resuming() {
+        let mut session = Session::new();
+        let response = session.response("initialize", json!({ "adapterID": "rast" }));
+        assert_eq!(response["body"]["supportSuspendDebuggee"], true);
+
+        let args = json!({ "terminateDebuggee": false, "suspendDebuggee": true });
+        let messages = disconnect_in("launch", args);
+        assert!(logged(&messages, "the debuggee stays suspended"));
+        assert!(!logged(&messages, "keeps running"));
+        assert!(events(&messages, "thread").is_empty());
+
+        // Убить важнее, чем приостановить
+        let args = json!({ "terminateDebuggee": true, "suspendDebuggee": true });
+        assert!(logged(
+            &disconnect_in("launch", args),
+            "Terminating the debuggee"
+        ));
+    }
+
+    #[test]
+    fn success_and_error_responses_both_close_the_request() {
+        let mut session = Session::new();
+        session.request("initialize", json!({ "adapterID": "rast" }));
+        session.st.log_level = LogLevel::Trace;
+        for (command, success) in [("threads", true), ("restart", false)] {
+            let messages = session.request(command, json!({}));
+            assert!(session.st.answered, "{command}");
+            let responses = kinds(&messages)
+                .iter()
+                .filter(|kind| kind.as_str() == "response")
+                .count();
+            assert_eq!(responses, 1, "{command}");
+            assert_eq!(response_in(&messages)["success"], success);
+            let seq = response_in(&messages)["request_seq"].clone();
+            assert!(logged(
+                &messages,
+                &format!("Responding to '{command}' (seq {seq})")
+            ));
+            assert!(!logged(&messages, "Second response"));
+            assert!(!logged(&messages, "finished without a response"));
+        }
+    }
+
+    #[test]
+    fn frames_carry_their_program_counter() {
+        let mut session = paused();
+        let response = session.response(
+            "stackTrace",
+            json!({ "threadId": session.st.main_thread_id }),
+        );
+        let frames = response["body"]["stackFrames"].as_array().unwrap().clone();
+        let pcs: Vec<&Value> = frames
+            .iter()
+            .map(|f| &f["instructionPointerReference"])
+            .collect();
+        let top = format!(
+            "0x{:016x}",
+            0x1040 + session.st.stopped_instruction as u64 * 4
+        );
+        assert_eq!(pcs, [&json!(top), &json!("0x0000000000001010")]);
+    }
+
+    #[test]
+    fn frames_link_to_a_registered_module() {
+        let mut session = paused();
+        let response = session.response("modules", json!({}));
+        let module = response["body"]["modules"][0].clone();
+        assert_eq!(module["name"], "demo");
+        let frame = top_frame(&mut session);
+        assert_eq!(frame["moduleId"], module["id"], "{frame}");
+
+        // Модуль, о котором клиент не слышал, не упоминаем
+        session.st.modules.clear();
+        assert!(top_frame(&mut session).get("moduleId").is_none());
+    }
+
+    #[test]
+    fn initialize_telemetry_reports_the_adapter_id() {
+        let mut session = Session::new();
+        session.st.telemetry = true;
+        session.st.adapter_id = "custom-rast".to_string();
+        let messages = session.request(
+            "initialize",
+            json!({ "adapterID": "rast", "supportsTelemetry": true }),
+        );
+        let telemetry: Vec<&Value> = events(&messages, "output")
+            .into_iter()
+            .filter(|e| e["body"]["category"] == "telemetry")
+            .collect();
+        assert_eq!(telemetry.len(), 1, "{messages:?}");
+        assert_eq!(telemetry[0]["body"]["output"], "initialize");
+        assert_eq!(telemetry[0]["body"]["data"]["adapterId"], "custom-rast");
+        assert_eq!(telemetry[0]["body"]["data"]["clientAdapterId"], "rast");
+    }
+
+    fn telemetry(messages: &[Value]) -> Vec<String> {
+        events(messages, "output")
+            .iter()
+            .filter(|e| e["body"]["category"] == "telemetry")
+            .map(|e| e["body"]["output"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    // Вся сессия целиком: какие telemetry-события клиент получил
+    fn session_telemetry(initialize: Value, enabled: bool) -> Vec<String> {
+        let mut session = Session::new();
+        session.st.telemetry = enabled;
+        let mut sent = session.request("initialize", initialize);
+        sent.extend(session.request("launch", json!({ "program": "/tmp/demo" })));
+        sent.extend(session.request("configurationDone", Value::Null));
+        let thread_id = session.st.main_thread_id;
+        sent.extend(session.request("pause", json!({ "threadId": thread_id })));
+        sent.extend(session.request("disconnect", json!({})));
+        telemetry(&sent)
+    }
+
+    #[test]
+    fn telemetry_goes_only_to_clients_that_ask_for_it() {
+        let asks = json!({ "adapterID": "rast", "supportsTelemetry": true });
+        assert_eq!(
+            session_telemetry(asks.clone(), true),
+            ["initialize", "launch", "firstStop", "disconnect"]
+        );
+        assert!(session_telemetry(asks, false).is_empty());
+        assert!(session_telemetry(json!({ "adapterID": "rast" }), true).is_empty());
+        let declines = json!({ "adapterID": "rast", "supportsTelemetry": false });
+        assert!(session_telemetry(declines, true).is_empty());
+    }
+
+    #[test]
+    fn crlf_sources_index_lines_and_serve_the_original_bytes() {
+        let path = std::env::temp_dir().join(format!("rast-dap-{}-crlf.rs", std::process::id()));
+        std::fs::write(&path, "a();\r\nb();\r\nc();\r\n").unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let mut session = Session::launched();
+        let response = session.response(
+            "setBreakpoints",
+            json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }, { "line": 4 }] }),
+        );
+        let verified: Vec<&Value> = response["body"]["breakpoints"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bp| &bp["verified"])
+            .collect();
+        assert_eq!(verified, [true, false]);
+
+        let source = |session: &mut Session| {
+            let response = session.response(
+                "source",
+                json!({ "source": { "path": path }, "sourceReference": 0 }),
+            );
+            response["body"]["content"].clone()
+        };
+        assert_eq!(source(&mut session), "a();\r\nb();\r\nc();\r\n");
+        session.st.line_endings = crate::source_text::LineEndings::Lf;
+        assert_eq!(source(&mut session), "a();\nb();\nc();\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn launch_without_a_program_is_an_error() {
+        for launch in [json!({}), json!({ "program": "  " })] {
+            let mut session = Session::new();
+            session.request("initialize", json!({ "adapterID": "rast" }));
+            let messages = session.request("launch", launch.clone());
+            let response = response_in(&messages);
+            assert_eq!(response["success"], false, "{launch}");
+            let error = &response["body"]["error"];
+            assert_eq!(error["variables"]["field"], "program", "{error}");
+            assert!(logged(
+                &messages,
+                "set \"program\" in the launch configuration"
+            ));
+            assert_ne!(session.st.phase, SessionPhase::Running);
+        }
+    }
+
+    // setVariable на point.x в compute: что пришло клиенту
+    fn set_point_x(supports_invalidated: bool) -> (Vec<Value>, Value) {
+        let mut session = Session::new();
+        session.request(
+            "initialize",
+            json!({ "adapterID": "rast", "supportsInvalidatedEvent": supports_invalidated }),
+        );
+        session.request("launch", json!({ "program": "/tmp/demo" }));
+        session.request("configurationDone", Value::Null);
+        let thread_id = session.st.main_thread_id;
+        session.request("pause", json!({ "threadId": thread_id }));
+        let frame_id = top_frame(&mut session)["id"].clone();
+        let scopes = session.response("scopes", json!({ "frameId": frame_id }));
+        let locals = scopes["body"]["scopes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|s| s["name"] == "Locals")
+            .unwrap()["variablesReference"]
+            .clone();
+        let vars = session.response("variables", json!({ "variablesReference": locals }));
+        let point = vars["body"]["variables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|v| v["name"] == "point")
+            .unwrap()["variablesReference"]
+            .clone();
+        let messages = session.request(
+            "setVariable",
+            json!({ "variablesReference": point, "name": "x", "value": "5" }),
+        );
+        (messages, frame_id)
+    }
+
+    #[test]
+    fn set_variable_invalidates_the_whole_frame() {
+        let (messages, frame_id) = set_point_x(true);
+        assert_eq!(response_in(&messages)["success"], true, "{messages:?}");
+        let invalidated = events(&messages, "invalidated");
+        assert_eq!(invalidated.len(), 1, "{messages:?}");
+        assert_eq!(invalidated[0]["body"]["stackFrameId"], frame_id);
+        assert_eq!(invalidated[0]["body"]["areas"], json!(["variables"]));
+
+        let (messages, _) = set_point_x(false);
+        assert!(events(&messages, "invalidated").is_empty());
+    }
+
+    #[test]
+    fn rapid_threads_requests_reuse_the_list_within_the_ttl() {
+        let reused = |ttl: std::time::Duration| {
+            let mut session = Session::launched();
+            session.st.threads_cache_ttl = ttl;
+            session.st.log_level = LogLevel::Debug;
+            [(); 2].map(|_| {
+                logged(
+                    &session.request("threads", Value::Null),
+                    "reusing the cached list",
+                )
+            })
+        };
+        assert_eq!(reused(std::time::Duration::from_secs(60)), [false, true]);
+        assert_eq!(reused(std::time::Duration::ZERO), [false, false]);
+    }
+
+    #[test]
+    fn source_content_is_cached_until_the_file_changes() {
+        let path = std::env::temp_dir().join(format!("rast-dap-{}-cached.rs", std::process::id()));
+        std::fs::write(&path, "a();\n").unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        let name = path.to_string_lossy().into_owned();
+        let mut session = Session::launched();
+        let mut source = || {
+            let args = json!({ "source": { "path": name }, "sourceReference": 0 });
+            session.response("source", args)["body"]["content"].clone()
+        };
+        assert_eq!(source(), "a();\n");
+
+        // Тот же отпечаток (mtime и размер) — отдаём закэшированное, файл не читаем
+        std::fs::write(&path, "b();\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        assert_eq!(source(), "a();\n");
+
+        std::fs::write(&path, "b();\nc();\n").unwrap();
+        assert_eq!(source(), "b();\nc();\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // Переменная с детьми: ссылка и те, кто под ней
+    fn node(name: &str, reference: i64) -> dap::types::Variable {
+        dap::types::Variable {
+            name: name.to_string(),
+            value: "Node".to_string(),
+            variables_reference: reference,
+            ..Default::default()
+        }
+    }
+
+    fn clipboard(session: &mut Session, expression: &str) -> String {
+        let frame_id = top_frame(session)["id"].clone();
+        let response = session.response(
+            "evaluate",
+            json!({ "expression": expression, "frameId": frame_id, "context": "clipboard" }),
+        );
+        response["body"]["result"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn expansion_stops_at_the_max_variable_depth() {
+        let mut session = paused();
+        session.st.max_variable_depth = 1;
+        let locals = session.st.call_stack[0].locals_ref;
+        let point = session.st.variables[&locals]
+            .iter()
+            .find(|v| v.name == "point")
+            .unwrap()
+            .variables_reference;
+        let response = session.response("variables", json!({ "variablesReference": point }));
+        let vars = &response["body"]["variables"];
+        assert_eq!(names(vars), ["…"]);
+        assert_eq!(vars[0]["value"], "… (max depth reached)");
+
+        // Цепочка глубже предела: раскрытие для clipboard обрывается, а не идёт до конца
+        session.st.max_variable_depth = 3;
+        let refs: Vec<i64> = (0..10).map(|_| session.st.alloc_vars_ref()).collect();
+        for pair in refs.windows(2) {
+            session
+                .st
+                .variables
+                .insert(pair[0], vec![node("next", pair[1])]);
+        }
+        session.st.variables.insert(refs[9], vec![node("leaf", 0)]);
+        session
+            .st
+            .variables
+            .insert(locals, vec![node("list", refs[0])]);
+        assert_eq!(
+            clipboard(&mut session, "list"),
+            "{ next: { next: { next: … (max depth reached) } } }"
+        );
+    }
+
+    #[test]
+    fn reference_cycles_become_back_reference_placeholders() {
+        let mut session = paused();
+        let locals = session.st.call_stack[0].locals_ref;
+        let frame_id = session.st.call_stack[0].id;
+        // node.next указывает обратно на node
+        let cyclic = session.st.alloc_vars_ref();
+        session
+            .st
+            .variables
+            .insert(cyclic, vec![node("value", 0), node("next", cyclic)]);
+        session
+            .st
+            .variables
+            .insert(locals, vec![node("node", cyclic)]);
+        session.st.ref_frames.insert(
+            cyclic,
+            crate::state::RefOrigin {
+                frame_id,
+                depth: 1,
+                parent: Some(locals),
+            },
+        );
+
+        let response = session.response("variables", json!({ "variablesReference": cyclic }));
+        let next = &response["body"]["variables"][1];
+        assert_eq!(next["name"], "next");
+        assert_eq!(next["value"], "↺ (cycle)");
+        assert_eq!(next["variablesReference"], 0);
+
+        assert_eq!(
+            clipboard(&mut session, "node"),
+            "{ value: Node, next: ↺ (cycle) }"
+        );
+    }
+
+    #[test]
+    fn restarting_an_attach_session_reattaches() {
+        let restart = |start: &str| {
+            let mut session = Session::new();
+            session.request("initialize", json!({ "adapterID": "rast" }));
+            session.request(
+                start,
+                json!({ "program": "/tmp/demo", "threadName": "worker" }),
+            );
+            session.request("configurationDone", Value::Null);
+            session.st.ensure_thread(42);
+            session.st.log_level = LogLevel::Debug;
+            let messages = session.request("restart", json!({}));
+            assert_eq!(response_in(&messages)["success"], true, "{messages:?}");
+            (messages, session)
+        };
+
+        let (messages, session) = restart("attach");
+        assert!(logged(&messages, "Re-attaching to the running debuggee"));
+        assert!(events(&messages, "thread").is_empty(), "{messages:?}");
+        assert!(session.st.threads.contains_key(&42));
+        assert_eq!(session.st.main_thread_name, "worker");
+
+        let (messages, session) = restart("launch");
+        assert!(!logged(&messages, "Re-attaching"));
+        assert!(!events(&messages, "thread").is_empty(), "{messages:?}");
+        assert!(!session.st.threads.contains_key(&42));
+    }
+
+    // initialize, launch с этим конфигом, configurationDone
+    fn launched_with(launch: Value) -> Session {
+        let mut session = Session::new();
+        session.request("initialize", json!({ "adapterID": "rast" }));
+        session.request("launch", launch);
+        session.request("configurationDone", Value::Null);
+        session
+    }
+
+    #[test]
+    fn step_stops_keep_focus_and_breakpoint_stops_take_it() {
+        let hints = |preserve: bool| {
+            let mut session =
+                launched_with(json!({ "program": "/tmp/demo", "preserveFocusOnStep": preserve }));
+            set_breakpoints(&mut session, json!({ "path": "/tmp/main.rs" }), &[3]);
+            let thread_id = session.st.main_thread_id;
+            let paused = session.request("pause", json!({ "threadId": thread_id }));
+            let stepped = session.request("next", json!({ "threadId": thread_id }));
+            [paused, stepped].map(|messages| {
+                let stopped = events(&messages, "stopped")[0].clone();
+                stopped["body"]["preserveFocusHint"].clone()
+            })
+        };
+        assert_eq!(hints(true), [json!(false), json!(true)]);
+        assert_eq!(hints(false), [json!(false), json!(false)]);
+    }
+
+    #[test]
+    fn a_line_with_chained_calls_has_several_locations() {
+        let path = std::env::temp_dir().join(format!("rast-dap-{}-inline.rs", std::process::id()));
+        std::fs::write(&path, "fn main() {\n    a(); v.iter().sum();\n}\n").unwrap();
+        let name = path.to_string_lossy().into_owned();
+        let mut session = Session::launched();
+        let response = session.response(
+            "breakpointLocations",
+            json!({ "source": { "path": name }, "line": 2 }),
+        );
+        let locations = &response["body"]["breakpoints"];
+        let columns: Vec<(i64, i64)> = locations
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|l| (l["line"].as_i64().unwrap(), l["column"].as_i64().unwrap()))
+            .collect();
+        // 1-based: a(), начало второго statement'а, iter, sum
+        assert_eq!(columns, [(2, 5), (2, 10), (2, 12), (2, 19)]);
+
+        let response = session.response(
+            "breakpointLocations",
+            json!({ "source": { "path": name }, "line": 2, "column": 11, "endColumn": 18 }),
+        );
+        assert_eq!(
+            response["body"]["breakpoints"],
+            json!([{ "line": 2, "column": 12 }])
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn single_threaded_targets_report_one_thread_and_no_thread_events() {
+        let lifecycle = |single: bool| {
+            let mut session =
+                launched_with(json!({ "program": "/tmp/demo", "singleThreaded": single }));
+            session.st.ensure_thread(42);
+            let threads = session.response("threads", Value::Null)["body"]["threads"]
+                .as_array()
+                .unwrap()
+                .len();
+            let mut sent = session.request("restart", json!({}));
+            sent.extend(session.request("disconnect", json!({})));
+            (threads, events(&sent, "thread").len())
+        };
+        assert_eq!(lifecycle(true), (1, 0));
+        let (threads, thread_events) = lifecycle(false);
+        assert_eq!(threads, 2);
+        assert!(thread_events > 0);
+    }
+
+    #[test]
+    fn set_breakpoints_answers_in_request_order() {
+        let path = std::env::temp_dir().join(format!("rast-dap-{}-order.rs", std::process::id()));
+        std::fs::write(&path, "a();\nb();\nc();\n").unwrap();
+        let name = path.to_string_lossy().into_owned();
+        let mut session = Session::launched();
+        let response = session.response(
+            "setBreakpoints",
+            json!({
+                "source": { "path": name },
+                "breakpoints": [{ "line": 9 }, { "line": 3 }, { "line": 1 }, { "line": 3 }],
+            }),
+        );
+        let breakpoints = response["body"]["breakpoints"].as_array().unwrap();
+        let answered: Vec<(&Value, &Value)> = breakpoints
+            .iter()
+            .map(|bp| (&bp["line"], &bp["verified"]))
+            .collect();
+        assert_eq!(
+            answered,
+            [
+                (&json!(9), &json!(false)),
+                (&json!(3), &json!(true)),
+                (&json!(1), &json!(true)),
+                (&json!(3), &json!(true)),
+            ]
+        );
+        assert_eq!(breakpoints[1]["id"], breakpoints[3]["id"]);
+
+        // Внутри — без повторов и по строкам
+        let stored: Vec<i64> = session
+            .st
+            .breakpoints_by_path
+            .values()
+            .next()
+            .unwrap()
+            .iter()
+            .map(|bp| bp.line)
+            .collect();
+        assert_eq!(stored, [1, 3, 9]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn completions_follow_the_requested_frame() {
+        let mut session = paused();
+        let response = session.response(
+            "stackTrace",
+            json!({ "threadId": session.st.main_thread_id }),
+        );
+        let frames = response["body"]["stackFrames"].as_array().unwrap().clone();
+        let mut labels = |frame_id: Option<&Value>| {
+            let mut args = json!({ "text": "", "column": 1 });
+            if let Some(frame_id) = frame_id {
+                args["frameId"] = frame_id.clone();
+            }
+            let response = session.response("completions", args);
+            let mut labels: Vec<String> = response["body"]["targets"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|t| t["label"].as_str().unwrap().to_string())
+                .collect();
+            labels.sort();
+            labels
+        };
+        let compute = labels(Some(&frames[0]["id"]));
+        let main = labels(Some(&frames[1]["id"]));
+        assert!(compute.contains(&"point".to_string()), "{compute:?}");
+        assert!(!compute.contains(&"total".to_string()), "{compute:?}");
+        assert!(main.contains(&"total".to_string()), "{main:?}");
+        assert!(!main.contains(&"point".to_string()), "{main:?}");
+        assert_eq!(labels(None), ["compute", "main"]);
+    }
+
+    #[test]
+    fn empty_set_breakpoints_clears_the_source() {
+        let removed = |messages: &[Value]| -> Vec<i64> {
+            events(messages, "breakpoint")
+                .iter()
+                .filter(|e| e["body"]["reason"] == "removed")
+                .map(|e| e["body"]["breakpoint"]["id"].as_i64().unwrap())
+                .collect()
+        };
+        for source in [
+            json!({ "path": "/tmp/main.rs" }),
+            json!({ "sourceReference": 7 }),
+        ] {
+            let mut session = Session::launched();
+            let ids = set_breakpoints(&mut session, source.clone(), &[3, 5]);
+            let messages = session.request(
+                "setBreakpoints",
+                json!({ "source": source, "breakpoints": [] }),
+            );
+            assert_eq!(response_in(&messages)["body"]["breakpoints"], json!([]));
+            assert_eq!(removed(&messages), ids, "{source}");
+            assert!(session.st.breakpoints_by_path.is_empty(), "{source}");
+            assert!(session.st.breakpoints_by_ref.is_empty(), "{source}");
+
+            // Без breakpoints вовсе — то же самое; снимать уже нечего
+            let messages = session.request("setBreakpoints", json!({ "source": source }));
+            assert_eq!(response_in(&messages)["body"]["breakpoints"], json!([]));
+            assert!(removed(&messages).is_empty(), "{source}");
+        }
+    }
+
+    #[test]
+    fn stops_before_configuration_done_wait_for_it() {
+        let mut session = Session::new();
+        session.request("initialize", json!({ "adapterID": "rast" }));
+        session.request("launch", json!({ "program": "/tmp/demo" }));
+        let thread_id = session.st.main_thread_id;
+        let messages = session.request("pause", json!({ "threadId": thread_id }));
+        assert_eq!(kinds(&messages), ["response"]);
+        let messages = session.request("continue", json!({ "threadId": thread_id }));
+        assert_eq!(kinds(&messages), ["response"]);
+
+        let messages = session.request("configurationDone", Value::Null);
+        assert_eq!(kinds(&messages), ["response", "stopped", "continued"]);
+        let messages = session.request("pause", json!({ "threadId": thread_id }));
+        assert_eq!(kinds(&messages), ["response", "stopped"]);
+    }
+
+    #[test]
+    fn unknown_depth_stacks_page_until_a_short_page() {
+        let mut session = paused();
+        let thread_id = session.st.main_thread_id;
+        let response = session.response("stackTrace", json!({ "threadId": thread_id }));
+        assert_eq!(response["body"]["totalFrames"], 2);
+
+        session.st.stack_depth_known = false;
+        // Как клиент: страницы по одному фрейму, пока страница не придёт неполной
+        let mut names = Vec::new();
+        for page in 0.. {
+            assert!(page < 10, "paging did not terminate");
+            let response = session.response(
+                "stackTrace",
+                json!({ "threadId": thread_id, "startFrame": page, "levels": 1 }),
+            );
+            assert!(response["body"].get("totalFrames").is_none(), "{response}");
+            let frames = response["body"]["stackFrames"].as_array().unwrap().clone();
+            names.extend(
+                frames
+                    .iter()
+                    .map(|f| f["name"].as_str().unwrap().to_string()),
+            );
+            if frames.is_empty() {
+                break;
+            }
+        }
+        assert_eq!(names.len(), 2, "{names:?}");
+    }
+
+    #[test]
+    fn uri_clients_send_and_receive_file_uris() {
+        let mut session = Session::new();
+        session.request(
+            "initialize",
+            json!({ "adapterID": "rast", "pathFormat": "uri", "locale": "de-DE" }),
+        );
+        assert_eq!(session.st.locale.as_deref(), Some("de-DE"));
+        session.request("launch", json!({ "program": "/tmp/demo" }));
+        session.request("configurationDone", Value::Null);
+
+        let uri = "file:///tmp/a%20b/main.rs";
+        let response = session.response(
+            "setBreakpoints",
+            json!({ "source": { "path": uri }, "breakpoints": [{ "line": 3 }] }),
+        );
+        assert_eq!(response["body"]["breakpoints"][0]["source"]["path"], uri);
+        // Внутри — обычный путь, как его видит бэкенд
+        assert!(session
+            .st
+            .breakpoints_by_path
+            .contains_key("/tmp/a b/main.rs"));
+
+        let thread_id = session.st.main_thread_id;
+        session.request("pause", json!({ "threadId": thread_id }));
+        let frame = top_frame(&mut session);
+        assert_eq!(frame["source"]["path"], uri, "{frame}");
+    }
+}
@@ -0,0 +1,195 @@
+use dap::types::{CompletionItem, CompletionItemType, Variable};
+
+use crate::state::DapState;
+
+pub(crate) fn default_trigger_characters() -> Vec<String> {
+    [".", ":", "->"].iter().map(|s| s.to_string()).collect()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Хвост-идентификатор строки: для "foo.ba" это "ba"
+fn trailing_ident(text: &str) -> &str {
+    let start = text
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_ident_char(*c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(text.len());
+    &text[start..]
+}
+
+// Выражение перед триггером: "self.items" в "x = self.items."
+fn trailing_path(text: &str) -> &str {
+    let start = text
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_ident_char(*c) || *c == '.')
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(text.len());
+    &text[start..]
+}
+
+// start/length — какой кусок text клиент заменит на label. По спецификации в UTF-16
+// code units, 0- или 1-based по columnsStartAt1 клиента.
+fn item(var: &Variable, kind: CompletionItemType, start: i64, length: i64) -> CompletionItem {
+    CompletionItem {
+        label: var.name.clone(),
+        detail: var.type_field.clone(),
+        type_field: Some(kind),
+        start: Some(start),
+        length: Some(length),
+        ..Default::default()
+    }
+}
+
+// Без фрейма подсказывать можно только то, что видно отовсюду — функции из символов
+fn global_symbols(st: &DapState) -> Vec<Variable> {
+    st.symbols
+        .keys()
+        .map(|name| Variable {
+            name: name.clone(),
+            type_field: Some("fn".to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+// column — 1-based позиция курсора в text (в символах).
+// frame_id — чьи локальные подсказывать; None — только глобальные символы
+pub(crate) fn completion_items(
+    st: &DapState,
+    text: &str,
+    column: i64,
+    frame_id: Option<i64>,
+) -> Vec<CompletionItem> {
+    let cursor = text
+        .char_indices()
+        .nth(column.saturating_sub(1).max(0) as usize)
+        .map(|(i, _)| i)
+        .unwrap_or(text.len());
+    let prefix = &text[..cursor];
+    let partial = trailing_ident(prefix);
+    let before = &prefix[..prefix.len() - partial.len()];
+    // Заменяется только недописанный идентификатор перед курсором: в "a + po|int" это "po"
+    let base = st
+        .client
+        .as_ref()
+        .and_then(|c| c.columns_start_at1)
+        .unwrap_or(true) as i64;
+    let start = before.encode_utf16().count() as i64 + base;
+    let length = partial.encode_utf16().count() as i64;
+
+    let trigger = st
+        .completion_trigger_characters
+        .iter()
+        .filter(|t| !t.is_empty() && before.ends_with(t.as_str()))
+        .max_by_key(|t| t.len());
+
+    let candidates: Vec<(Variable, CompletionItemType)> = match (trigger, frame_id) {
+        // После триггера — поля/члены переменной слева от него, ищем её в том же фрейме
+        (Some(t), Some(frame_id)) => {
+            let base = trailing_path(&before[..before.len() - t.len()]);
+            st.find_variable_in_frame(base, Some(frame_id))
+                .filter(|v| v.variables_reference > 0)
+                .and_then(|v| st.variables.get(&v.variables_reference))
+                .map(|children| {
+                    children
+                        .iter()
+                        .map(|c| (c.clone(), CompletionItemType::Property))
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+        // У функций членов нет
+        (Some(_), None) => Vec::new(),
+        (None, Some(frame_id)) => st
+            .frame_roots(Some(frame_id))
+            .into_iter()
+            .map(|v| (v.clone(), CompletionItemType::Variable))
+            .collect(),
+        (None, None) => global_symbols(st)
+            .into_iter()
+            .map(|v| (v, CompletionItemType::Function))
+            .collect(),
+    };
+
+    candidates
+        .iter()
+        .filter(|(v, _)| v.name.starts_with(partial))
+        .map(|(v, kind)| item(v, kind.clone(), start, length))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stopped() -> (DapState, Option<i64>) {
+        let mut st = DapState::new();
+        st.load_symbols(DapState::demo_symbols("demo"));
+        st.begin_stop();
+        let top = Some(st.call_stack[0].id);
+        (st, top)
+    }
+
+    fn labels(items: &[CompletionItem]) -> Vec<&str> {
+        items.iter().map(|i| i.label.as_str()).collect()
+    }
+
+    #[test]
+    fn locals_of_the_frame_match_the_partial_identifier() {
+        let (st, top) = stopped();
+        let items = completion_items(&st, "a + po", 7, top);
+        assert_eq!(labels(&items), ["point"]);
+        assert_eq!(items[0].start, Some(5));
+        assert_eq!(items[0].length, Some(2));
+        assert!(matches!(
+            items[0].type_field,
+            Some(CompletionItemType::Variable)
+        ));
+    }
+
+    #[test]
+    fn members_follow_a_trigger_character() {
+        let (st, top) = stopped();
+        let items = completion_items(&st, "point.", 7, top);
+        assert_eq!(labels(&items), ["x", "y"]);
+        assert_eq!(items[0].start, Some(7));
+        assert_eq!(items[0].length, Some(0));
+        assert!(completion_items(&st, "demo.", 6, top).is_empty());
+    }
+
+    #[test]
+    fn without_a_frame_only_functions_are_offered() {
+        let (st, _) = stopped();
+        let items = completion_items(&st, "ma", 3, None);
+        assert_eq!(labels(&items), ["main"]);
+        assert!(matches!(
+            items[0].type_field,
+            Some(CompletionItemType::Function)
+        ));
+    }
+
+    #[test]
+    fn replacement_range_is_in_utf16_client_columns() {
+        let (mut st, top) = stopped();
+        // Курсор после "de": хвост строки не учитывается
+        assert_eq!(labels(&completion_items(&st, "dexyz", 3, top)), ["demo"]);
+        let items = completion_items(&st, "\"😀\" + fa", 9, top);
+        assert_eq!(labels(&items), ["factor"]);
+        assert_eq!(items[0].start, Some(8));
+
+        st.client = Some(dap::requests::InitializeArguments {
+            columns_start_at1: Some(false),
+            ..Default::default()
+        });
+        let items = completion_items(&st, "\"😀\" + fa", 9, top);
+        assert_eq!(items[0].start, Some(7));
+        assert_eq!(items[0].length, Some(2));
+    }
+}
@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::Read;
+use std::rc::Rc;
+
+use serde_json::Value;
+
+use crate::error::HandlerResult;
+use crate::state::DapState;
+use crate::trace::FrameSplitter;
+use crate::types::DapServer;
+
+// Обработчик custom-команды из DapState.custom_commands
+pub(crate) type CustomHandler = fn(Option<&Value>, &mut DapServer, &mut DapState) -> HandlerResult;
+
+// dap разбирает только команды из спецификации: на custom-запросе poll_request отдаёт
+// ParseError, а прочитанное тело выбрасывает. Поэтому ниже BufReader копим тела фреймов
+// в том же порядке, в каком их разбирает Server, — после каждого poll_request забираем одно.
+#[derive(Clone, Default)]
+pub(crate) struct RawFrames(Rc<RefCell<VecDeque<String>>>);
+
+impl RawFrames {
+    pub(crate) fn next(&self) -> Option<String> {
+        self.0.borrow_mut().pop_front()
+    }
+}
+
+pub(crate) struct FrameCapture<R: Read> {
+    inner: R,
+    splitter: FrameSplitter,
+    frames: RawFrames,
+}
+
+impl<R: Read> FrameCapture<R> {
+    pub(crate) fn new(inner: R, frames: RawFrames) -> Self {
+        Self {
+            inner,
+            splitter: FrameSplitter::default(),
+            frames,
+        }
+    }
+}
+
+impl<R: Read> Read for FrameCapture<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.frames
+            .0
+            .borrow_mut()
+            .extend(self.splitter.push(&buf[..n]));
+        Ok(n)
+    }
+}
+
+// Команды спецификации, которые dap умеет разбирать: такой запрос в handle_custom —
+// значит, сломаны его аргументы, а не неизвестная команда
+pub(crate) const SPEC_COMMANDS: &[&str] = &[
+    "attach",
+    "breakpointLocations",
+    "cancel",
+    "completions",
+    "configurationDone",
+    "continue",
+    "dataBreakpointInfo",
+    "disassemble",
+    "disconnect",
+    "evaluate",
+    "exceptionInfo",
+    "goto",
+    "gotoTargets",
+    "initialize",
+    "launch",
+    "loadedSources",
+    "modules",
+    "next",
+    "pause",
+    "readMemory",
+    "restart",
+    "restartFrame",
+    "reverseContinue",
+    "scopes",
+    "setBreakpoints",
+    "setDataBreakpoints",
+    "setExceptionBreakpoints",
+    "setExpression",
+    "setFunctionBreakpoints",
+    "setInstructionBreakpoints",
+    "setVariable",
+    "source",
+    "stackTrace",
+    "stepBack",
+    "stepIn",
+    "stepInTargets",
+    "stepOut",
+    "terminate",
+    "terminateThreads",
+    "threads",
+    "variables",
+    "writeMemory",
+];
+
+// То немногое, что нужно от запроса, который dap не разобрал
+#[derive(Debug)]
+pub(crate) struct CustomRequest {
+    pub(crate) seq: i64,
+    pub(crate) command: String,
+    pub(crate) arguments: Option<Value>,
+}
+
+impl CustomRequest {
+    pub(crate) fn parse(frame: &str) -> Option<Self> {
+        let value: Value = serde_json::from_str(frame).ok()?;
+        if value.get("type")?.as_str()? != "request" {
+            return None;
+        }
+        Some(Self {
+            seq: value.get("seq")?.as_i64()?,
+            command: value.get("command")?.as_str()?.to_string(),
+            arguments: value.get("arguments").cloned(),
+        })
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use dap::errors::ServerError;
+use dap::responses::{Response, ResponseMessage};
+use dap::types::Message;
+
+// Почему обработчик не ответил успехом. Роутер сам превращает ошибку в error-ответ
+// на запрос — обработчику достаточно вернуть Err. Transport — сломан канал к клиенту:
+// отвечать некуда, ошибка уходит в главный цикл как DynResult.
+#[derive(Debug)]
+pub(crate) enum HandlerError {
+    InvalidArgument(String),
+    NotFound(String),
+    BackendError(String),
+    Unsupported(String),
+    Transport(Box<dyn std::error::Error>),
+    // Текст внутренней ошибки — шаблон с `{name}`: в ответ уходит и шаблон (Message.format)
+    // с variables, чтобы клиент мог его перевести, и уже подставленный текст
+    Templated(Box<HandlerError>, Vec<(&'static str, String)>),
+}
+
+pub(crate) type HandlerResult = Result<(), HandlerError>;
+
+impl HandlerError {
+    // Transport текста для клиента не имеет — ему переменные ни к чему
+    pub(crate) fn with_variables(
+        self,
+        variables: impl IntoIterator<Item = (&'static str, String)>,
+    ) -> Self {
+        match self {
+            HandlerError::Transport(_) => self,
+            _ => HandlerError::Templated(Box::new(self), variables.into_iter().collect()),
+        }
+    }
+
+    // id в body.error: клиент (и тесты) различают причины, не разбирая текст
+    fn code(&self) -> Option<i64> {
+        match self {
+            HandlerError::InvalidArgument(_) => Some(1001),
+            HandlerError::NotFound(_) => Some(1002),
+            HandlerError::BackendError(_) => Some(1003),
+            HandlerError::Unsupported(_) => Some(1004),
+            HandlerError::Templated(inner, _) => inner.code(),
+            HandlerError::Transport(_) => None,
+        }
+    }
+
+    // Err — ответить нельзя (Transport), ошибку отдаём наверх как есть.
+    // По seq, а не по Request: custom-запросы (см. custom_request) до Request не доходят
+    pub(crate) fn into_response(
+        self,
+        request_seq: i64,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let message = match self {
+            HandlerError::Transport(e) => return Err(e),
+            _ => self.to_string(),
+        };
+        let (format, variables) = match &self {
+            HandlerError::Templated(inner, variables) => (
+                inner.to_string(),
+                variables
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect(),
+            ),
+            _ => literal_format(&message),
+        };
+        let mut response = Response {
+            request_seq,
+            success: false,
+            message: Some(ResponseMessage::Error(message)),
+            ..Default::default()
+        };
+        response.error = self.code().map(|id| Message {
+            id,
+            format,
+            variables,
+            send_telemetry: None,
+            show_user: None,
+            url: None,
+            url_label: None,
+        });
+        Ok(response)
+    }
+}
+
+// Один проход слева направо: подставленное значение уже не шаблон, даже если в нём
+// `{другая}`. Незнакомое `{name}` остаётся как есть
+fn substitute(template: &str, variables: &[(&str, String)]) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        text.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = after.find('}').and_then(|close| {
+            let name = &after[..close];
+            let value = variables.iter().find(|(n, _)| *n == name)?;
+            Some((&value.1, close))
+        });
+        match value {
+            Some((value, close)) => {
+                text.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = after;
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+// Текст без шаблона: `{` и `}` в нём — не переменные, а клиент развернул бы их как
+// переменные. Поэтому каждая скобка в format — переменная lbrace/rbrace
+fn literal_format(text: &str) -> (String, HashMap<String, String>) {
+    if !text.contains(['{', '}']) {
+        return (text.to_string(), HashMap::new());
+    }
+    let mut format = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '{' => format.push_str("{lbrace}"),
+            '}' => format.push_str("{rbrace}"),
+            c => format.push(c),
+        }
+    }
+    let variables = [("lbrace", "{"), ("rbrace", "}")]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    (format, variables)
+}
+
+impl fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandlerError::InvalidArgument(msg)
+            | HandlerError::NotFound(msg)
+            | HandlerError::BackendError(msg)
+            | HandlerError::Unsupported(msg) => f.write_str(msg),
+            HandlerError::Transport(e) => write!(f, "{e}"),
+            HandlerError::Templated(inner, variables) => {
+                f.write_str(&substitute(&inner.to_string(), variables))
+            }
+        }
+    }
+}
+
+impl std::error::Error for HandlerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HandlerError::Transport(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+// `?` на server.respond/send_event и на хелперах, которые только пишут клиенту
+impl From<ServerError> for HandlerError {
+    fn from(e: ServerError) -> Self {
+        HandlerError::Transport(Box::new(e))
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for HandlerError {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        HandlerError::Transport(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_message(e: HandlerError) -> Message {
+        e.into_response(7).unwrap().error.unwrap()
+    }
+
+    #[test]
+    fn each_kind_answers_with_its_own_id() {
+        let kinds = [
+            (HandlerError::InvalidArgument("a".to_string()), 1001),
+            (HandlerError::NotFound("b".to_string()), 1002),
+            (HandlerError::BackendError("c".to_string()), 1003),
+            (HandlerError::Unsupported("d".to_string()), 1004),
+        ];
+        for (err, id) in kinds {
+            let text = err.to_string();
+            let response = err.into_response(7).unwrap();
+            assert!(!response.success);
+            assert_eq!(response.request_seq, 7);
+            assert!(matches!(&response.message, Some(ResponseMessage::Error(m)) if *m == text));
+            assert_eq!(response.error.unwrap().id, id);
+        }
+    }
+
+    #[test]
+    fn literal_braces_are_escaped_in_format() {
+        let message = error_message(HandlerError::InvalidArgument(
+            "Expected '{ x: 1 }'".to_string(),
+        ));
+        assert_eq!(message.id, 1001);
+        assert_eq!(message.format, "Expected '{lbrace} x: 1 {rbrace}'");
+        assert_eq!(message.variables["lbrace"], "{");
+        assert_eq!(message.variables["rbrace"], "}");
+    }
+
+    #[test]
+    fn plain_text_has_no_variables() {
+        let message = error_message(HandlerError::NotFound("No such frame".to_string()));
+        assert_eq!(message.format, "No such frame");
+        assert!(message.variables.is_empty());
+    }
+
+    #[test]
+    fn templated_error_keeps_its_template() {
+        let response = HandlerError::Unsupported("Unknown '{command}'".to_string())
+            .with_variables([("command", "x{y}".to_string())])
+            .into_response(7)
+            .unwrap();
+        assert!(
+            matches!(&response.message, Some(ResponseMessage::Error(text)) if text == "Unknown 'x{y}'")
+        );
+        let message = response.error.unwrap();
+        assert_eq!(message.id, 1004);
+        assert_eq!(message.format, "Unknown '{command}'");
+        assert_eq!(message.variables["command"], "x{y}");
+    }
+
+    #[test]
+    fn substituted_values_are_not_expanded_again() {
+        let err = HandlerError::NotFound("No '{path}' at line {line} ({other})".to_string())
+            .with_variables([
+                ("path", "{line}".to_string()),
+                ("line", "{path}".to_string()),
+            ]);
+        assert_eq!(err.to_string(), "No '{line}' at line {path} ({other})");
+    }
+
+    #[test]
+    fn transport_error_is_not_answered() {
+        assert!(HandlerError::Transport("closed".into())
+            .into_response(7)
+            .is_err());
+    }
+}
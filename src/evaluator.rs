@@ -0,0 +1,297 @@
+use serde_json::Value;
+
+use crate::state::DapState;
+use crate::types::DynResult;
+
+// Общая точка для evaluate, условных брейков и logpoint'ов.
+// Бэкенд со своим языком выражений подменяет реализацию в DapState.evaluator.
+pub(crate) trait ExpressionEvaluator: std::fmt::Debug {
+    fn eval(&self, expr: &str, frame_id: Option<i64>, st: &DapState) -> DynResult<Value>;
+}
+
+// По умолчанию: целые числа, имена переменных, + - * / %, скобки и сравнения.
+#[derive(Debug, Default)]
+pub(crate) struct DefaultEvaluator;
+
+impl ExpressionEvaluator for DefaultEvaluator {
+    fn eval(&self, expr: &str, frame_id: Option<i64>, st: &DapState) -> DynResult<Value> {
+        let tokens = tokenize(expr)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            frame_id,
+            st,
+        };
+        let value = parser.comparison()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(format!("Unexpected token in '{expr}'").into());
+        }
+        Ok(value)
+    }
+}
+
+pub(crate) fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().map(|f| f != 0.0).unwrap_or(false),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+pub(crate) fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+const OPS: [&str; 11] = ["==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%"];
+
+fn tokenize(expr: &str) -> DynResult<Vec<Token>> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(Token::Int(text.parse()?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            match OPS.iter().find(|op| rest.starts_with(**op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    i += op.len();
+                }
+                None => return Err(format!("Unexpected character '{c}'").into()),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    frame_id: Option<i64>,
+    st: &'a DapState,
+}
+
+impl Parser<'_> {
+    fn peek_op(&self, ops: &[&str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn comparison(&mut self) -> DynResult<Value> {
+        let left = self.sum()?;
+        if let Some(op) = self.peek_op(&["==", "!=", "<=", ">=", "<", ">"]) {
+            self.pos += 1;
+            let right = self.sum()?;
+            let result = match (left.as_i64(), right.as_i64()) {
+                (Some(l), Some(r)) => match op {
+                    "==" => l == r,
+                    "!=" => l != r,
+                    "<=" => l <= r,
+                    ">=" => l >= r,
+                    "<" => l < r,
+                    _ => l > r,
+                },
+                _ => match op {
+                    "==" => left == right,
+                    "!=" => left != right,
+                    _ => return Err(format!("Cannot compare {left} and {right}").into()),
+                },
+            };
+            return Ok(Value::Bool(result));
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> DynResult<Value> {
+        let mut acc = self.product()?;
+        while let Some(op) = self.peek_op(&["+", "-"]) {
+            self.pos += 1;
+            let right = self.product()?;
+            acc = arith(op, &acc, &right)?;
+        }
+        Ok(acc)
+    }
+
+    fn product(&mut self) -> DynResult<Value> {
+        let mut acc = self.unary()?;
+        while let Some(op) = self.peek_op(&["*", "/", "%"]) {
+            self.pos += 1;
+            let right = self.unary()?;
+            acc = arith(op, &acc, &right)?;
+        }
+        Ok(acc)
+    }
+
+    fn unary(&mut self) -> DynResult<Value> {
+        if self.peek_op(&["-"]).is_some() {
+            self.pos += 1;
+            let v = self.unary()?;
+            return arith("-", &Value::from(0), &v);
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> DynResult<Value> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or("Unexpected end of expression")?;
+        self.pos += 1;
+        match token {
+            Token::Int(n) => Ok(Value::from(n)),
+            Token::Ident(name) => self.lookup(&name),
+            Token::LParen => {
+                let v = self.comparison()?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::RParen) => {
+                        self.pos += 1;
+                        Ok(v)
+                    }
+                    _ => Err("Missing ')'".into()),
+                }
+            }
+            other => Err(format!("Unexpected token {other:?}").into()),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> DynResult<Value> {
+        match name {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            _ => {}
+        }
+        let var = self
+            .st
+            .find_variable_in_frame(name, self.frame_id)
+            .ok_or_else(|| format!("Unknown variable '{name}'"))?;
+        Ok(match var.value.parse::<i64>() {
+            Ok(n) => Value::from(n),
+            Err(_) => Value::String(var.value.clone()),
+        })
+    }
+}
+
+fn arith(op: &str, left: &Value, right: &Value) -> DynResult<Value> {
+    let (l, r) = match (left.as_i64(), right.as_i64()) {
+        (Some(l), Some(r)) => (l, r),
+        _ => return Err(format!("'{op}' needs integer operands").into()),
+    };
+    let result = match op {
+        "+" => l.checked_add(r),
+        "-" => l.checked_sub(r),
+        "*" => l.checked_mul(r),
+        "/" => l.checked_div(r),
+        _ => l.checked_rem(r),
+    };
+    result
+        .map(Value::from)
+        .ok_or_else(|| format!("Arithmetic error in {l} {op} {r}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    // Остановка демо-стека: compute (demo, point, factor) <- main (total)
+    fn stopped() -> DapState {
+        let mut st = DapState::new();
+        st.begin_stop();
+        st
+    }
+
+    fn eval(expr: &str) -> DynResult<Value> {
+        DefaultEvaluator.eval(expr, None, &DapState::new())
+    }
+
+    #[test]
+    fn arithmetic_precedence_and_parentheses() {
+        assert_eq!(eval("1 + 2 * 3").unwrap(), json!(7));
+        assert_eq!(eval("(1 + 2) * 3").unwrap(), json!(9));
+        assert_eq!(eval("7 % 4 - -2").unwrap(), json!(5));
+        assert_eq!(eval("10 / 3").unwrap(), json!(3));
+    }
+
+    #[test]
+    fn comparisons_give_booleans() {
+        assert_eq!(eval("2 + 2 == 4").unwrap(), json!(true));
+        assert_eq!(eval("1 >= 2").unwrap(), json!(false));
+        assert_eq!(eval("true != false").unwrap(), json!(true));
+        assert!(eval("true < 1").is_err());
+    }
+
+    #[test]
+    fn errors_are_reported_not_panicked() {
+        assert!(eval("1 / 0").is_err());
+        assert!(eval("9223372036854775807 + 1").is_err());
+        assert!(eval("(1 + 2").is_err());
+        assert!(eval("1 2").is_err());
+        assert!(eval("1 & 2").is_err());
+        assert!(eval("").is_err());
+        assert!(eval("nobody").is_err());
+    }
+
+    #[test]
+    fn truthiness_and_display() {
+        assert!(is_truthy(&json!(1)));
+        assert!(!is_truthy(&json!(0)));
+        assert!(!is_truthy(&json!("")));
+        assert!(!is_truthy(&Value::Null));
+        assert!(is_truthy(&json!([0])));
+        assert_eq!(value_to_string(&json!("text")), "text");
+        assert_eq!(value_to_string(&json!(true)), "true");
+    }
+
+    #[test]
+    fn variables_resolve_in_the_requested_frame() {
+        let st = stopped();
+        let main = Some(st.call_stack[1].id);
+        let eval = |expr, frame| DefaultEvaluator.eval(expr, frame, &st);
+        assert_eq!(eval("demo * factor", None).unwrap(), json!(4));
+        assert_eq!(eval("point.x + point.y", None).unwrap(), json!(7));
+        assert_eq!(eval("total", main).unwrap(), json!(12));
+        assert!(eval("demo", main).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+
+use dap::events::Event;
+
+use crate::types::{DapServer, DynResult};
+
+const EVENT_QUEUE_BOUND: usize = 256;
+
+// Единственная точка, откуда события уходят в writer: всё, что не ответ на запрос,
+// кладётся сюда и пишется главным циклом после обработчика, по порядку.
+// Потоки бэкенда получат клон SyncSender — при переполнении они ждут (backpressure).
+#[derive(Debug)]
+pub(crate) struct EventQueue {
+    sender: SyncSender<Event>,
+    receiver: Receiver<Event>,
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        let (sender, receiver) = sync_channel(EVENT_QUEUE_BOUND);
+        Self { sender, receiver }
+    }
+}
+
+impl EventQueue {
+    // Для главного потока: ждать места в очереди нельзя — разгребать её некому,
+    // поэтому при переполнении сначала сливаем накопленное.
+    pub(crate) fn push(&self, server: &mut DapServer, event: Event) -> DynResult<()> {
+        match self.sender.try_send(event) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(event) | TrySendError::Disconnected(event)) => {
+                self.drain(server)?;
+                server.send_event(event)?;
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn drain(&self, server: &mut DapServer) -> DynResult<()> {
+        while let Ok(event) = self.receiver.try_recv() {
+            server.send_event(event)?;
+        }
+        Ok(())
+    }
+}
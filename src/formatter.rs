@@ -0,0 +1,308 @@
+use dap::types::{StackFrameFormat, ValueFormat, Variable};
+
+use crate::state::{is_indexed, CallFrame, DapState};
+
+pub(crate) const DEFAULT_VALUE_LIMIT: usize = 200;
+
+// Как значение показывается в панели Variables. Полное значение для clipboard
+// берётся из хранилища напрямую (expand_value), форматтер на него не влияет.
+// format — ValueFormat из запроса (hex), None — как есть.
+pub(crate) trait VariableFormatter: std::fmt::Debug {
+    fn format(&self, var: &Variable, format: Option<&ValueFormat>, st: &DapState) -> String;
+}
+
+// По умолчанию: массивы — `Vec(3)`, строки длиннее st.value_limit обрезаются,
+// управляющие символы экранируются.
+#[derive(Debug, Default)]
+pub(crate) struct DefaultFormatter;
+
+impl VariableFormatter for DefaultFormatter {
+    fn format(&self, var: &Variable, format: Option<&ValueFormat>, st: &DapState) -> String {
+        if var.variables_reference > 0 {
+            if let Some(children) = st.variables.get(&var.variables_reference) {
+                if is_indexed(children) {
+                    let type_name = var.type_field.as_deref().unwrap_or("Array");
+                    let base = type_name.split('<').next().unwrap_or(type_name);
+                    return format!("{base}({})", children.len());
+                }
+            }
+        }
+        let value = render_value(&var.value, var.type_field.as_deref(), format);
+        truncate(&escape_control(&value), st.value_limit)
+    }
+}
+
+const INTEGER_TYPES: &[&str] = &[
+    "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+];
+// Не enum, даже если значение похоже на идентификатор
+const NON_ENUM_TYPES: &[&str] = &["bool", "char", "str", "&str", "String", "f32", "f64"];
+
+// Вид значения по типу от бэкенда: от него зависит отображение и то, действует ли hex
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind<'a> {
+    Bool,
+    Integer,
+    // Тип, на который указывает
+    Pointer(&'a str),
+    // Имя enum без generic-параметров
+    Enum(&'a str),
+    Other,
+}
+
+fn value_kind<'a>(value: &str, type_name: Option<&'a str>) -> ValueKind<'a> {
+    let Some(type_name) = type_name.map(str::trim) else {
+        return ValueKind::Other;
+    };
+    if type_name == "bool" {
+        return ValueKind::Bool;
+    }
+    if INTEGER_TYPES.contains(&type_name) {
+        return ValueKind::Integer;
+    }
+    if let Some(pointee) = type_name
+        .strip_prefix("*const ")
+        .or_else(|| type_name.strip_prefix("*mut "))
+    {
+        return ValueKind::Pointer(pointee.trim());
+    }
+    // Вариант: `Red` или `Some(3)` у типа, который не примитив. Структура начинается
+    // с имени своего же типа (`Point { .. }`), поэтому её не трогаем
+    let base = type_name.split('<').next().unwrap_or(type_name);
+    let variant: String = value
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect();
+    let rest = &value[variant.len()..];
+    let is_variant = variant.starts_with(char::is_uppercase)
+        && variant != base
+        && (rest.is_empty() || rest.starts_with('('));
+    if is_variant && !NON_ENUM_TYPES.contains(&type_name) {
+        return ValueKind::Enum(base);
+    }
+    ValueKind::Other
+}
+
+fn parse_int(value: &str) -> Option<i128> {
+    let value = value.trim();
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    let magnitude = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+fn int_to_string(n: i128, hex: bool) -> String {
+    match (hex, n < 0) {
+        (false, _) => n.to_string(),
+        (true, false) => format!("{n:#x}"),
+        (true, true) => format!("-{:#x}", n.unsigned_abs()),
+    }
+}
+
+// Значение для показа: булевы — true/false, enum — `Color::Red`, указатели — `0x… -> Foo`.
+// hex действует только на целые и указатели (указатели и без него в hex)
+pub(crate) fn render_value(
+    value: &str,
+    type_name: Option<&str>,
+    format: Option<&ValueFormat>,
+) -> String {
+    let hex = format.and_then(|f| f.hex);
+    match value_kind(value, type_name) {
+        ValueKind::Bool => match value.trim() {
+            "1" | "true" => "true".to_string(),
+            "0" | "false" => "false".to_string(),
+            other => other.to_string(),
+        },
+        ValueKind::Integer => match parse_int(value) {
+            Some(n) => int_to_string(n, hex.unwrap_or(false)),
+            None => value.to_string(),
+        },
+        ValueKind::Pointer(pointee) => match parse_int(value) {
+            Some(0) => format!("null -> {pointee}"),
+            Some(addr) => format!("{} -> {pointee}", int_to_string(addr, hex.unwrap_or(true))),
+            None => value.to_string(),
+        },
+        ValueKind::Enum(base) => format!("{base}::{value}"),
+        ValueKind::Other => value.to_string(),
+    }
+}
+
+fn escape_control(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Лимит в символах, не байтах — иначе режем посреди UTF-8
+fn truncate(value: &str, limit: usize) -> String {
+    let total = value.chars().count();
+    if total <= limit {
+        return value.to_string();
+    }
+    let head: String = value.chars().take(limit).collect();
+    format!("{head}… ({total} chars, copy value to see all)")
+}
+
+// Подпись фрейма в Call Stack по StackTraceArguments.format. Без format — просто имя функции.
+// parameters включает список аргументов; имена и значения в нём по умолчанию есть, типы — нет.
+pub(crate) fn frame_name(
+    frame: &CallFrame,
+    format: Option<&StackFrameFormat>,
+    st: &DapState,
+) -> String {
+    let Some(format) = format else {
+        return frame.name.clone();
+    };
+    let mut name = frame.name.clone();
+
+    if format.parameters.unwrap_or(false) {
+        let names = format.parameter_names.unwrap_or(true);
+        let types = format.parameter_types.unwrap_or(false);
+        let values = format.parameter_values.unwrap_or(true);
+        let params: Vec<String> = st
+            .variables
+            .get(&frame.arguments_ref)
+            .into_iter()
+            .flatten()
+            .map(|var| {
+                let mut param = String::new();
+                if names {
+                    param.push_str(&var.name);
+                }
+                if let Some(type_name) = var.type_field.as_deref().filter(|_| types) {
+                    if !param.is_empty() {
+                        param.push_str(": ");
+                    }
+                    param.push_str(type_name);
+                }
+                if values {
+                    if !param.is_empty() {
+                        param.push_str(" = ");
+                    }
+                    param.push_str(&st.formatter.format(var, None, st));
+                }
+                param
+            })
+            .collect();
+        name = format!("{name}({})", params.join(", "));
+    }
+
+    if format.line.unwrap_or(false) {
+        name = format!("{name} Line {}", frame.line);
+    }
+    // Как в WinDbg: module!function
+    if let Some(module) = frame
+        .module
+        .as_deref()
+        .filter(|_| format.module.unwrap_or(false))
+    {
+        name = format!("{module}!{name}");
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: ValueFormat = ValueFormat { hex: Some(true) };
+
+    #[test]
+    fn integers_honour_hex() {
+        assert_eq!(render_value("255", Some("u8"), None), "255");
+        assert_eq!(render_value("255", Some("u8"), Some(&HEX)), "0xff");
+        assert_eq!(render_value("-16", Some("i32"), Some(&HEX)), "-0x10");
+        assert_eq!(render_value("0x1F", Some("u32"), None), "31");
+        assert_eq!(render_value("oops", Some("i32"), None), "oops");
+    }
+
+    #[test]
+    fn booleans_are_spelled_out() {
+        assert_eq!(render_value("1", Some("bool"), None), "true");
+        assert_eq!(render_value("false", Some("bool"), Some(&HEX)), "false");
+        assert_eq!(render_value("2", Some("bool"), None), "2");
+    }
+
+    #[test]
+    fn pointers_show_their_pointee() {
+        let off = ValueFormat { hex: Some(false) };
+        assert_eq!(render_value("0", Some("*const u8"), None), "null -> u8");
+        assert_eq!(
+            render_value("4096", Some("*mut Node"), None),
+            "0x1000 -> Node"
+        );
+        assert_eq!(
+            render_value("4096", Some("*mut Node"), Some(&off)),
+            "4096 -> Node"
+        );
+    }
+
+    #[test]
+    fn enum_variants_are_qualified() {
+        assert_eq!(render_value("Red", Some("Color"), None), "Color::Red");
+        assert_eq!(
+            render_value("Some(3)", Some("Option<i32>"), None),
+            "Option::Some(3)"
+        );
+        assert_eq!(
+            render_value("Point { x: 1 }", Some("Point"), None),
+            "Point { x: 1 }"
+        );
+        assert_eq!(render_value("Hello", Some("String"), None), "Hello");
+        assert_eq!(render_value("Red", None, None), "Red");
+    }
+
+    fn variable(value: &str, type_name: &str, variables_reference: i64) -> Variable {
+        Variable {
+            name: "v".to_string(),
+            value: value.to_string(),
+            type_field: Some(type_name.to_string()),
+            variables_reference,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_formatter_escapes_and_truncates() {
+        let mut st = DapState::new();
+        st.value_limit = 5;
+        let format = |var| DefaultFormatter.format(&var, None, &st);
+        assert_eq!(format(variable("a\tb", "String", 0)), "a\\tb");
+        assert_eq!(
+            format(variable("мир мир", "String", 0)),
+            "мир м… (7 chars, copy value to see all)"
+        );
+        assert_eq!(format(variable("\u{1}", "char", 0)), "\\u{1}");
+    }
+
+    #[test]
+    fn default_formatter_summarizes_arrays() {
+        let mut st = DapState::new();
+        let children = ["[0]", "[1]", "[2]"]
+            .iter()
+            .map(|name| Variable {
+                name: name.to_string(),
+                ..variable("1", "i32", 0)
+            })
+            .collect();
+        st.variables.insert(7, children);
+        let var = variable("[1, 1, 1]", "Vec<i32>", 7);
+        assert_eq!(DefaultFormatter.format(&var, None, &st), "Vec(3)");
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::time::Duration;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::error::HandlerError;
+use crate::utils::parse_env_file;
+
+// Наша часть launch/attach-конфига (additional_data). Всё необязательное:
+// отсутствующее поле = значение по умолчанию, а вот поле не того типа — ошибка.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub(crate) struct LaunchConfig {
+    pub(crate) program: Option<String>,
+    pub(crate) args: Vec<String>,
+    pub(crate) thread_name: Option<String>,
+    pub(crate) stop_on_entry: bool,
+    // Миллисекунды, сколько стоять на entry перед автоматическим continue
+    pub(crate) entry_continue_after: Option<u64>,
+    pub(crate) path_mappings: PathMappings,
+    // Stopped после шага не забирает фокус (preserveFocusHint)
+    pub(crate) preserve_focus_on_step: bool,
+    // Программа заведомо однопоточная: один поток в threads и никаких thread-событий
+    pub(crate) single_threaded: bool,
+    // На restart вернуть в консоль вывод прошлого запуска и разделитель
+    pub(crate) retain_console_on_restart: bool,
+    pub(crate) env_file: Option<String>,
+    pub(crate) env: BTreeMap<String, Value>,
+    // Не наследовать окружение адаптера: у debuggee только envFile и env
+    pub(crate) clear_env: bool,
+}
+
+// pathMappings: [{ "remoteRoot": "/app", "localRoot": "/home/me/app" }, ...] или
+// { "/app": "/home/me/app" }
+// Не untagged: его ошибка "did not match any variant" не говорит, что именно не так
+#[derive(Debug, Clone)]
+pub(crate) enum PathMappings {
+    List(Vec<PathMapping>),
+    Map(BTreeMap<String, String>),
+}
+
+impl<'de> Deserialize<'de> for PathMappings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let mappings = if value.is_object() {
+            serde_json::from_value(value).map(PathMappings::Map)
+        } else {
+            serde_json::from_value(value).map(PathMappings::List)
+        };
+        mappings.map_err(D::Error::custom)
+    }
+}
+
+impl Default for PathMappings {
+    fn default() -> Self {
+        PathMappings::List(Vec::new())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PathMapping {
+    remote_root: String,
+    local_root: String,
+}
+
+impl LaunchConfig {
+    // Ошибка называет поле: serde сам по себе говорит только "invalid type",
+    // поэтому при неудаче разбираем поля по одному и ищем виноватое
+    pub(crate) fn parse(data: Option<&Value>) -> Result<Self, HandlerError> {
+        let Some(data) = data else {
+            return Ok(Self::default());
+        };
+        if !data.is_object() {
+            return Err(HandlerError::InvalidArgument(
+                "Invalid launch configuration: expected an object".to_string(),
+            ));
+        }
+        Self::deserialize(data).map_err(|e| {
+            let field = data
+                .as_object()
+                .into_iter()
+                .flatten()
+                .find_map(|(key, value)| {
+                    let single = serde_json::json!({ key: value });
+                    Self::deserialize(&single)
+                        .err()
+                        .map(|e| (key.clone(), e.to_string()))
+                });
+            match field {
+                Some((field, error)) => HandlerError::InvalidArgument(
+                    "Invalid launch configuration: '{field}': {error}".to_string(),
+                )
+                .with_variables([("field", field), ("error", error)]),
+                None => HandlerError::InvalidArgument(
+                    "Invalid launch configuration: {error}".to_string(),
+                )
+                .with_variables([("error", e.to_string())]),
+            }
+        })
+    }
+
+    // args: ["--port", "1234", ...]
+    pub(crate) fn port(&self) -> Option<u16> {
+        match self.args.as_slice() {
+            [flag, port, ..] if flag == "--port" => port.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn thread_name(&self) -> Option<String> {
+        self.thread_name
+            .clone()
+            .filter(|name| !name.trim().is_empty())
+    }
+
+    pub(crate) fn entry_continue_after(&self) -> Option<Duration> {
+        self.entry_continue_after.map(Duration::from_millis)
+    }
+
+    // Пары (remote, local)
+    pub(crate) fn path_mappings(&self) -> Vec<(String, String)> {
+        match &self.path_mappings {
+            PathMappings::List(list) => list
+                .iter()
+                .map(|m| (m.remote_root.clone(), m.local_root.clone()))
+                .collect(),
+            PathMappings::Map(map) => map
+                .iter()
+                .map(|(remote, local)| (remote.clone(), local.clone()))
+                .collect(),
+        }
+    }
+
+    // Окружение для debuggee: env из конфига > envFile > окружение адаптера (без clearEnv)
+    pub(crate) fn resolve_env(&self) -> Result<BTreeMap<String, String>, HandlerError> {
+        self.resolve_env_over(std::env::vars_os())
+    }
+
+    // inherited — окружение адаптера. Не-UTF-8 переменные пропускаем: env::vars()
+    // на них паникует, а передать их дальше как String всё равно нельзя
+    fn resolve_env_over(
+        &self,
+        inherited: impl IntoIterator<Item = (OsString, OsString)>,
+    ) -> Result<BTreeMap<String, String>, HandlerError> {
+        let mut env: BTreeMap<String, String> = if self.clear_env {
+            BTreeMap::new()
+        } else {
+            inherited
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    Some((key.into_string().ok()?, value.into_string().ok()?))
+                })
+                .collect()
+        };
+
+        if let Some(path) = &self.env_file {
+            let contents = std::fs::read_to_string(path).map_err(|e| {
+                HandlerError::InvalidArgument("Cannot read envFile '{path}': {error}".to_string())
+                    .with_variables([("path", path.clone()), ("error", e.to_string())])
+            })?;
+            let vars = parse_env_file(&contents).map_err(|e| {
+                HandlerError::InvalidArgument("envFile '{path}': {error}".to_string())
+                    .with_variables([("path", path.clone()), ("error", e.to_string())])
+            })?;
+            env.extend(vars);
+        }
+
+        // `"KEY": null` — снять переменную, унаследованную или из envFile
+        for (key, value) in &self.env {
+            let value = match value {
+                Value::Null => {
+                    env.remove(key);
+                    continue;
+                }
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            env.insert(key.clone(), value);
+        }
+        Ok(env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn config(data: Value) -> LaunchConfig {
+        LaunchConfig::parse(Some(&data)).unwrap()
+    }
+
+    #[test]
+    fn full_config_is_parsed() {
+        let config = config(json!({
+            "program": "/tmp/demo",
+            "args": ["--port", "4711"],
+            "stopOnEntry": true,
+            "threadName": "worker",
+            "pathMappings": [{ "remoteRoot": "/app", "localRoot": "/src" }],
+            "env": { "A": "1" },
+            "unknownToUs": { "left": "for other tools" },
+        }));
+        assert_eq!(config.program.as_deref(), Some("/tmp/demo"));
+        assert_eq!(config.port(), Some(4711));
+        assert!(config.stop_on_entry);
+        assert_eq!(config.thread_name().as_deref(), Some("worker"));
+        assert_eq!(
+            config.path_mappings(),
+            [("/app".to_string(), "/src".to_string())]
+        );
+        assert_eq!(config.env["A"], "1");
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        let config = config(json!({ "program": "/tmp/demo" }));
+        assert!(!config.stop_on_entry && !config.clear_env && !config.single_threaded);
+        assert!(config.args.is_empty() && config.env.is_empty());
+        assert_eq!(config.port(), None);
+        assert_eq!(config.entry_continue_after(), None);
+        assert!(config.path_mappings().is_empty());
+        assert!(LaunchConfig::parse(None).unwrap().program.is_none());
+    }
+
+    #[test]
+    fn invalid_field_is_named_in_the_error() {
+        let data = json!({ "program": "/tmp/demo", "stopOnEntry": "yes" });
+        let err = LaunchConfig::parse(Some(&data)).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("Invalid launch configuration: 'stopOnEntry': invalid type"),
+            "{message}"
+        );
+        let err = LaunchConfig::parse(Some(&json!([1]))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid launch configuration: expected an object"
+        );
+    }
+
+    fn inherited(vars: &[(&str, &str)]) -> Vec<(OsString, OsString)> {
+        vars.iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect()
+    }
+
+    #[test]
+    fn env_is_merged_onto_the_inherited_environment() {
+        let env = config(json!({ "env": { "NEW": "1" } }))
+            .resolve_env_over(inherited(&[("HOME", "/root")]))
+            .unwrap();
+        assert_eq!(env["HOME"], "/root");
+        assert_eq!(env["NEW"], "1");
+
+        let env = config(json!({ "env": { "HOME": "override" } }))
+            .resolve_env_over(inherited(&[("HOME", "/root")]))
+            .unwrap();
+        assert_eq!(env["HOME"], "override");
+    }
+
+    #[test]
+    fn clear_env_drops_the_inherited_environment() {
+        let env = config(json!({ "clearEnv": true, "env": { "ONLY": 1 } }))
+            .resolve_env_over(inherited(&[("HOME", "/root")]))
+            .unwrap();
+        assert_eq!(env.len(), 1);
+        assert_eq!(env["ONLY"], "1");
+    }
+
+    #[test]
+    fn null_env_value_unsets_the_variable() {
+        let env = config(json!({ "env": { "HOME": null } }))
+            .resolve_env_over(inherited(&[("HOME", "/root"), ("PATH", "/bin")]))
+            .unwrap();
+        assert!(!env.contains_key("HOME"));
+        assert_eq!(env["PATH"], "/bin");
+
+        let env = config(json!({ "clearEnv": true, "env": { "MISSING": null } }))
+            .resolve_env_over(inherited(&[]))
+            .unwrap();
+        assert!(env.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_inherited_variables_are_skipped() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let mut vars = inherited(&[("PATH", "/bin")]);
+        vars.push((OsString::from_vec(b"BAD\xff".to_vec()), "1".into()));
+        vars.push(("LANG".into(), OsString::from_vec(b"x\xfe".to_vec())));
+        let env = config(json!({})).resolve_env_over(vars).unwrap();
+        assert_eq!(env.keys().collect::<Vec<_>>(), ["PATH"]);
+    }
+
+    // envFile во временном каталоге; имя — по тесту, чтобы параллельные тесты не делили файл
+    fn env_file(name: &str, contents: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("rast-dap-{}-{name}.env", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn env_file_and_env_layer_over_a_cleared_environment() {
+        let path = env_file("layers", b"FROM_FILE=1\nSHARED=file\n");
+        let env = config(json!({
+            "clearEnv": true,
+            "envFile": path,
+            "env": { "SHARED": "env", "EXTRA": "2" },
+        }))
+        .resolve_env_over(inherited(&[("HOME", "/root")]))
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            env.into_iter().collect::<Vec<_>>(),
+            [
+                ("EXTRA".to_string(), "2".to_string()),
+                ("FROM_FILE".to_string(), "1".to_string()),
+                ("SHARED".to_string(), "env".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn non_utf8_env_file_is_an_error_naming_the_file() {
+        let path = env_file("binary", b"KEY=\xff\n");
+        let err = config(json!({ "clearEnv": true, "envFile": path }))
+            .resolve_env_over(inherited(&[]))
+            .unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        let message = err.to_string();
+        assert!(
+            message.starts_with(&format!("Cannot read envFile '{path}'")),
+            "{message}"
+        );
+    }
+}
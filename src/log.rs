@@ -1,9 +1,42 @@
+use std::str::FromStr;
+
 use dap::{
     events::{Event, OutputEventBody},
     server::Server,
-    types::OutputEventCategory,
+    types::{OutputEventCategory, Source},
 };
 
+use crate::state::DapState;
+use crate::types::{DapServer, DynResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "trace" => Ok(LogLevel::Trace),
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            other => Err(format!(
+                "Unknown log level '{other}' (expected trace, debug, info, warn or error)"
+            )),
+        }
+    }
+}
+
+// Служебный лог адаптера — сразу в writer. Вывод самой сессии (console_output,
+// diagnostic_output) идёт через очередь событий, по порядку с остальными событиями
 pub(crate) fn dap_log<S: std::io::Read, W: std::io::Write>(
     server: &mut Server<S, W>,
     msg: impl AsRef<str>,
@@ -14,3 +47,137 @@ pub(crate) fn dap_log<S: std::io::Read, W: std::io::Write>(
         ..Default::default()
     }));
 }
+
+// Вывод программы/logpoint'ов: ANSI-цвета отдаём как есть только клиенту с supportsANSIStyling,
+// остальным — вырезаем, иначе в консоли будет мусор вида "\x1b[31m".
+pub(crate) fn styled(ansi_styling: bool, msg: &str) -> String {
+    if ansi_styling {
+        msg.to_string()
+    } else {
+        strip_ansi(msg)
+    }
+}
+
+pub(crate) fn console_output(
+    server: &mut DapServer,
+    st: &DapState,
+    msg: impl AsRef<str>,
+) -> DynResult<()> {
+    let output = styled(st.ansi_styling, msg.as_ref());
+    st.events.push(
+        server,
+        Event::Output(OutputEventBody {
+            category: Some(OutputEventCategory::Console),
+            output: format!("{output}\n"),
+            ..Default::default()
+        }),
+    )
+}
+
+// Сообщение с привязкой к месту в коде: клик по нему в консоли открывает source:line
+pub(crate) fn diagnostic_output(
+    server: &mut DapServer,
+    st: &DapState,
+    msg: impl AsRef<str>,
+    source: Option<Source>,
+    line: i64,
+) -> DynResult<()> {
+    let output = styled(st.ansi_styling, msg.as_ref());
+    st.events.push(
+        server,
+        Event::Output(OutputEventBody {
+            category: Some(OutputEventCategory::Console),
+            output: format!("{output}\n"),
+            source,
+            line: Some(line),
+            column: Some(1),
+            ..Default::default()
+        }),
+    )
+}
+
+// CSI (ESC [ ... финальный байт) и OSC (ESC ] ... BEL/ST); одиночный ESC тоже выкидываем
+pub(crate) fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('[') => {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            Some(_) => {
+                chars.next();
+            }
+            None => {}
+        }
+    }
+    out
+}
+
+// Служебный лог адаптера: пишется в консоль только если level >= порога из DapState
+pub(crate) fn dap_log_at<S: std::io::Read, W: std::io::Write>(
+    server: &mut Server<S, W>,
+    threshold: LogLevel,
+    level: LogLevel,
+    msg: impl AsRef<str>,
+) {
+    if level >= threshold {
+        dap_log(server, msg);
+    }
+}
+
+pub(crate) fn dap_debug<S: std::io::Read, W: std::io::Write>(
+    server: &mut Server<S, W>,
+    threshold: LogLevel,
+    msg: impl AsRef<str>,
+) {
+    dap_log_at(server, threshold, LogLevel::Debug, msg);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csi_sequences_are_removed() {
+        assert_eq!(strip_ansi("\x1b[1;31merror\x1b[0m: x"), "error: x");
+        assert_eq!(strip_ansi("\x1b[2K\x1b[Gdone"), "done");
+    }
+
+    #[test]
+    fn osc_sequences_end_at_bel_or_st() {
+        assert_eq!(strip_ansi("\x1b]0;title\x07text"), "text");
+        assert_eq!(
+            strip_ansi("\x1b]8;;http://x\x1b\\link\x1b]8;;\x1b\\"),
+            "link"
+        );
+    }
+
+    #[test]
+    fn plain_text_and_lone_escapes() {
+        assert_eq!(strip_ansi("мир [ok]"), "мир [ok]");
+        assert_eq!(strip_ansi("a\x1b7b"), "ab");
+        assert_eq!(strip_ansi("end\x1b"), "end");
+    }
+}
@@ -1,35 +1,155 @@
+mod breakpoint_matcher;
+mod capabilities;
+mod cli;
 mod command_handler;
+mod completions;
+mod custom_request;
+mod error;
+mod evaluator;
+mod event_queue;
+mod formatter;
+mod launch_config;
 mod log;
+mod metrics;
+mod modules;
+mod recording;
+mod source_text;
 mod state;
+mod telemetry;
+mod trace;
+mod transport;
 mod types;
 mod utils;
-use crate::command_handler::handle;
-use crate::log::dap_log;
+mod variables_cache;
+use crate::cli::{parse_cli_args, version_string, CliAction, ADAPTER_ID_ENV, USAGE};
+use crate::command_handler::{custom_commands, handle, handle_custom};
+use crate::custom_request::{FrameCapture, RawFrames};
+use crate::log::{dap_log_at, LogLevel};
+use crate::recording::open_input;
 use crate::state::DapState;
-use crate::types::DynResult;
+use crate::trace::{TraceReader, TraceWriter};
+use crate::transport::{PatchWriter, ResponsePatches, RetryReader, SnakeCaseReader};
+use crate::types::{DapServer, DynResult};
+use crate::utils::{error_detail, is_broken_pipe};
+use dap::errors::ServerError;
 use dap::prelude::*;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, BufWriter, Read, Write};
 
 fn main() -> DynResult<()> {
-    let output = BufWriter::new(std::io::stdout());
-    let input = BufReader::new(std::io::stdin());
+    let opts = match parse_cli_args(std::env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("[DAP] {e}");
+            std::process::exit(2);
+        }
+    };
+
+    match opts.action {
+        CliAction::PrintVersion => {
+            println!("{}", version_string());
+            return Ok(());
+        }
+        CliAction::PrintHelp => {
+            println!("{}\n\n{USAGE}", version_string());
+            return Ok(());
+        }
+        CliAction::Run => {}
+    }
+
+    let patches = ResponsePatches::default();
+    let frames = RawFrames::default();
+    let input = match open_input(opts.record.as_deref(), opts.replay.as_deref()) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("[DAP] Cannot open session file: {e}");
+            std::process::exit(2);
+        }
+    };
     let mut state = DapState::new();
-    let mut server = Server::new(input, output);
+    state.custom_commands = custom_commands();
+    state.response_patches = patches.clone();
+    state.log_level = opts.log_level;
+    state.completion_trigger_characters = opts.completion_trigger_characters;
+    state.metrics.enabled = opts.metrics;
+    state.sort_variables = opts.sort_variables;
+    state.max_variable_depth = opts.max_variable_depth;
+    state.stack_depth_known = opts.stack_depth_known;
+    state.telemetry = opts.telemetry;
+    state.line_endings = opts.line_endings;
+    state.threads_cache_ttl = opts.threads_cache_ttl;
+    state
+        .variables_cache
+        .set_capacity(opts.variables_cache_size);
+    let adapter_id = opts
+        .adapter_id
+        .or_else(|| std::env::var(ADAPTER_ID_ENV).ok())
+        .filter(|id| !id.trim().is_empty());
+    if let Some(id) = adapter_id {
+        state.adapter_id = id;
+    }
+    let stdout: Box<dyn Write + Send> = Box::new(std::io::stdout());
+    let mut server = open_server(input, stdout, &patches, &frames);
+    serve(&mut server, &mut state, &frames)
+}
 
+// Весь путь байтов: запись/трасса/перевод ключей на входе, патчи и трасса на выходе
+pub(crate) fn open_server(
+    input: Box<dyn Read>,
+    output: Box<dyn Write + Send>,
+    patches: &ResponsePatches,
+    frames: &RawFrames,
+) -> DapServer {
+    let input = SnakeCaseReader::new(FrameCapture::new(
+        TraceReader::new(RetryReader::new(input)),
+        frames.clone(),
+    ));
+    let output = PatchWriter::new(TraceWriter::new(output), patches.clone());
+    Server::new(BufReader::new(input), BufWriter::new(output))
+}
+
+// Главный цикл: до disconnect, конца входа или закрытого клиентом вывода
+pub(crate) fn serve(
+    server: &mut DapServer,
+    state: &mut DapState,
+    frames: &RawFrames,
+) -> DynResult<()> {
     loop {
-        let req = match server.poll_request()? {
-            Some(req) => req,
-            None => {
+        let polled = server.poll_request();
+        // Тело фрейма, который poll_request только что разобрал или не смог разобрать
+        let frame = frames.next();
+        let result: DynResult<()> = match polled {
+            Ok(Some(req)) => {
+                state.request_frame = frame;
+                handle(req, server, state)
+            }
+            Ok(None) => {
                 eprintln!("No request received, exiting.");
                 break;
             }
+            Err(ServerError::ParseError(e)) => match frame {
+                Some(frame) => handle_custom(&frame, &error_detail(&e), server, state),
+                None => Ok(()),
+            },
+            Err(e) => return Err(e.into()),
         };
 
-        let result: DynResult<()> = handle(req, &mut server, &mut state);
-
         if let Err(e) = result {
+            // Писать в консоль клиента уже бесполезно — одно сообщение в stderr и выход
+            if is_broken_pipe(e.as_ref()) {
+                eprintln!("[DAP] Client closed the output stream, exiting.");
+                break;
+            }
             eprintln!("[DAP] Error processing command: {}", e);
-            dap_log(&mut server, format!("Error: {}", e));
+            dap_log_at(
+                server,
+                state.log_level,
+                LogLevel::Error,
+                format!("Error: {}", e),
+            );
+        }
+
+        if state.disconnected {
+            break;
         }
     }
 
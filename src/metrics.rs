@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CommandTiming {
+    pub(crate) count: u64,
+    pub(crate) total: Duration,
+    pub(crate) min: Duration,
+    pub(crate) max: Duration,
+}
+
+impl CommandTiming {
+    pub(crate) fn avg(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        // Делим в u128: `count as u32` молча обрезал бы счётчик
+        let nanos = self.total.as_nanos() / u128::from(self.count);
+        Duration::from_nanos(nanos as u64)
+    }
+}
+
+// Время обработки по командам; пишется только при --metrics
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    pub(crate) enabled: bool,
+    pub(crate) by_command: BTreeMap<String, CommandTiming>,
+}
+
+impl Metrics {
+    pub(crate) fn record(&mut self, command: &str, elapsed: Duration) {
+        let timing = self
+            .by_command
+            .entry(command.to_string())
+            .or_insert(CommandTiming {
+                count: 0,
+                total: Duration::ZERO,
+                min: elapsed,
+                max: elapsed,
+            });
+        timing.count += 1;
+        timing.total += elapsed;
+        timing.min = timing.min.min(elapsed);
+        timing.max = timing.max.max(elapsed);
+    }
+
+    pub(crate) fn summary(&self) -> String {
+        if !self.enabled {
+            return "Metrics are disabled (start the adapter with --metrics)".to_string();
+        }
+        if self.by_command.is_empty() {
+            return "No commands recorded yet".to_string();
+        }
+        self.by_command
+            .iter()
+            .map(|(name, t)| {
+                format!(
+                    "{name}: count={} min={:?} max={:?} avg={:?}",
+                    t.count,
+                    t.min,
+                    t.max,
+                    t.avg()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_count_min_max_and_average() {
+        let mut metrics = Metrics::default();
+        for ms in [30, 10, 20] {
+            metrics.record("threads", Duration::from_millis(ms));
+        }
+        let t = metrics.by_command["threads"];
+        assert_eq!(t.count, 3);
+        assert_eq!(t.min, Duration::from_millis(10));
+        assert_eq!(t.max, Duration::from_millis(30));
+        assert_eq!(t.avg(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn average_of_a_count_beyond_u32() {
+        let timing = CommandTiming {
+            count: u64::from(u32::MAX) + 1,
+            total: Duration::from_nanos((u64::from(u32::MAX) + 1) * 3),
+            min: Duration::ZERO,
+            max: Duration::ZERO,
+        };
+        assert_eq!(timing.avg(), Duration::from_nanos(3));
+    }
+
+    #[test]
+    fn summary_depends_on_the_flag() {
+        let mut metrics = Metrics::default();
+        assert!(metrics.summary().contains("--metrics"));
+        metrics.enabled = true;
+        assert_eq!(metrics.summary(), "No commands recorded yet");
+        metrics.record("next", Duration::from_millis(1));
+        assert!(metrics.summary().starts_with("next: count=1"));
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::Path;
+
+use dap::events::{Event, ModuleEventBody};
+use dap::types::{Module, ModuleEventReason, ModuleId};
+
+use crate::launch_config::LaunchConfig;
+use crate::state::DapState;
+use crate::types::{DapServer, DynResult};
+
+const DEFAULT_PROGRAM: &str = "debuggee";
+
+// ModuleId из dap не умеет PartialEq, а Number там без значения — сравниваем строки
+fn same_id(a: &ModuleId, b: &ModuleId) -> bool {
+    match (a, b) {
+        (ModuleId::String(a), ModuleId::String(b)) => a == b,
+        (ModuleId::Number, ModuleId::Number) => true,
+        _ => false,
+    }
+}
+
+// Модуль самой отлаживаемой программы: id = путь из "program" launch-конфига
+pub(crate) fn program_module(config: Option<&LaunchConfig>) -> Module {
+    let path = config.and_then(|c| c.program.clone());
+    let name = path
+        .as_deref()
+        .and_then(|p| Path::new(p).file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| DEFAULT_PROGRAM.to_string());
+
+    Module {
+        id: ModuleId::String(path.clone().unwrap_or_else(|| name.clone())),
+        name,
+        path,
+        is_optimized: Some(false),
+        is_user_code: Some(true),
+        version: None,
+        symbol_status: Some("Symbols loaded".to_string()),
+        symbol_file_path: None,
+        date_time_stamp: None,
+        address_range: None,
+    }
+}
+
+fn send_module_event(
+    server: &mut DapServer,
+    st: &DapState,
+    reason: ModuleEventReason,
+    module: Module,
+) -> DynResult<()> {
+    st.events
+        .push(server, Event::Module(ModuleEventBody { reason, module }))
+}
+
+// Бэкенд сообщил о загрузке библиотеки. Уже известный id — это changed, а не второй new
+pub(crate) fn load_module(
+    server: &mut DapServer,
+    st: &mut DapState,
+    module: Module,
+) -> DynResult<()> {
+    if st.modules.iter().any(|m| same_id(&m.id, &module.id)) {
+        return change_module(server, st, module);
+    }
+    st.modules.push(module.clone());
+    send_module_event(server, st, ModuleEventReason::New, module)
+}
+
+pub(crate) fn change_module(
+    server: &mut DapServer,
+    st: &mut DapState,
+    module: Module,
+) -> DynResult<()> {
+    match st.modules.iter_mut().find(|m| same_id(&m.id, &module.id)) {
+        Some(existing) => *existing = module.clone(),
+        None => st.modules.push(module.clone()),
+    }
+    send_module_event(server, st, ModuleEventReason::Changed, module)
+}
+
+// Неизвестный id молча игнорируем — клиенту нечего удалять
+pub(crate) fn unload_module(
+    server: &mut DapServer,
+    st: &mut DapState,
+    id: &ModuleId,
+) -> DynResult<()> {
+    let Some(pos) = st.modules.iter().position(|m| same_id(&m.id, id)) else {
+        return Ok(());
+    };
+    let module = st.modules.remove(pos);
+    send_module_event(server, st, ModuleEventReason::Removed, module)
+}
+
+// После restart программа та же — changed; другой "program" — старый удалить, новый загрузить
+pub(crate) fn reload_program_module(
+    server: &mut DapServer,
+    st: &mut DapState,
+    previous: Module,
+) -> DynResult<()> {
+    let current = program_module(st.launch_config.as_ref());
+    if same_id(&previous.id, &current.id) {
+        return change_module(server, st, current);
+    }
+    unload_module(server, st, &previous.id)?;
+    load_module(server, st, current)
+}
+
+pub(crate) fn unload_all_modules(server: &mut DapServer, st: &mut DapState) -> DynResult<()> {
+    while let Some(module) = st.modules.pop() {
+        send_module_event(server, st, ModuleEventReason::Removed, module)?;
+    }
+    Ok(())
+}
+
+// Страница для modules-запроса: moduleCount 0 или отсутствует = все до конца
+pub(crate) fn modules_page(st: &DapState, start: Option<i64>, count: Option<i64>) -> Vec<Module> {
+    let start = start.unwrap_or(0).max(0) as usize;
+    let count = match count {
+        Some(c) if c > 0 => c as usize,
+        _ => usize::MAX,
+    };
+    st.modules.iter().skip(start).take(count).cloned().collect()
+}
@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io::{Read, Write};
+
+// --record: входящий поток пишется в файл байт в байт, вместе с заголовками.
+// Этот же файл годится как вход для --replay — воспроизвести сессию пользователя.
+pub(crate) struct RecordingReader<R: Read> {
+    inner: R,
+    file: File,
+}
+
+impl<R: Read> RecordingReader<R> {
+    pub(crate) fn new(inner: R, file: File) -> Self {
+        Self { inner, file }
+    }
+}
+
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        // Сразу на диск: адаптер может упасть, а запись нужна как раз для таких случаев
+        self.file.write_all(&buf[..n])?;
+        self.file.flush()?;
+        Ok(n)
+    }
+}
+
+// Откуда читать запросы: stdin живого клиента или файл записи
+pub(crate) fn open_input(
+    record: Option<&str>,
+    replay: Option<&str>,
+) -> std::io::Result<Box<dyn Read>> {
+    let input: Box<dyn Read> = match replay {
+        Some(path) => Box::new(File::open(path)?),
+        None => Box::new(std::io::stdin()),
+    };
+    Ok(match record {
+        Some(path) => Box::new(RecordingReader::new(input, File::create(path)?)),
+        None => input,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::command_handler::custom_commands;
+    use crate::custom_request::RawFrames;
+    use crate::state::DapState;
+    use crate::trace::FrameSplitter;
+    use crate::transport::ResponsePatches;
+    use crate::{open_server, serve};
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Прогон адаптера целиком, как из main; результат — тела исходящих фреймов
+    fn run(input: Box<dyn Read>) -> Vec<String> {
+        let patches = ResponsePatches::default();
+        let frames = RawFrames::default();
+        let output = Output::default();
+        let mut server = open_server(input, Box::new(output.clone()), &patches, &frames);
+        let mut state = DapState::new();
+        state.custom_commands = custom_commands();
+        state.response_patches = patches;
+        state.telemetry = false;
+        serve(&mut server, &mut state, &frames).unwrap();
+        drop(server);
+        let bytes = output.0.lock().unwrap().clone();
+        FrameSplitter::default().push(&bytes)
+    }
+
+    #[test]
+    fn recorded_session_replays_to_the_same_output() {
+        let requests: Vec<(&str, Value)> = vec![
+            ("initialize", json!({ "adapterID": "rast" })),
+            (
+                "launch",
+                json!({ "program": "/tmp/demo", "stopOnEntry": true }),
+            ),
+            ("configurationDone", json!({})),
+            ("threads", json!({})),
+            ("rast/reloadSymbols", Value::Null),
+            ("disconnect", json!({})),
+        ];
+        let input: String = requests
+            .into_iter()
+            .enumerate()
+            .map(|(seq, (command, arguments))| {
+                let body = json!({
+                    "seq": seq + 1, "type": "request", "command": command, "arguments": arguments,
+                })
+                .to_string();
+                format!("Content-Length: {}\r\n\r\n{body}", body.len())
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("rast-dap-{}.session", std::process::id()));
+
+        let reader = RecordingReader::new(
+            std::io::Cursor::new(input.clone()),
+            File::create(&path).unwrap(),
+        );
+        let live = run(Box::new(reader));
+        let replayed = run(open_input(None, path.to_str()).unwrap());
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recorded, input);
+        assert!(live.len() > 6, "{live:?}");
+        assert_eq!(replayed, live);
+    }
+}
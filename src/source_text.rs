@@ -0,0 +1,231 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+// Как отдавать содержимое в ответе на source: как лежит на диске или с \n вместо \r\n/\r
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LineEndings {
+    Keep,
+    Lf,
+}
+
+impl FromStr for LineEndings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "keep" => Ok(LineEndings::Keep),
+            "lf" => Ok(LineEndings::Lf),
+            other => Err(format!(
+                "Unknown line endings '{other}' (expected keep or lf)"
+            )),
+        }
+    }
+}
+
+// Отпечаток файла на диске (mtime + размер): не совпал с закэшированным — файл перечитываем
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    pub(crate) fn of(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let meta = std::fs::metadata(path)?;
+        Ok(Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        })
+    }
+}
+
+// Где в строке можно поставить брейк: начало каждого statement'а (первый символ и после ';')
+// и имя каждого вызова в цепочке (.map(...).filter(...)) — это и есть inline-брейки.
+// Не парсер: строки и // комментарии пропускаем, остальное смотрим по символам.
+// Колонки 0-based, в UTF-16 code units, как их ждёт клиент.
+pub(crate) fn breakpoint_columns(line: &str) -> Vec<i64> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut columns = Vec::new();
+    let mut column = 0;
+    let mut statement_start = true;
+    let mut in_string = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if in_string {
+            match c {
+                // Экранированный символ проходим вместе с обратным слешем
+                '\\' => {
+                    column += 1;
+                    i += 1;
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            break;
+        } else if !c.is_whitespace() {
+            if statement_start && !matches!(c, '}' | ')' | ']' | ',') {
+                columns.push(column as i64);
+            }
+            statement_start = c == ';' || c == '{';
+            match c {
+                '"' => in_string = true,
+                '.' if is_call(&chars[i + 1..]) => {
+                    columns.push((column + c.len_utf16()) as i64);
+                }
+                _ => {}
+            }
+        }
+        column += chars.get(i).map_or(0, |c| c.len_utf16());
+        i += 1;
+    }
+    columns.dedup();
+    columns
+}
+
+// После точки идёт ident( или ident::<..>( — вызов метода, а не поле и не 1.5
+fn is_call(rest: &[char]) -> bool {
+    let ident = rest
+        .iter()
+        .take_while(|c| c.is_alphanumeric() || **c == '_')
+        .count();
+    let after = &rest[ident..];
+    ident > 0
+        && !rest[0].is_ascii_digit()
+        && (after.first() == Some(&'(') || after.starts_with(&[':', ':']))
+}
+
+// Исходник как есть плюс индекс начал строк. Концом строки считаем \r\n, \n и одиночный \r,
+// так что номер строки не зависит от того, в каком редакторе файл сохраняли.
+#[derive(Debug, Clone)]
+pub(crate) struct SourceText {
+    raw: String,
+    // Смещения в raw: (начало строки, конец без терминатора)
+    lines: Vec<(usize, usize)>,
+}
+
+impl SourceText {
+    pub(crate) fn new(raw: String) -> Self {
+        let bytes = raw.as_bytes();
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\n' => {
+                    lines.push((start, i));
+                    start = i + 1;
+                }
+                b'\r' => {
+                    lines.push((start, i));
+                    if bytes.get(i + 1) == Some(&b'\n') {
+                        i += 1;
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        // Хвост без перевода строки — тоже строка; пустой хвост после последнего \n — нет
+        if start < bytes.len() {
+            lines.push((start, bytes.len()));
+        }
+        Self { raw, lines }
+    }
+
+    pub(crate) fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    // 1-based, без терминатора
+    pub(crate) fn line(&self, line: usize) -> Option<&str> {
+        let &(start, end) = self.lines.get(line.checked_sub(1)?)?;
+        Some(&self.raw[start..end])
+    }
+
+    pub(crate) fn content(&self, endings: LineEndings) -> String {
+        match endings {
+            LineEndings::Keep => self.raw.clone(),
+            LineEndings::Lf => {
+                let mut out = String::with_capacity(self.raw.len());
+                for &(start, end) in &self.lines {
+                    out.push_str(&self.raw[start..end]);
+                    out.push('\n');
+                }
+                // Последняя строка без терминатора так и остаётся без него
+                if !self.raw.ends_with(['\n', '\r']) {
+                    out.pop();
+                }
+                out
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_and_chained_calls_are_breakpoint_columns() {
+        assert_eq!(breakpoint_columns("    let x = 1;"), [4]);
+        assert_eq!(breakpoint_columns("a(); b();"), [0, 5]);
+        assert_eq!(
+            breakpoint_columns("v.iter().map(|x| x + 1).sum();"),
+            [0, 2, 9, 24]
+        );
+        assert_eq!(breakpoint_columns("v.collect::<Vec<_>>();"), [0, 2]);
+    }
+
+    #[test]
+    fn fields_numbers_strings_and_comments_are_skipped() {
+        assert_eq!(breakpoint_columns("a.b.c; 1.5"), [0, 7]);
+        assert_eq!(breakpoint_columns(r#"println!("a.b(); \"c\"; d()");"#), [0]);
+        assert_eq!(breakpoint_columns("x(); // y(); z()"), [0]);
+        assert_eq!(breakpoint_columns("} else {"), Vec::<i64>::new());
+        assert_eq!(breakpoint_columns(""), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn columns_count_utf16_units() {
+        assert_eq!(breakpoint_columns("let s = \"😀\"; f();"), [0, 14]);
+    }
+
+    #[test]
+    fn lines_split_on_every_terminator() {
+        let text = SourceText::new("a\r\nb\rc\nd".to_string());
+        assert_eq!(text.line_count(), 4);
+        assert_eq!(text.line(1), Some("a"));
+        assert_eq!(text.line(2), Some("b"));
+        assert_eq!(text.line(4), Some("d"));
+        assert_eq!(text.line(0), None);
+        assert_eq!(text.line(5), None);
+    }
+
+    #[test]
+    fn trailing_newline_is_not_a_line() {
+        let text = SourceText::new("a\n\nb\n".to_string());
+        assert_eq!(text.line_count(), 3);
+        assert_eq!(text.line(2), Some(""));
+        assert_eq!(SourceText::new(String::new()).line_count(), 0);
+    }
+
+    #[test]
+    fn content_keeps_or_normalizes_endings() {
+        let text = SourceText::new("a\r\nb\rc".to_string());
+        assert_eq!(text.content(LineEndings::Keep), "a\r\nb\rc");
+        assert_eq!(text.content(LineEndings::Lf), "a\nb\nc");
+        let text = SourceText::new("a\r\n".to_string());
+        assert_eq!(text.content(LineEndings::Lf), "a\n");
+    }
+
+    #[test]
+    fn line_endings_parse_case_insensitively() {
+        assert_eq!("LF".parse(), Ok(LineEndings::Lf));
+        assert_eq!("keep".parse(), Ok(LineEndings::Keep));
+        assert!("crlf".parse::<LineEndings>().is_err());
+    }
+}
@@ -1,42 +1,1614 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use dap::types::Source;
+use dap::events::Event;
+use dap::requests::{InitializeArguments, PathFormat};
+use dap::types::{
+    CustomValue, DataBreakpoint, ExceptionDetails, FunctionBreakpoint, Module, Source,
+    SourceBreakpoint, SteppingGranularity, Thread, ValueFormat, Variable,
+};
+
+use crate::breakpoint_matcher::{BreakpointDecision, BreakpointMatcher, DefaultMatcher};
+use crate::completions::default_trigger_characters;
+use crate::custom_request::CustomHandler;
+use crate::error::HandlerError;
+use crate::evaluator::{value_to_string, DefaultEvaluator, ExpressionEvaluator};
+use crate::event_queue::EventQueue;
+use crate::formatter::{DefaultFormatter, VariableFormatter, DEFAULT_VALUE_LIMIT};
+use crate::launch_config::LaunchConfig;
+use crate::log::LogLevel;
+use crate::metrics::Metrics;
+use crate::source_text::{FileStamp, LineEndings, SourceText};
+use crate::trace::tracing_enabled;
+use crate::transport::ResponsePatches;
+use crate::types::DynResult;
+use crate::utils::{
+    file_uri_to_path, format_address, map_path, normalize_path, path_to_file_uri, reverse_map,
+};
+use crate::variables_cache::VariablesCache;
+
+// Пространства id не пересекаются, чтобы клиент, путающий виды id, не попал в чужой объект:
+//   потоки              1 .. 1_000
+//   брейки         10_000 .. 1_000_000  (общий счётчик для всех файлов и функций)
+//   variablesReference  1_000_000 .. 2^32, монотонно
+//   фреймы         от 2^32  (FRAME_ID_BASE + остановка * FRAMES_PER_STOP + глубина)
+// Новые id создавать только через alloc_*/frame_id ниже; threadId от клиента — только
+// из диапазона потоков (is_thread_id).
+const THREAD_ID_BASE: i64 = 1;
+const THREAD_ID_END: i64 = 1_000;
+// Номер остановки входит в id фрейма целиком: id с прошлой остановки не совпадает
+// ни с одним новым, frame() по нему ничего не находит
+const FRAME_ID_BASE: i64 = 1 << 32;
+const FRAMES_PER_STOP: i64 = 100;
+const BREAKPOINT_ID_BASE: i64 = 10_000;
+// Ссылки на переменные никогда не переиспользуются между остановками,
+// поэтому ссылка с прошлой остановки просто ничего не находит.
+const VARS_REF_BASE: i64 = 1_000_000;
+const DEFAULT_MAIN_THREAD_NAME: &str = "Main Thread";
+const DEFAULT_ADAPTER_ID: &str = "rust-dap-adapter";
+// Дальше этой вложенности (считая от scope'а) детей не отдаём — защита от циклических данных
+pub(crate) const DEFAULT_MAX_VARIABLE_DEPTH: usize = 32;
+const MAX_DEPTH_PLACEHOLDER: &str = "… (max depth reached)";
+// Ребёнок ссылается на одного из своих предков: раскрывать его — ходить по кругу
+const CYCLE_PLACEHOLDER: &str = "↺ (cycle)";
+// Клиенты опрашивают threads пачками; в пределах этого окна отдаём прошлый список
+pub(crate) const DEFAULT_THREADS_CACHE_TTL: Duration = Duration::from_millis(200);
+// retainConsoleOnRestart: сколько последних строк вывода переживают restart
+const CONSOLE_HISTORY_LIMIT: usize = 500;
+pub(crate) const RESTART_SEPARATOR: &str = "──────── Restarted ────────";
+
+// Необязательные запросы, которые клиенты шлют "на пробу": пустой успешный ответ вместо ошибки
+const SOFT_UNSUPPORTED: [&str; 1] = ["loadedSources"];
+
+#[derive(Debug, Clone)]
+pub(crate) struct ThreadState {
+    pub(crate) name: String,
+    pub(crate) running: bool,
+}
+
+// Фрейм текущей остановки; call_stack[0] — самый верхний
+#[derive(Debug, Clone)]
+pub(crate) struct CallFrame {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    // В путях debuggee; None — источник фрейма неизвестен
+    pub(crate) source: Option<Source>,
+    pub(crate) line: i64,
+    pub(crate) column: i64,
+    pub(crate) arguments_ref: i64,
+    pub(crate) locals_ref: i64,
+    // Имя модуля (программы или библиотеки), в котором стоит фрейм
+    pub(crate) module: Option<String>,
+    // Program counter; None — бэкенд его не знает (нет символов)
+    pub(crate) pc: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct StoredBreakpoint {
+    pub(crate) id: i64,
+    pub(crate) line: i64,
+    pub(crate) column: Option<i64>,
+    pub(crate) condition: Option<String>,
+    pub(crate) log_message: Option<String>,
+    pub(crate) hit_condition: Option<String>,
+    // Сколько раз условие брейка выполнилось; обнуляется на restart
+    pub(crate) hits: u64,
+}
+
+impl StoredBreakpoint {
+    pub(crate) fn from_source(id: i64, bp: &SourceBreakpoint) -> Self {
+        Self {
+            id,
+            line: bp.line,
+            column: bp.column,
+            condition: bp.condition.clone().filter(|c| !c.trim().is_empty()),
+            log_message: bp.log_message.clone(),
+            hit_condition: bp.hit_condition.clone().filter(|c| !c.trim().is_empty()),
+            hits: 0,
+        }
+    }
+}
+
+// verified не храним: он зависит от того, загружены ли уже символы (DapState.symbols)
+#[derive(Debug, Clone)]
+pub(crate) struct StoredFunctionBreakpoint {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+}
+
+// dataId — наш же формат из dataBreakpointInfo: `var:<evaluateName>` привязан к имени
+// и переживает restart, `addr:0x...` — к адресу, который в новом запуске уже другой.
+pub(crate) const DATA_ID_VAR_PREFIX: &str = "var:";
+pub(crate) const DATA_ID_ADDR_PREFIX: &str = "addr:";
+
+#[derive(Debug, Clone)]
+pub(crate) struct StoredDataBreakpoint {
+    pub(crate) id: i64,
+    pub(crate) data_id: String,
+}
+
+// Исключение (паника), на котором стоим. inner — цепочка "caused by" от внешнего к корню
+#[derive(Debug, Clone)]
+pub(crate) struct ExceptionRecord {
+    pub(crate) type_name: String,
+    pub(crate) message: String,
+    pub(crate) stack_trace: Option<String>,
+    pub(crate) inner: Vec<ExceptionRecord>,
+}
+
+impl ExceptionRecord {
+    pub(crate) fn to_details(&self) -> ExceptionDetails {
+        ExceptionDetails {
+            message: Some(self.message.clone()),
+            type_name: Some(self.type_name.clone()),
+            full_type_name: None,
+            evaluate_name: None,
+            stack_trace: self.stack_trace.clone(),
+            inner_exception: (!self.inner.is_empty())
+                .then(|| self.inner.iter().map(Self::to_details).collect()),
+        }
+    }
+}
+
+pub(crate) fn is_persistent_data_id(data_id: &str) -> bool {
+    data_id.starts_with(DATA_ID_VAR_PREFIX)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RefOrigin {
+    pub(crate) frame_id: i64,
+    pub(crate) depth: usize,
+    // None — корень: arguments/locals фрейма
+    pub(crate) parent: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Symbol {
+    pub(crate) address: u64,
+    pub(crate) module: String,
+}
+
+// Брейки ставятся либо по path, либо (для виртуальных/сгенерированных source)
+// по sourceReference — path приоритетнее, если есть оба. Path в ключе нормализован,
+// исходный путь для ответов клиенту остаётся в самом Source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum SourceKey {
+    Path(String),
+    Reference(i32),
+}
+
+impl SourceKey {
+    pub(crate) fn of(source: &Source) -> Option<Self> {
+        if let Some(path) = &source.path {
+            return Some(SourceKey::Path(normalize_path(path)));
+        }
+        match source.source_reference {
+            Some(r) if r > 0 => Some(SourceKey::Reference(r)),
+            _ => None,
+        }
+    }
+}
+
+// Uninitialized → Initialized → Configured → Running. launch может прийти и до
+// configurationDone (VS Code шлёт их параллельно), поэтому Running достижим из обоих.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SessionPhase {
+    Uninitialized,
+    Initialized,
+    Configured,
+    Running,
+}
 
-#[derive(Default, Debug)]
 pub(crate) struct DapState {
+    pub(crate) log_level: LogLevel,
+    // Наш id для клиента (--adapter-id); не путать с adapterID из InitializeArguments
+    pub(crate) adapter_id: String,
+    // false (--unknown-stack-depth) — глубину стека бэкенд дёшево не знает, totalFrames
+    // не шлём: клиент дочитывает стек страницами, пока не придёт неполная
+    pub(crate) stack_depth_known: bool,
+    // --no-telemetry выключает telemetry-события целиком
+    pub(crate) telemetry: bool,
+    pub(crate) metrics: Metrics,
+    // RUST_DAP_TRACE: помимо фреймов — итоги сессии на disconnect
+    pub(crate) tracing: bool,
+    // Для итогов сессии в trace-логе
+    pub(crate) started_at: Instant,
+    // launch/restart, после которого ещё не было остановки (для firstStop в телеметрии)
+    pub(crate) launched_at: Option<Instant>,
+    pub(crate) client: Option<InitializeArguments>,
+    // pathFormat из initialize: при uri Source.path в обе стороны — file://-URI
+    pub(crate) path_format: PathFormat,
+    // Локаль клиента из initialize, на будущее для локализации сообщений
+    pub(crate) locale: Option<String>,
+    pub(crate) phase: SessionPhase,
+    // configurationDone уже был (phase Running этого не различает)
+    pub(crate) configured: bool,
+    // Stopped/Continued, случившиеся до configurationDone: уйдут сразу после него
+    pub(crate) deferred_run_events: Vec<Event>,
+    // stopOnEntry: остановка ждёт, пока будут и launch, и configurationDone
+    pub(crate) entry_stop_pending: bool,
+    pub(crate) entry_continue_after: Option<Duration>,
+    // Когда таймер entryContinueAfter отправил Continued — с этого момента потоки идут
+    pub(crate) entry_continue_at: Option<Instant>,
+    // Поколение таймера entryContinueAfter: таймер старого поколения молчит
+    pub(crate) entry_timer_epoch: Arc<AtomicU64>,
+    pub(crate) ansi_styling: bool,
+    // supportsTelemetry из initialize: поля нет в спецификации, клиент объявляет его сам
+    pub(crate) client_telemetry: bool,
+    // Тело текущего запроса, как его прислал клиент: dap выбрасывает поля не из спецификации
+    pub(crate) request_frame: Option<String>,
+    pub(crate) completion_trigger_characters: Vec<String>,
+    pub(crate) launch_config: Option<LaunchConfig>,
+    // Текущий запрос уже получил ответ (respond_success/respond_error)
+    pub(crate) answered: bool,
+    // Сессия начата attach'ем: debuggee не наш, по умолчанию disconnect его не убивает
+    pub(crate) attached: bool,
+    pub(crate) disconnected: bool,
+    // Вывод прошлых запусков для retainConsoleOnRestart, не больше CONSOLE_HISTORY_LIMIT строк
+    pub(crate) console_history: VecDeque<String>,
     pub(crate) main_thread_id: i64,
+    pub(crate) main_thread_name: String,
+    pub(crate) threads: BTreeMap<i64, ThreadState>,
+    // Последний continue/step был singleThread: потоки ходят по отдельности,
+    // и pause тоже останавливает только запрошенный
+    pub(crate) single_thread_resume: bool,
+    // Готовый ответ на threads и когда он собран; None = список потоков менялся, собрать заново
+    threads_cache: Option<(Instant, Vec<Thread>)>,
+    pub(crate) threads_cache_ttl: Duration,
+    pub(crate) modules: Vec<Module>,
+    // Внутри адаптера source хранится в путях debuggee, клиенту отдаётся через to_client
     pub(crate) current_source: Option<Source>,
+    pub(crate) path_mappings: Vec<(String, String)>,
+    pub(crate) adapter_data_by_source: HashMap<SourceKey, CustomValue>,
+    // Прочитанные исходники по нормализованному пути debuggee вместе с отпечатком файла
+    pub(crate) source_texts: HashMap<String, (FileStamp, SourceText)>,
+    pub(crate) line_endings: LineEndings,
     pub(crate) stopped_line: i64,
     pub(crate) stopped_column: i64,
-    pub(crate) breakpoints_by_path: HashMap<String, Vec<i64>>,
-    pub(crate) vars_ref: i64,
+    pub(crate) stopped_instruction: i64,
+    // Брейки, на которых стоим (hitBreakpointIds); пусто — остановка не на брейке
+    pub(crate) stopped_breakpoint_ids: Vec<i64>,
+    pub(crate) breakpoints_by_path: HashMap<String, Vec<StoredBreakpoint>>,
+    pub(crate) breakpoints_by_ref: HashMap<i32, Vec<StoredBreakpoint>>,
+    pub(crate) next_breakpoint_id: i64,
+    pub(crate) exception_filters: Vec<(String, Option<String>)>,
+    pub(crate) function_breakpoints: Vec<StoredFunctionBreakpoint>,
+    pub(crate) data_breakpoints: Vec<StoredDataBreakpoint>,
+    // Для exceptionInfo; живёт до следующей остановки
+    pub(crate) last_exception: Option<ExceptionRecord>,
+    // Имена функций, известные бэкенду; пусто, пока программа не загружена
+    pub(crate) symbols: BTreeMap<String, Symbol>,
+    pub(crate) call_stack: Vec<CallFrame>,
+    // Сколько было остановок: входит в id фреймов
+    pub(crate) stop_epoch: u64,
+    pub(crate) variables: HashMap<i64, Vec<Variable>>,
+    // variablesReference → откуда он: фрейм, глубина от scope'а, родитель
+    pub(crate) ref_frames: HashMap<i64, RefOrigin>,
+    pub(crate) variables_cache: VariablesCache,
+    pub(crate) sort_variables: bool,
+    pub(crate) max_variable_depth: usize,
+    pub(crate) value_limit: usize,
+    pub(crate) formatter: Box<dyn VariableFormatter>,
+    pub(crate) next_vars_ref: i64,
+    pub(crate) evaluator: Box<dyn ExpressionEvaluator>,
+    pub(crate) breakpoint_matcher: Box<dyn BreakpointMatcher>,
+    pub(crate) soft_unsupported: HashSet<String>,
+    // Custom-запросы по имени команды (см. custom_request)
+    pub(crate) custom_commands: BTreeMap<&'static str, CustomHandler>,
+    pub(crate) events: EventQueue,
+    // Поля ответа, которые dap сериализует не так (см. transport::ResponsePatches)
+    pub(crate) response_patches: ResponsePatches,
+}
+
+// Компактный дамп для trace-лога: коллекции только размером, плюс место остановки.
+// Полный дамп всех полей — verbose_debug().
+impl std::fmt::Debug for DapState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let breakpoints: usize = self
+            .breakpoints_by_path
+            .values()
+            .chain(self.breakpoints_by_ref.values())
+            .map(Vec::len)
+            .sum();
+        let files = self.breakpoints_by_path.len() + self.breakpoints_by_ref.len();
+        let source = self
+            .current_source
+            .as_ref()
+            .and_then(|s| s.path.as_deref().or(s.name.as_deref()))
+            .unwrap_or("<none>");
+
+        write!(
+            f,
+            "DapState {{ phase: {:?}, configured: {}, threads: {}, modules: {}, \
+             breakpoints: {breakpoints} across {files} files, function_breakpoints: {}, \
+             symbols: {}, stopped at {source}:{}:{}, frames: {}, variables: {} ({} cached) }}",
+            self.phase,
+            self.configured,
+            self.threads.len(),
+            self.modules.len(),
+            self.function_breakpoints.len(),
+            self.symbols.len(),
+            self.stopped_line,
+            self.stopped_column,
+            self.call_stack.len(),
+            self.variables.len(),
+            self.variables_cache.len(),
+        )
+    }
+}
+
+struct VerboseState<'a>(&'a DapState);
+
+impl std::fmt::Debug for VerboseState<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let st = self.0;
+        f.debug_struct("DapState")
+            .field("log_level", &st.log_level)
+            .field("adapter_id", &st.adapter_id)
+            .field("stack_depth_known", &st.stack_depth_known)
+            .field("telemetry", &st.telemetry)
+            .field("metrics", &st.metrics)
+            .field("tracing", &st.tracing)
+            .field("started_at", &st.started_at)
+            .field("launched_at", &st.launched_at)
+            .field("client", &st.client)
+            .field("path_format", &st.path_format)
+            .field("locale", &st.locale)
+            .field("phase", &st.phase)
+            .field("configured", &st.configured)
+            .field("deferred_run_events", &st.deferred_run_events)
+            .field("entry_stop_pending", &st.entry_stop_pending)
+            .field("entry_continue_after", &st.entry_continue_after)
+            .field("entry_continue_at", &st.entry_continue_at)
+            .field("entry_timer_epoch", &st.entry_timer_epoch)
+            .field("ansi_styling", &st.ansi_styling)
+            .field("client_telemetry", &st.client_telemetry)
+            .field("request_frame", &st.request_frame)
+            .field(
+                "completion_trigger_characters",
+                &st.completion_trigger_characters,
+            )
+            .field("launch_config", &st.launch_config)
+            .field("answered", &st.answered)
+            .field("attached", &st.attached)
+            .field("disconnected", &st.disconnected)
+            .field("console_history", &st.console_history.len())
+            .field("main_thread_id", &st.main_thread_id)
+            .field("main_thread_name", &st.main_thread_name)
+            .field("threads", &st.threads)
+            .field("single_thread_resume", &st.single_thread_resume)
+            .field("threads_cache", &st.threads_cache)
+            .field("threads_cache_ttl", &st.threads_cache_ttl)
+            .field("modules", &st.modules)
+            .field("current_source", &st.current_source)
+            .field("path_mappings", &st.path_mappings)
+            .field("adapter_data_by_source", &st.adapter_data_by_source)
+            .field("source_texts", &st.source_texts.keys())
+            .field("line_endings", &st.line_endings)
+            .field("stopped_line", &st.stopped_line)
+            .field("stopped_column", &st.stopped_column)
+            .field("stopped_instruction", &st.stopped_instruction)
+            .field("stopped_breakpoint_ids", &st.stopped_breakpoint_ids)
+            .field("breakpoints_by_path", &st.breakpoints_by_path)
+            .field("breakpoints_by_ref", &st.breakpoints_by_ref)
+            .field("next_breakpoint_id", &st.next_breakpoint_id)
+            .field("exception_filters", &st.exception_filters)
+            .field("function_breakpoints", &st.function_breakpoints)
+            .field("data_breakpoints", &st.data_breakpoints)
+            .field("last_exception", &st.last_exception)
+            .field("symbols", &st.symbols)
+            .field("call_stack", &st.call_stack)
+            .field("stop_epoch", &st.stop_epoch)
+            .field("variables", &st.variables)
+            .field("ref_frames", &st.ref_frames)
+            .field("variables_cache", &st.variables_cache)
+            .field("sort_variables", &st.sort_variables)
+            .field("max_variable_depth", &st.max_variable_depth)
+            .field("value_limit", &st.value_limit)
+            .field("formatter", &st.formatter)
+            .field("next_vars_ref", &st.next_vars_ref)
+            .field("evaluator", &st.evaluator)
+            .field("breakpoint_matcher", &st.breakpoint_matcher)
+            .field("soft_unsupported", &st.soft_unsupported)
+            .field(
+                "custom_commands",
+                &st.custom_commands.keys().collect::<Vec<_>>(),
+            )
+            .field("events", &st.events)
+            .field("response_patches", &st.response_patches)
+            .finish()
+    }
+}
+
+impl Default for DapState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DapState {
+    // Всё состояние целиком, как было у derive(Debug) — для `$state full` в REPL
+    pub(crate) fn verbose_debug(&self) -> String {
+        format!("{:#?}", VerboseState(self))
+    }
+
+    // `$state` в REPL: то, что обычно ищут в дампе, одним читаемым блоком
+    pub(crate) fn state_summary(&self) -> String {
+        let mut lines = vec![format!(
+            "Phase: {:?}{}",
+            self.phase,
+            if self.configured { " (configured)" } else { "" }
+        )];
+
+        let source = self
+            .current_source
+            .as_ref()
+            .and_then(|s| s.path.as_deref().or(s.name.as_deref()))
+            .unwrap_or("<unknown source>");
+        match self.call_stack.first() {
+            Some(top) => lines.push(format!(
+                "Stopped in {} at {source}:{}:{} (instruction {})",
+                top.name, self.stopped_line, self.stopped_column, self.stopped_instruction
+            )),
+            None => lines.push("Not stopped".to_string()),
+        }
+
+        lines.push(format!("Threads ({}):", self.threads.len()));
+        for (id, thread) in &self.threads {
+            let status = if thread.running { "running" } else { "stopped" };
+            lines.push(format!("  {id} {} [{status}]", thread.name));
+        }
+
+        let mut files: Vec<(String, usize)> = self
+            .breakpoints_by_path
+            .iter()
+            .map(|(path, bps)| (path.clone(), bps.len()))
+            .chain(
+                self.breakpoints_by_ref
+                    .iter()
+                    .map(|(r, bps)| (format!("<source reference {r}>"), bps.len())),
+            )
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        files.sort();
+        lines.push(format!("Breakpoints in {} files:", files.len()));
+        for (file, count) in files {
+            lines.push(format!("  {file}: {count}"));
+        }
+        lines.push(format!(
+            "Function breakpoints: {}, data breakpoints: {}, exception filters: {}",
+            self.function_breakpoints.len(),
+            self.data_breakpoints.len(),
+            self.exception_filters.len()
+        ));
+        lines.push(format!("Modules: {}", self.modules.len()));
+        lines.join("\n")
+    }
+
     pub(crate) fn new() -> Self {
         Self {
-            main_thread_id: 1,
+            log_level: LogLevel::Debug,
+            adapter_id: DEFAULT_ADAPTER_ID.to_string(),
+            stack_depth_known: true,
+            telemetry: true,
+            metrics: Metrics::default(),
+            tracing: tracing_enabled(),
+            started_at: Instant::now(),
+            launched_at: None,
+            client: None,
+            path_format: PathFormat::Path,
+            locale: None,
+            phase: SessionPhase::Uninitialized,
+            configured: false,
+            deferred_run_events: Vec::new(),
+            entry_stop_pending: false,
+            entry_continue_after: None,
+            entry_continue_at: None,
+            entry_timer_epoch: Arc::default(),
+            ansi_styling: false,
+            client_telemetry: false,
+            request_frame: None,
+            completion_trigger_characters: default_trigger_characters(),
+            launch_config: None,
+            answered: false,
+            attached: false,
+            disconnected: false,
+            console_history: VecDeque::new(),
+            main_thread_id: THREAD_ID_BASE,
+            main_thread_name: DEFAULT_MAIN_THREAD_NAME.to_string(),
+            threads: Self::initial_threads(THREAD_ID_BASE, DEFAULT_MAIN_THREAD_NAME),
+            single_thread_resume: false,
+            threads_cache: None,
+            threads_cache_ttl: DEFAULT_THREADS_CACHE_TTL,
+            modules: Vec::new(),
             current_source: None,
+            path_mappings: Vec::new(),
+            adapter_data_by_source: HashMap::new(),
+            source_texts: HashMap::new(),
+            line_endings: LineEndings::Keep,
             stopped_line: 1,
             stopped_column: 1,
+            stopped_instruction: 0,
+            stopped_breakpoint_ids: Vec::new(),
             breakpoints_by_path: HashMap::new(),
-            vars_ref: 2000,
+            breakpoints_by_ref: HashMap::new(),
+            next_breakpoint_id: BREAKPOINT_ID_BASE,
+            exception_filters: Vec::new(),
+            function_breakpoints: Vec::new(),
+            data_breakpoints: Vec::new(),
+            last_exception: None,
+            symbols: BTreeMap::new(),
+            call_stack: Vec::new(),
+            stop_epoch: 0,
+            variables: HashMap::new(),
+            ref_frames: HashMap::new(),
+            variables_cache: VariablesCache::default(),
+            sort_variables: false,
+            max_variable_depth: DEFAULT_MAX_VARIABLE_DEPTH,
+            value_limit: DEFAULT_VALUE_LIMIT,
+            formatter: Box::new(DefaultFormatter),
+            next_vars_ref: VARS_REF_BASE,
+            evaluator: Box::new(DefaultEvaluator),
+            breakpoint_matcher: Box::new(DefaultMatcher),
+            soft_unsupported: SOFT_UNSUPPORTED.iter().map(|c| c.to_string()).collect(),
+            custom_commands: BTreeMap::new(),
+            events: EventQueue::default(),
+            response_patches: ResponsePatches::default(),
         }
     }
 
-    pub(crate) fn pick_stop_location(&mut self) {
-        if let Some(src) = &self.current_source {
-            if let Some(path) = &src.path {
-                if let Some(lines) = self.breakpoints_by_path.get(path) {
-                    if let Some(first) = lines.first() {
-                        self.stopped_line = *first;
-                        self.stopped_column = 1;
-                        return;
+    fn initial_threads(main_thread_id: i64, name: &str) -> BTreeMap<i64, ThreadState> {
+        let mut threads = BTreeMap::new();
+        threads.insert(
+            main_thread_id,
+            ThreadState {
+                name: name.to_string(),
+                running: true,
+            },
+        );
+        threads
+    }
+
+    // Новый запуск той же сессии: потоки и данные остановки с нуля,
+    // брейкпоинты и launch-конфиг остаются.
+    pub(crate) fn reset_session(&mut self) {
+        self.launched_at = None;
+        self.deferred_run_events.clear();
+        self.single_thread_resume = false;
+        self.last_exception = None;
+        self.threads = Self::initial_threads(self.main_thread_id, &self.main_thread_name);
+        self.invalidate_threads();
+        self.variables.clear();
+        self.ref_frames.clear();
+        self.variables_cache.clear();
+        self.call_stack.clear();
+        self.stopped_line = 1;
+        self.stopped_column = 1;
+        self.stopped_instruction = 0;
+        self.cancel_entry_continue();
+        self.reset_hit_counts();
+        // Адресные watchpoint'ы в новом запуске указывали бы в чужую память
+        self.data_breakpoints
+            .retain(|bp| is_persistent_data_id(&bp.data_id));
+    }
+
+    // disconnect с restart: клиент сейчас переподключится — ждём новый initialize,
+    // но брейкпоинты, launch-конфиг и модули debuggee остаются.
+    pub(crate) fn prepare_reconnect(&mut self) {
+        self.reset_session();
+        self.phase = SessionPhase::Uninitialized;
+        self.configured = false;
+        self.entry_stop_pending = false;
+        self.client = None;
+        self.client_telemetry = false;
+        self.path_format = PathFormat::Path;
+        self.locale = None;
+    }
+
+    // Итоги для баг-репорта: сколько раз какие команды, сколько длилась сессия,
+    // какие брейки срабатывали. Вызывать до teardown — он всё это чистит.
+    pub(crate) fn session_summary(&self) -> String {
+        let mut lines = vec![format!(
+            "Session summary: {:?} since start",
+            self.started_at.elapsed()
+        )];
+        for (name, timing) in &self.metrics.by_command {
+            lines.push(format!(
+                "  {name}: {} calls, {:?} total",
+                timing.count, timing.total
+            ));
+        }
+        let mut hits: Vec<(&str, i64, u64)> = self
+            .breakpoints_by_path
+            .iter()
+            .flat_map(|(path, bps)| bps.iter().map(move |bp| (path.as_str(), bp.line, bp.hits)))
+            .filter(|(_, _, hits)| *hits > 0)
+            .collect();
+        hits.sort();
+        for (path, line, count) in hits {
+            lines.push(format!("  breakpoint {path}:{line}: {count} hits"));
+        }
+        lines.join("\n")
+    }
+
+    pub(crate) fn preserve_focus_on_step(&self) -> bool {
+        self.launch_config
+            .as_ref()
+            .is_some_and(|c| c.preserve_focus_on_step)
+    }
+
+    pub(crate) fn single_thread_mode(&self) -> bool {
+        self.launch_config
+            .as_ref()
+            .is_some_and(|c| c.single_threaded)
+    }
+
+    pub(crate) fn retain_console(&self) -> bool {
+        self.launch_config
+            .as_ref()
+            .is_some_and(|c| c.retain_console_on_restart)
+    }
+
+    // Без retainConsoleOnRestart не копим — иначе память уходит на то, что никто не покажет
+    pub(crate) fn remember_output(&mut self, output: &str) {
+        if !self.retain_console() {
+            return;
+        }
+        self.console_history
+            .extend(output.lines().map(str::to_string));
+        let overflow = self
+            .console_history
+            .len()
+            .saturating_sub(CONSOLE_HISTORY_LIMIT);
+        self.console_history.drain(..overflow);
+    }
+
+    // Только клиенту, который сам попросил: без supportsTelemetry category telemetry
+    // у многих клиентов попадает прямо в консоль пользователя
+    pub(crate) fn telemetry_enabled(&self) -> bool {
+        self.telemetry && self.client_telemetry
+    }
+
+    // Обычный disconnect: от сессии не остаётся ничего, кроме настроек из CLI
+    pub(crate) fn teardown(&mut self) {
+        self.reset_session();
+        self.console_history.clear();
+        self.launch_config = None;
+        self.attached = false;
+        self.breakpoints_by_path.clear();
+        self.breakpoints_by_ref.clear();
+        self.function_breakpoints.clear();
+        self.data_breakpoints.clear();
+        self.exception_filters.clear();
+        self.symbols.clear();
+        self.current_source = None;
+        self.adapter_data_by_source.clear();
+        self.source_texts.clear();
+        self.disconnected = true;
+    }
+
+    fn reset_hit_counts(&mut self) {
+        let all = self
+            .breakpoints_by_path
+            .values_mut()
+            .chain(self.breakpoints_by_ref.values_mut());
+        for bp in all.flatten() {
+            bp.hits = 0;
+        }
+    }
+
+    pub(crate) fn alloc_vars_ref(&mut self) -> i64 {
+        let r = self.next_vars_ref;
+        self.next_vars_ref += 1;
+        r
+    }
+
+    pub(crate) fn alloc_breakpoint_id(&mut self) -> i64 {
+        let id = self.next_breakpoint_id;
+        self.next_breakpoint_id += 1;
+        id
+    }
+
+    // Заменить брейкпоинты файла. Брейк, оставшийся на той же линии, сохраняет свой id,
+    // новые получают id из общего счётчика — id не пересекаются между файлами.
+    // Храним без повторов и по порядку строк, а возвращаем по одному брейку на каждый
+    // запрошенный и в порядке запроса: ответ setBreakpoints сопоставляется по индексу.
+    pub(crate) fn replace_breakpoints(
+        &mut self,
+        key: &SourceKey,
+        requested: &[SourceBreakpoint],
+    ) -> Vec<StoredBreakpoint> {
+        let mut previous = match key {
+            SourceKey::Path(path) => self.breakpoints_by_path.remove(path),
+            SourceKey::Reference(r) => self.breakpoints_by_ref.remove(r),
+        }
+        .unwrap_or_default();
+        let mut stored: Vec<StoredBreakpoint> = Vec::new();
+        let mut response = Vec::with_capacity(requested.len());
+        for src_bp in requested {
+            // То же место дважды в запросе — один брейк, обе позиции ответа получают его
+            if let Some(same) = stored
+                .iter()
+                .find(|bp| bp.line == src_bp.line && bp.column == src_bp.column)
+            {
+                response.push(same.clone());
+                continue;
+            }
+            let (id, hits) = match previous.iter().position(|bp| bp.line == src_bp.line) {
+                Some(pos) => {
+                    let old = previous.remove(pos);
+                    (old.id, old.hits)
+                }
+                None => (self.alloc_breakpoint_id(), 0),
+            };
+            let mut bp = StoredBreakpoint::from_source(id, src_bp);
+            bp.hits = hits;
+            stored.push(bp.clone());
+            response.push(bp);
+        }
+        stored.sort_by_key(|bp| (bp.line, bp.column));
+        match key {
+            SourceKey::Path(path) => {
+                self.breakpoints_by_path.insert(path.clone(), stored);
+            }
+            SourceKey::Reference(r) => {
+                self.breakpoints_by_ref.insert(*r, stored);
+            }
+        }
+        response
+    }
+
+    // Как replace_breakpoints, но ключ — имя функции
+    pub(crate) fn replace_function_breakpoints(
+        &mut self,
+        requested: &[FunctionBreakpoint],
+    ) -> Vec<StoredFunctionBreakpoint> {
+        let mut previous = std::mem::take(&mut self.function_breakpoints);
+        for fbp in requested {
+            let id = match previous.iter().position(|bp| bp.name == fbp.name) {
+                Some(pos) => previous.remove(pos).id,
+                None => self.alloc_breakpoint_id(),
+            };
+            self.function_breakpoints.push(StoredFunctionBreakpoint {
+                id,
+                name: fbp.name.clone(),
+            });
+        }
+        self.function_breakpoints.clone()
+    }
+
+    // Как replace_function_breakpoints, ключ — dataId
+    pub(crate) fn replace_data_breakpoints(
+        &mut self,
+        requested: &[DataBreakpoint],
+    ) -> Vec<StoredDataBreakpoint> {
+        let mut previous = std::mem::take(&mut self.data_breakpoints);
+        for dbp in requested {
+            let id = match previous.iter().position(|bp| bp.data_id == dbp.data_id) {
+                Some(pos) => previous.remove(pos).id,
+                None => self.alloc_breakpoint_id(),
+            };
+            self.data_breakpoints.push(StoredDataBreakpoint {
+                id,
+                data_id: dbp.data_id.clone(),
+            });
+        }
+        self.data_breakpoints.clone()
+    }
+
+    // dataBreakpointInfo: что можно поставить на `name`. Адрес (0x...) — адресный dataId,
+    // иначе ищем переменную в контейнере или во фрейме. None — следить не за чем.
+    pub(crate) fn data_id_for(
+        &self,
+        name: &str,
+        variables_reference: Option<i64>,
+        frame_id: Option<i64>,
+    ) -> Option<String> {
+        let name = name.trim();
+        if let Some(hex) = name.strip_prefix("0x") {
+            let address = u64::from_str_radix(hex, 16).ok()?;
+            return Some(format!("{DATA_ID_ADDR_PREFIX}{}", format_address(address)));
+        }
+        let var = match variables_reference {
+            Some(r) => self.variables.get(&r)?.iter().find(|v| v.name == name),
+            None => self.find_variable_in_frame(name, frame_id),
+        }?;
+        let path = var.evaluate_name.as_deref().unwrap_or(&var.name);
+        Some(format!("{DATA_ID_VAR_PREFIX}{path}"))
+    }
+
+    // Новые символы от бэкенда. Возвращает брейки, которые из-за них стали verified —
+    // о них надо сообщить клиенту Breakpoint event'ом.
+    pub(crate) fn load_symbols<I: IntoIterator<Item = (String, Symbol)>>(
+        &mut self,
+        symbols: I,
+    ) -> Vec<StoredFunctionBreakpoint> {
+        let before: Vec<i64> = self
+            .function_breakpoints
+            .iter()
+            .filter(|bp| self.symbols.contains_key(&bp.name))
+            .map(|bp| bp.id)
+            .collect();
+        self.symbols.extend(symbols);
+        self.function_breakpoints
+            .iter()
+            .filter(|bp| !before.contains(&bp.id) && self.symbols.contains_key(&bp.name))
+            .cloned()
+            .collect()
+    }
+
+    // Для `$sym`: подстрока имени без учёта регистра
+    pub(crate) fn find_symbols(&self, query: &str) -> Vec<(&String, &Symbol)> {
+        let query = query.to_lowercase();
+        self.symbols
+            .iter()
+            .filter(|(name, _)| name.to_lowercase().contains(&query))
+            .collect()
+    }
+
+    // adapterData — непрозрачные данные клиента, отдаём обратно без изменений
+    pub(crate) fn remember_source(&mut self, source: &Source) {
+        if let (Some(key), Some(data)) = (SourceKey::of(source), &source.adapter_data) {
+            self.adapter_data_by_source.insert(key, data.clone());
+        }
+    }
+
+    fn with_adapter_data(&self, mut source: Source) -> Source {
+        if source.adapter_data.is_none() {
+            if let Some(key) = SourceKey::of(&source) {
+                source.adapter_data = self.adapter_data_by_source.get(&key).cloned();
+            }
+        }
+        source
+    }
+
+    // setBreakpoints обычно приходит раньше launch, т.е. до маппингов — уже сохранённые
+    // пути переводим из старой схемы в новую, иначе брейки потеряются.
+    pub(crate) fn set_path_mappings(&mut self, mappings: Vec<(String, String)>) {
+        let old = std::mem::replace(&mut self.path_mappings, mappings);
+        let new = &self.path_mappings;
+        let remap = |p: &str| normalize_path(&reverse_map(new, &map_path(&old, p)));
+
+        self.breakpoints_by_path = std::mem::take(&mut self.breakpoints_by_path)
+            .into_iter()
+            .map(|(p, bps)| (remap(&p), bps))
+            .collect();
+        self.adapter_data_by_source = std::mem::take(&mut self.adapter_data_by_source)
+            .into_iter()
+            .map(|(key, data)| match key {
+                SourceKey::Path(p) => (SourceKey::Path(remap(&p)), data),
+                other => (other, data),
+            })
+            .collect();
+        if let Some(source) = &mut self.current_source {
+            source.path = source.path.as_deref().map(remap);
+        }
+    }
+
+    // Source от клиента -> пути debuggee (ключ для breakpoints_by_path)
+    pub(crate) fn to_remote(&self, mut source: Source) -> Source {
+        source.path = source.path.map(|p| {
+            let p = match self.path_format {
+                PathFormat::Uri => file_uri_to_path(&p).unwrap_or(p),
+                _ => p,
+            };
+            reverse_map(&self.path_mappings, &p)
+        });
+        source
+    }
+
+    // Текст файла по локальному пути, кэш по нормализованному пути.
+    // None — файла нет или он не читается (виртуальный source и т.п.).
+    // На каждый вызов только stat: файл читается заново, лишь когда изменился
+    pub(crate) fn source_text(&mut self, path: &str) -> Option<&SourceText> {
+        let key = normalize_path(path);
+        let Ok(stamp) = FileStamp::of(path) else {
+            self.source_texts.remove(&key);
+            return None;
+        };
+        let fresh = self
+            .source_texts
+            .get(&key)
+            .is_some_and(|(cached, _)| *cached == stamp);
+        if !fresh {
+            let bytes = std::fs::read(path).ok()?;
+            let text = SourceText::new(String::from_utf8_lossy(&bytes).into_owned());
+            self.source_texts.insert(key.clone(), (stamp, text));
+        }
+        self.source_texts.get(&key).map(|(_, text)| text)
+    }
+
+    // Source для ответа клиенту: локальный путь и его adapterData
+    pub(crate) fn to_client(&self, source: Source) -> Source {
+        let mut source = self.with_adapter_data(source);
+        source.path = source.path.map(|p| {
+            let p = map_path(&self.path_mappings, &p);
+            match self.path_format {
+                PathFormat::Uri => path_to_file_uri(&p),
+                _ => p,
+            }
+        });
+        source
+    }
+
+    // Пустой setBreakpoints: файл уходит из карты целиком, а не остаётся с пустым списком
+    pub(crate) fn clear_breakpoints(&mut self, key: &SourceKey) -> Vec<StoredBreakpoint> {
+        match key {
+            SourceKey::Path(path) => self.breakpoints_by_path.remove(path),
+            SourceKey::Reference(r) => self.breakpoints_by_ref.remove(r),
+        }
+        .unwrap_or_default()
+    }
+
+    pub(crate) fn breakpoints_for(&self, key: &SourceKey) -> Option<&Vec<StoredBreakpoint>> {
+        match key {
+            SourceKey::Path(path) => self.breakpoints_by_path.get(path),
+            SourceKey::Reference(r) => self.breakpoints_by_ref.get(r),
+        }
+    }
+
+    fn breakpoints_for_mut(&mut self, key: &SourceKey) -> Option<&mut Vec<StoredBreakpoint>> {
+        match key {
+            SourceKey::Path(path) => self.breakpoints_by_path.get_mut(path),
+            SourceKey::Reference(r) => self.breakpoints_by_ref.get_mut(r),
+        }
+    }
+
+    fn record_hit(&mut self, key: &SourceKey, id: i64) -> u64 {
+        let bp = self
+            .breakpoints_for_mut(key)
+            .and_then(|bps| bps.iter_mut().find(|bp| bp.id == id));
+        match bp {
+            Some(bp) => {
+                bp.hits += 1;
+                bp.hits
+            }
+            None => 0,
+        }
+    }
+
+    // threadName из launch-конфига; без него — остаётся прежнее имя
+    pub(crate) fn set_main_thread_name(&mut self, name: Option<String>) {
+        if let Some(name) = name {
+            self.main_thread_name = name;
+        }
+        if let Some(thread) = self.threads.get_mut(&self.main_thread_id) {
+            thread.name = self.main_thread_name.clone();
+        }
+        self.invalidate_threads();
+    }
+
+    // Звать из всего, что добавляет/удаляет/переименовывает потоки
+    pub(crate) fn invalidate_threads(&mut self) {
+        self.threads_cache = None;
+    }
+
+    pub(crate) fn threads_cache_fresh(&self) -> bool {
+        self.threads_cache
+            .as_ref()
+            .is_some_and(|(at, _)| at.elapsed() < self.threads_cache_ttl)
+    }
+
+    pub(crate) fn thread_list(&mut self) -> Vec<Thread> {
+        if let Some((_, threads)) = self
+            .threads_cache
+            .as_ref()
+            .filter(|_| self.threads_cache_fresh())
+        {
+            return threads.clone();
+        }
+        let single = self.single_thread_mode();
+        let threads: Vec<Thread> = self
+            .threads
+            .iter()
+            .filter(|(id, _)| !single || **id == self.main_thread_id)
+            .map(|(id, t)| Thread {
+                id: *id,
+                name: t.name.clone(),
+            })
+            .collect();
+        self.threads_cache = Some((Instant::now(), threads.clone()));
+        threads
+    }
+
+    pub(crate) fn set_all_running(&mut self, running: bool) {
+        for thread in self.threads.values_mut() {
+            thread.running = running;
+        }
+    }
+
+    pub(crate) fn set_thread_running(&mut self, thread_id: i64, running: bool) {
+        if let Some(thread) = self.threads.get_mut(&thread_id) {
+            thread.running = running;
+        }
+    }
+
+    // Бэкенд остановился в потоке, о котором мы не слышали: без записи threads
+    // вернёт пустой список и UI не покажет остановку
+    pub(crate) fn ensure_thread(&mut self, thread_id: i64) {
+        if !is_thread_id(thread_id) || self.threads.contains_key(&thread_id) {
+            return;
+        }
+        self.threads.insert(
+            thread_id,
+            ThreadState {
+                name: format!("Thread {thread_id}"),
+                running: false,
+            },
+        );
+        self.invalidate_threads();
+    }
+
+    // Неизвестный thread_id считаем работающим — пусть pause остановит всё как раньше
+    pub(crate) fn is_thread_running(&self, thread_id: i64) -> bool {
+        self.threads.get(&thread_id).is_none_or(|t| t.running)
+    }
+
+    // Запущенный таймер entryContinueAfter больше не отправит Continued
+    pub(crate) fn cancel_entry_continue(&mut self) {
+        self.entry_continue_at = None;
+        self.entry_timer_epoch.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // Автоматический continue после entry уже случился на стороне клиента — догоняем состояние
+    pub(crate) fn apply_entry_continue(&mut self) {
+        if self
+            .entry_continue_at
+            .is_some_and(|at| Instant::now() >= at)
+        {
+            self.entry_continue_at = None;
+            self.set_all_running(true);
+        }
+    }
+
+    // Остановка на панике: messages[0] — сама паника, дальше — её причины по порядку.
+    // Стек есть только у внешней: причины — значения ошибок, а не кадры
+    pub(crate) fn raise_panic(&mut self, messages: &[&str]) {
+        let Some((message, causes)) = messages.split_first() else {
+            return;
+        };
+        self.begin_stop();
+        let inner = causes.iter().rev().fold(Vec::new(), |inner, cause| {
+            vec![ExceptionRecord {
+                type_name: "error".to_string(),
+                message: cause.to_string(),
+                stack_trace: None,
+                inner,
+            }]
+        });
+        let stack_trace = self
+            .call_stack
+            .iter()
+            .map(|frame| {
+                let path = frame
+                    .source
+                    .clone()
+                    .and_then(|s| self.to_client(s).path)
+                    .unwrap_or_else(|| "<unknown>".to_string());
+                format!("at {} ({path}:{})", frame.name, frame.line)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.last_exception = Some(ExceptionRecord {
+            type_name: "panic".to_string(),
+            message: message.to_string(),
+            stack_trace: Some(stack_trace),
+            inner,
+        });
+    }
+
+    // Всё, что относится к последней остановке: ссылки на переменные и фреймы устарели
+    pub(crate) fn forget_stop(&mut self) {
+        self.last_exception = None;
+        self.stopped_breakpoint_ids.clear();
+        self.variables.clear();
+        self.ref_frames.clear();
+        self.variables_cache.clear();
+        self.call_stack.clear();
+    }
+
+    // Вызывать перед каждым Stopped event: сбрасывает всё, что было выдано
+    // клиенту на прошлой остановке, и заполняет заново для текущего места.
+    pub(crate) fn begin_stop(&mut self) {
+        // Остановка стоит всех; pause/step, останавливающие один поток, вернут флаг сами
+        self.single_thread_resume = false;
+        self.forget_stop();
+        self.stop_epoch += 1;
+        self.populate_demo_stack();
+        self.track_frame_refs();
+    }
+
+    // Обходим от arguments/locals каждого фрейма вниз по вложенным ссылкам. В ширину —
+    // чтобы у ссылки, достижимой несколькими путями, глубина была по кратчайшему.
+    // Уже виденную ссылку повторно не обходим, так что цикл в данных не зацикливает и нас
+    fn track_frame_refs(&mut self) {
+        for frame in &self.call_stack {
+            let root = |reference| {
+                let origin = RefOrigin {
+                    frame_id: frame.id,
+                    depth: 0,
+                    parent: None,
+                };
+                (reference, origin)
+            };
+            let mut pending = VecDeque::from([root(frame.arguments_ref), root(frame.locals_ref)]);
+            while let Some((reference, origin)) = pending.pop_front() {
+                if reference == 0 || self.ref_frames.contains_key(&reference) {
+                    continue;
+                }
+                self.ref_frames.insert(reference, origin);
+                let child = RefOrigin {
+                    frame_id: frame.id,
+                    depth: origin.depth + 1,
+                    parent: Some(reference),
+                };
+                if let Some(vars) = self.variables.get(&reference) {
+                    pending.extend(vars.iter().map(|v| (v.variables_reference, child)));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn frame_of_ref(&self, reference: i64) -> Option<i64> {
+        self.ref_frames.get(&reference).map(|o| o.frame_id)
+    }
+
+    fn depth_of_ref(&self, reference: i64) -> usize {
+        self.ref_frames.get(&reference).map_or(0, |o| o.depth)
+    }
+
+    // Сама ссылка и все её предки до scope'а
+    fn ref_ancestors(&self, reference: i64) -> HashSet<i64> {
+        let mut ancestors = HashSet::new();
+        let mut current = Some(reference);
+        while let Some(r) = current.filter(|r| ancestors.insert(*r)) {
+            current = self.ref_frames.get(&r).and_then(|o| o.parent);
+        }
+        ancestors
+    }
+
+    // Шаг в пределах верхнего фрейма. Instruction — следующая инструкция той же линии,
+    // line/statement (и по умолчанию) — следующая линия.
+    pub(crate) fn step(&mut self, granularity: Option<&SteppingGranularity>) {
+        match granularity {
+            Some(SteppingGranularity::Instruction) => self.stopped_instruction += 1,
+            _ => {
+                self.stopped_line += 1;
+                self.stopped_column = 1;
+                self.stopped_instruction = 0;
+            }
+        }
+    }
+
+    fn sync_top_frame(&mut self) {
+        if let Some(top) = self.call_stack.first_mut() {
+            top.line = self.stopped_line;
+            top.column = self.stopped_column;
+        }
+    }
+
+    // Функции демо-программы, те же, что в populate_demo_stack; адреса условные
+    pub(crate) fn demo_symbols(module: &str) -> Vec<(String, Symbol)> {
+        [("main", 0x1000), ("compute", 0x1040)]
+            .into_iter()
+            .map(|(name, address)| {
+                let symbol = Symbol {
+                    address,
+                    module: module.to_string(),
+                };
+                (name.to_string(), symbol)
+            })
+            .collect()
+    }
+
+    // Демо-данные вместо настоящего бэкенда: `compute`, вызванный из `main`,
+    // у каждого фрейма свои локальные переменные
+    fn populate_demo_stack(&mut self) {
+        let point_ref = self.alloc_vars_ref();
+        self.variables.insert(
+            point_ref,
+            vec![
+                demo_variable("x", "3", "i32", "point.x", 0),
+                demo_variable("y", "4", "i32", "point.y", 0),
+            ],
+        );
+        let compute_locals = self.alloc_vars_ref();
+        self.variables.insert(
+            compute_locals,
+            vec![
+                demo_variable("demo", "1", "i32", "demo", 0),
+                demo_variable("point", "Point { x: 3, y: 4 }", "Point", "point", point_ref),
+            ],
+        );
+        let compute_args = self.alloc_vars_ref();
+        self.variables.insert(
+            compute_args,
+            vec![demo_variable("factor", "4", "i32", "factor", 0)],
+        );
+        let main_args = self.alloc_vars_ref();
+        self.variables.insert(main_args, Vec::new());
+        let main_locals = self.alloc_vars_ref();
+        self.variables.insert(
+            main_locals,
+            vec![demo_variable("total", "12", "i32", "total", 0)],
+        );
+
+        let module = self.modules.first().map(|m| m.name.clone());
+        // PC: верхний фрейм — на текущей инструкции, main — на адресе возврата из compute
+        let pc = |name: &str, offset: u64| self.symbols.get(name).map(|s| s.address + offset);
+        let compute_pc = pc("compute", self.stopped_instruction as u64 * 4);
+        let main_pc = pc("main", 0x10);
+        self.call_stack = vec![
+            CallFrame {
+                id: frame_id(self.stop_epoch, 0),
+                name: "compute".to_string(),
+                source: self.current_source.clone(),
+                line: self.stopped_line,
+                column: self.stopped_column,
+                arguments_ref: compute_args,
+                locals_ref: compute_locals,
+                module: module.clone(),
+                pc: compute_pc,
+            },
+            CallFrame {
+                id: frame_id(self.stop_epoch, 1),
+                name: "main".to_string(),
+                source: self.current_source.clone(),
+                line: 1,
+                column: 1,
+                arguments_ref: main_args,
+                locals_ref: main_locals,
+                module,
+                pc: main_pc,
+            },
+        ];
+    }
+
+    pub(crate) fn frame(&self, frame_id: i64) -> Option<&CallFrame> {
+        if frame_stop(frame_id) != Some(self.stop_epoch) {
+            return None;
+        }
+        self.call_stack.iter().find(|f| f.id == frame_id)
+    }
+
+    // Без frame_id — верхний фрейм, как делают клиенты для REPL без выбранного фрейма
+    pub(crate) fn frame_or_top(&self, frame_id: Option<i64>) -> Option<&CallFrame> {
+        match frame_id {
+            Some(id) => self.frame(id),
+            None => self.call_stack.first(),
+        }
+    }
+
+    // Верхний уровень фрейма: сначала аргументы, потом локальные
+    pub(crate) fn frame_roots(&self, frame_id: Option<i64>) -> Vec<&Variable> {
+        let frame = match self.frame_or_top(frame_id) {
+            Some(frame) => frame,
+            None => return Vec::new(),
+        };
+        [frame.arguments_ref, frame.locals_ref]
+            .iter()
+            .filter_map(|r| self.variables.get(r))
+            .flatten()
+            .collect()
+    }
+
+    // Дети ссылки в том виде, в каком их отдаёт variables-запрос (значения через formatter).
+    // С sort_variables — по алфавиту, служебные (`__x`) в конце;
+    // элементы массива ([0], [1], ...) всегда в исходном порядке.
+    pub(crate) fn variables_for(
+        &self,
+        reference: i64,
+        format: Option<&ValueFormat>,
+    ) -> Vec<Variable> {
+        if self.depth_of_ref(reference) >= self.max_variable_depth {
+            return vec![Variable {
+                name: "…".to_string(),
+                value: MAX_DEPTH_PLACEHOLDER.to_string(),
+                ..Default::default()
+            }];
+        }
+        let mut vars = self.variables.get(&reference).cloned().unwrap_or_default();
+        if self.sort_variables && !is_indexed(&vars) {
+            vars.sort_by(|a, b| {
+                let internal = |v: &Variable| v.name.starts_with("__");
+                internal(a)
+                    .cmp(&internal(b))
+                    .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+            });
+        }
+        let ancestors = self.ref_ancestors(reference);
+        for var in &mut vars {
+            if ancestors.contains(&var.variables_reference) {
+                var.value = CYCLE_PLACEHOLDER.to_string();
+                var.variables_reference = 0;
+                continue;
+            }
+            var.value = self.formatter.format(var, format, self);
+        }
+        vars
+    }
+
+    // variables_for через кэш: повторное раскрытие узла не пересобирает список.
+    // В кэше только обычный вид — hex запрашивают редко, его собираем каждый раз
+    pub(crate) fn cached_variables(
+        &mut self,
+        reference: i64,
+        format: Option<&ValueFormat>,
+    ) -> Vec<Variable> {
+        if format.and_then(|f| f.hex).unwrap_or(false) {
+            return self.variables_for(reference, format);
+        }
+        if let Some(vars) = self.variables_cache.get(reference) {
+            return vars;
+        }
+        let vars = self.variables_for(reference, None);
+        self.variables_cache.insert(reference, vars.clone());
+        vars
+    }
+
+    // Ищет по имени или evaluateName среди переменных фрейма и их детей
+    pub(crate) fn find_variable_in_frame(
+        &self,
+        name: &str,
+        frame_id: Option<i64>,
+    ) -> Option<&Variable> {
+        let mut pending = self.frame_roots(frame_id);
+        pending.reverse();
+        let mut seen = Vec::new();
+        while let Some(var) = pending.pop() {
+            if var.evaluate_name.as_deref() == Some(name) || var.name == name {
+                return Some(var);
+            }
+            let r = var.variables_reference;
+            if r > 0 && !seen.contains(&r) {
+                seen.push(r);
+                pending.extend(self.variables.get(&r).into_iter().flatten());
+            }
+        }
+        None
+    }
+
+    // setVariable: скаляру — значение выражения, контейнеру — литерал `{ x: 1, y: 2 }`.
+    // Выражения считаются во фрейме, которому принадлежит ссылка, а не в верхнем.
+    // Дети контейнера получают новую ссылку, старая просто перестаёт достигаться.
+    pub(crate) fn set_variable(
+        &mut self,
+        reference: i64,
+        name: &str,
+        value: &str,
+    ) -> Result<Variable, HandlerError> {
+        let current = self
+            .variables
+            .get(&reference)
+            .and_then(|vars| vars.iter().find(|v| v.name == name))
+            .cloned()
+            .ok_or_else(|| HandlerError::NotFound(format!("Unknown variable '{name}'")))?;
+        let frame_id = self.frame_of_ref(reference);
+
+        let mut updated = current.clone();
+        if current.variables_reference > 0 {
+            let old_children = self
+                .variables
+                .get(&current.variables_reference)
+                .cloned()
+                .unwrap_or_default();
+            let parent_name = current.evaluate_name.as_deref().unwrap_or(name);
+            let mut children = Vec::new();
+            for (field, expr) in parse_struct_literal(value).map_err(invalid_value)? {
+                let v = self
+                    .evaluator
+                    .eval(&expr, frame_id, self)
+                    .map_err(invalid_value)?;
+                let type_name = old_children
+                    .iter()
+                    .find(|c| c.name == field)
+                    .and_then(|c| c.type_field.clone())
+                    .unwrap_or_else(|| if v.is_boolean() { "bool" } else { "i32" }.to_string());
+                let evaluate_name = format!("{parent_name}.{field}");
+                children.push(demo_variable(
+                    &field,
+                    &value_to_string(&v),
+                    &type_name,
+                    &evaluate_name,
+                    0,
+                ));
+            }
+            let fields: Vec<String> = children
+                .iter()
+                .map(|c| format!("{}: {}", c.name, c.value))
+                .collect();
+            let type_name = current.type_field.as_deref().unwrap_or_default();
+            updated.value = format!("{type_name} {{ {} }}", fields.join(", "))
+                .trim_start()
+                .to_string();
+            updated.named_variables = Some(children.len() as i64);
+            updated.variables_reference = self.alloc_vars_ref();
+            self.variables.insert(updated.variables_reference, children);
+            if let Some(origin) = self.ref_frames.get(&reference).copied() {
+                let child = RefOrigin {
+                    frame_id: origin.frame_id,
+                    depth: origin.depth + 1,
+                    parent: Some(reference),
+                };
+                self.ref_frames.insert(updated.variables_reference, child);
+            }
+        } else {
+            let v = self
+                .evaluator
+                .eval(value, frame_id, self)
+                .map_err(invalid_value)?;
+            updated.value = value_to_string(&v);
+        }
+
+        if let Some(slot) = self
+            .variables
+            .get_mut(&reference)
+            .and_then(|vars| vars.iter_mut().find(|v| v.name == name))
+        {
+            *slot = updated.clone();
+        }
+        // Поменялось значение — устарели и список с ним, и все родители до корня
+        self.variables_cache.clear();
+        Ok(updated)
+    }
+
+    // Полное значение со всеми детьми (для clipboard), без обрезки
+    pub(crate) fn expand_value(&self, var: &Variable) -> String {
+        self.expand_value_at(var, &mut Vec::new())
+    }
+
+    // path — ссылки от корня раскрытия до текущей; своя на каждый вызов expand_value
+    fn expand_value_at(&self, var: &Variable, path: &mut Vec<i64>) -> String {
+        let reference = var.variables_reference;
+        if reference == 0 {
+            return var.value.clone();
+        }
+        let children = match self.variables.get(&reference) {
+            Some(children) => children,
+            None => return var.value.clone(),
+        };
+        if path.contains(&reference) {
+            return CYCLE_PLACEHOLDER.to_string();
+        }
+        if path.len() >= self.max_variable_depth {
+            return MAX_DEPTH_PLACEHOLDER.to_string();
+        }
+        path.push(reference);
+        let parts: Vec<String> = children
+            .iter()
+            .map(|c| format!("{}: {}", c.name, self.expand_value_at(c, path)))
+            .collect();
+        path.pop();
+        format!("{{ {} }}", parts.join(", "))
+    }
+
+    // Сообщение logpoint'а: {expr} заменяется вычисленным значением
+    pub(crate) fn interpolate_log_message(&self, message: &str) -> String {
+        let mut out = String::new();
+        let mut rest = message;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            match rest[start..].find('}') {
+                Some(end) => {
+                    let expr = &rest[start + 1..start + end];
+                    match self.evaluator.eval(expr, None, self) {
+                        Ok(v) => out.push_str(&value_to_string(&v)),
+                        Err(e) => out.push_str(&format!("<{e}>")),
                     }
+                    rest = &rest[start + end + 1..];
+                }
+                None => {
+                    out.push_str(&rest[start..]);
+                    rest = "";
                 }
             }
         }
-        self.stopped_line = 1;
+        out.push_str(rest);
+        out
+    }
+
+    // Брейки файла лежат по порядку строк (см. replace_breakpoints): logpoint'ы до места
+    // остановки печатаются, стоим на первой строке, где хоть один брейк решил Stop.
+    // Брейки той же строки тоже проверяем — их id уходят в hitBreakpointIds.
+    // Условия и logpoint'ы вычисляются по переменным уже начатой остановки (begin_stop).
+    // Возвращает сообщения logpoint'ов, пройденных до остановки, с их линиями.
+    pub(crate) fn pick_stop_location(&mut self) -> Vec<(String, i64)> {
+        let mut logs = Vec::new();
+        let mut stop: Option<(i64, Vec<i64>)> = None;
+        if let Some(key) = self.current_source.as_ref().and_then(SourceKey::of) {
+            let bps = self.breakpoints_for(&key).cloned().unwrap_or_default();
+            for bp in &bps {
+                if stop.as_ref().is_some_and(|(line, _)| *line != bp.line) {
+                    break;
+                }
+                if !self.breakpoint_matcher.condition_holds(bp, self) {
+                    continue;
+                }
+                let hits = self.record_hit(&key, bp.id);
+                match self.breakpoint_matcher.decide(bp, hits, self) {
+                    BreakpointDecision::Stop { ids } => stop
+                        .get_or_insert_with(|| (bp.line, Vec::new()))
+                        .1
+                        .extend(ids),
+                    BreakpointDecision::Log { message } => logs.push((message, bp.line)),
+                    BreakpointDecision::Continue => {}
+                }
+            }
+        }
+        let (line, ids) = stop.unwrap_or((1, Vec::new()));
+        self.stopped_line = line;
+        self.stopped_breakpoint_ids = ids;
         self.stopped_column = 1;
+        self.stopped_instruction = 0;
+        self.sync_top_frame();
+        logs
+    }
+}
+
+// Фрейм на глубине depth остановки stop_epoch (0 — верхний)
+fn frame_id(stop_epoch: u64, depth: usize) -> i64 {
+    FRAME_ID_BASE + stop_epoch as i64 * FRAMES_PER_STOP + depth as i64
+}
+
+// Номер остановки, на которой выдан frame_id; None — это вообще не id фрейма
+fn frame_stop(frame_id: i64) -> Option<u64> {
+    (frame_id >= FRAME_ID_BASE).then(|| ((frame_id - FRAME_ID_BASE) / FRAMES_PER_STOP) as u64)
+}
+
+pub(crate) fn is_thread_id(id: i64) -> bool {
+    (THREAD_ID_BASE..THREAD_ID_END).contains(&id)
+}
+
+// `{ x: 1, y: a + 2 }` или `Point { ... }` -> [(x, "1"), (y, "a + 2")]
+fn parse_struct_literal(value: &str) -> DynResult<Vec<(String, String)>> {
+    let body = value
+        .trim()
+        .split_once('{')
+        .and_then(|(_, rest)| rest.trim_end().strip_suffix('}'))
+        .ok_or_else(|| format!("Expected a struct literal like '{{ x: 1 }}', got '{value}'"))?;
+    body.split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            let (name, expr) = field
+                .split_once(':')
+                .ok_or_else(|| format!("Expected 'name: value', got '{field}'"))?;
+            Ok((name.trim().to_string(), expr.trim().to_string()))
+        })
+        .collect()
+}
+
+// Новое значение не разобралось или не посчиталось — виновато значение, а не бэкенд
+fn invalid_value(e: Box<dyn std::error::Error>) -> HandlerError {
+    HandlerError::InvalidArgument(e.to_string())
+}
+
+// Массивоподобный родитель: все имена — индексы, "[0]" или просто "0"
+pub(crate) fn is_indexed(vars: &[Variable]) -> bool {
+    !vars.is_empty() && vars.iter().all(is_index_variable)
+}
+
+// Элемент массива, а не именованное поле
+pub(crate) fn is_index_variable(var: &Variable) -> bool {
+    let name = var.name.trim_start_matches('[').trim_end_matches(']');
+    name.parse::<usize>().is_ok()
+}
+
+fn demo_variable(
+    name: &str,
+    value: &str,
+    type_name: &str,
+    evaluate_name: &str,
+    variables_reference: i64,
+) -> Variable {
+    Variable {
+        name: name.to_string(),
+        value: value.to_string(),
+        type_field: Some(type_name.to_string()),
+        evaluate_name: Some(evaluate_name.to_string()),
+        variables_reference,
+        ..Default::default()
     }
 }
@@ -0,0 +1,37 @@
+use dap::events::{Event, OutputEventBody};
+use dap::types::OutputEventCategory;
+use serde_json::{json, Value};
+
+use crate::state::DapState;
+use crate::types::{DapServer, DynResult};
+
+// Output с category telemetry: клиент забирает его себе, в консоль пользователя он не попадает.
+// data — плоские пары ключ/значение, чтобы их было легко агрегировать.
+pub(crate) fn send_telemetry(
+    server: &mut DapServer,
+    st: &DapState,
+    name: &str,
+    data: Value,
+) -> DynResult<()> {
+    if !st.telemetry_enabled() {
+        return Ok(());
+    }
+    st.events.push(
+        server,
+        Event::Output(OutputEventBody {
+            category: Some(OutputEventCategory::Telemetry),
+            output: name.to_string(),
+            data: Some(data),
+            ..Default::default()
+        }),
+    )
+}
+
+// Сколько прошло от launch/restart до первой остановки; последующие остановки не считаем
+pub(crate) fn report_first_stop(server: &mut DapServer, st: &mut DapState) -> DynResult<()> {
+    let Some(launched_at) = st.launched_at.take() else {
+        return Ok(());
+    };
+    let latency = launched_at.elapsed().as_millis() as u64;
+    send_telemetry(server, st, "firstStop", json!({ "latencyMs": latency }))
+}
@@ -0,0 +1,196 @@
+use std::io::{Read, Write};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Трассировка протокола для отладки самого адаптера: RUST_DAP_TRACE=1 пишет
+// в stderr каждый входящий и исходящий фрейм как есть. Консоль клиента не трогает.
+const TRACE_ENV: &str = "RUST_DAP_TRACE";
+
+pub(crate) fn tracing_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        std::env::var(TRACE_ENV)
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false)
+    })
+}
+
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{}.{:03}", now.as_secs(), now.subsec_millis())
+}
+
+pub(crate) fn trace_log(msg: impl AsRef<str>) {
+    if tracing_enabled() {
+        trace_write(msg);
+    }
+}
+
+// Без проверки RUST_DAP_TRACE — для тех, у кого свой флаг (DapState::tracing)
+pub(crate) fn trace_write(msg: impl AsRef<str>) {
+    let line = format!("[{}] [TRACE] {}", timestamp(), msg.as_ref());
+    #[cfg(test)]
+    CAPTURED.with(|captured| captured.borrow_mut().push(line.clone()));
+    eprintln!("{line}");
+}
+
+// В тестах stderr не прочитать — строки trace-лога копятся здесь, по потоку на тест
+#[cfg(test)]
+thread_local! {
+    static CAPTURED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+#[cfg(test)]
+pub(crate) fn take_captured() -> Vec<String> {
+    CAPTURED.with(|captured| std::mem::take(&mut *captured.borrow_mut()))
+}
+
+// Собирает байты потока и отдаёт целые JSON-тела фреймов `Content-Length: N\r\n\r\n<json>`
+#[derive(Default)]
+pub(crate) struct FrameSplitter {
+    buffer: Vec<u8>,
+}
+
+impl FrameSplitter {
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut frames = Vec::new();
+        while let Some(header_end) = find(&self.buffer, b"\r\n\r\n") {
+            let header = String::from_utf8_lossy(&self.buffer[..header_end]).to_string();
+            let length = header
+                .lines()
+                .find_map(|l| l.trim().strip_prefix("Content-Length:"))
+                .and_then(|v| v.trim().parse::<usize>().ok());
+            let length = match length {
+                Some(length) => length,
+                None => {
+                    // Непонятный заголовок: показать как есть и выбросить
+                    frames.push(header);
+                    self.buffer.drain(..header_end + 4);
+                    continue;
+                }
+            };
+            let body_start = header_end + 4;
+            if self.buffer.len() < body_start + length {
+                break;
+            }
+            let body = &self.buffer[body_start..body_start + length];
+            frames.push(String::from_utf8_lossy(body).to_string());
+            self.buffer.drain(..body_start + length);
+            // ServerOutput дописывает "\r\n" после тела
+            while self
+                .buffer
+                .first()
+                .is_some_and(|b| *b == b'\r' || *b == b'\n')
+            {
+                self.buffer.remove(0);
+            }
+        }
+        frames
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+pub(crate) struct TraceReader<R: Read> {
+    inner: R,
+    splitter: Option<FrameSplitter>,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self::with_tracing(inner, tracing_enabled())
+    }
+
+    pub(crate) fn with_tracing(inner: R, enabled: bool) -> Self {
+        Self {
+            inner,
+            splitter: enabled.then(FrameSplitter::default),
+        }
+    }
+}
+
+impl<R: Read> Read for TraceReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(splitter) = &mut self.splitter {
+            for frame in splitter.push(&buf[..n]) {
+                trace_log(format!("--> {frame}"));
+            }
+        }
+        Ok(n)
+    }
+}
+
+pub(crate) struct TraceWriter<W: Write> {
+    inner: W,
+    splitter: Option<FrameSplitter>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self::with_tracing(inner, tracing_enabled())
+    }
+
+    pub(crate) fn with_tracing(inner: W, enabled: bool) -> Self {
+        Self {
+            inner,
+            splitter: enabled.then(FrameSplitter::default),
+        }
+    }
+}
+
+impl<W: Write> Write for TraceWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(splitter) = &mut self.splitter {
+            for frame in splitter.push(&buf[..n]) {
+                trace_log(format!("<-- {frame}"));
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{body}", body.len())
+    }
+
+    #[test]
+    fn whole_frames_in_one_push() {
+        let mut splitter = FrameSplitter::default();
+        let bytes = format!("{}\r\n{}", frame("{\"a\":1}"), frame("{}"));
+        assert_eq!(splitter.push(bytes.as_bytes()), ["{\"a\":1}", "{}"]);
+    }
+
+    #[test]
+    fn frames_split_across_pushes() {
+        let mut splitter = FrameSplitter::default();
+        let bytes = frame("{\"мир\":true}");
+        let (head, tail) = bytes.as_bytes().split_at(10);
+        let (body, rest) = tail.split_at(tail.len() - 3);
+        assert!(splitter.push(head).is_empty());
+        assert!(splitter.push(body).is_empty());
+        assert_eq!(splitter.push(rest), ["{\"мир\":true}"]);
+        assert!(splitter.push(b"").is_empty());
+    }
+
+    #[test]
+    fn unknown_header_is_passed_through_and_dropped() {
+        let mut splitter = FrameSplitter::default();
+        let bytes = format!("X-Junk: 1\r\n\r\n{}", frame("{}"));
+        assert_eq!(splitter.push(bytes.as_bytes()), ["X-Junk: 1", "{}"]);
+    }
+}
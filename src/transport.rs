@@ -0,0 +1,393 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{Map, Value};
+
+use crate::trace::FrameSplitter;
+
+const WOULD_BLOCK_BACKOFF: Duration = Duration::from_millis(5);
+
+// poll_request читает заголовок и тело кусками; ошибка чтения посреди фрейма роняет
+// уже прочитанную часть и весь цикл. Interrupted std повторяет не везде, а WouldBlock
+// (неблокирующий пайп медленного клиента) — нигде, поэтому гасим обе здесь, ниже
+// BufReader. Ok(0) — настоящий EOF — отдаём как есть: по нему цикл и завершается.
+pub(crate) struct RetryReader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> RetryReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self { inner }
+    }
+}
+
+impl<R: Read> Read for RetryReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            match self.inner.read(buf) {
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    std::thread::sleep(WOULD_BLOCK_BACKOFF)
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// Ответы, которые dap 0.4.1 сериализует не по спецификации: у ответа без тела нет
+// `command`, а у части тел (ContinueResponse, ExceptionBreakpointsFilter) ключи в
+// snake_case. Обработчик кладёт сюда правильные поля по request_seq, PatchWriter
+// подставляет их в ответ перед отправкой. seq исходящих сообщений остаётся за Server.
+#[derive(Clone, Default)]
+pub(crate) struct ResponsePatches(Arc<Mutex<HashMap<i64, Map<String, Value>>>>);
+
+impl ResponsePatches {
+    pub(crate) fn set(&self, request_seq: i64, key: &str, value: Value) {
+        if let Ok(mut patches) = self.0.lock() {
+            patches
+                .entry(request_seq)
+                .or_default()
+                .insert(key.to_string(), value);
+        }
+    }
+
+    fn take(&self, request_seq: i64) -> Option<Map<String, Value>> {
+        self.0.lock().ok()?.remove(&request_seq)
+    }
+}
+
+impl std::fmt::Debug for ResponsePatches {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pending = self.0.lock().map(|p| p.len()).unwrap_or_default();
+        write!(f, "ResponsePatches({pending} pending)")
+    }
+}
+
+// Source, SourceBreakpoint и Breakpoint в dap 0.4.1 без rename_all: их поля dap читает
+// и пишет в snake_case, а клиент знает только camelCase. Без перевода sourceReference,
+// adapterData, hitCondition и logMessage клиента молча терялись при разборе
+const SNAKE_CASE_KEYS: [(&str, &str); 8] = [
+    ("sourceReference", "source_reference"),
+    ("adapterData", "adapter_data"),
+    ("presentationHint", "presentation_hint"),
+    ("hitCondition", "hit_condition"),
+    ("logMessage", "log_message"),
+    ("endLine", "end_line"),
+    ("endColumn", "end_column"),
+    ("instructionReference", "instruction_reference"),
+];
+
+fn rename_keys(fields: &mut Map<String, Value>, to_snake: bool) -> bool {
+    let mut renamed = false;
+    for (camel, snake) in SNAKE_CASE_KEYS {
+        let (from, to) = if to_snake {
+            (camel, snake)
+        } else {
+            (snake, camel)
+        };
+        if let Some(value) = fields.remove(from) {
+            fields.insert(to.to_string(), value);
+            renamed = true;
+        }
+    }
+    renamed
+}
+
+// В dap — варианты Command без аргументов: `"arguments": {}`, которые шлют клиенты
+// (по спецификации это пустой объект), dap не разбирает и отвечает ошибкой
+const NO_ARGUMENTS_COMMANDS: [&str; 3] = ["configurationDone", "loadedSources", "threads"];
+
+// Source из запроса, вместе со вложенными sources
+fn source_to_snake_case(source: &mut Value) {
+    let Some(fields) = source.as_object_mut() else {
+        return;
+    };
+    rename_keys(fields, true);
+    for nested in fields
+        .get_mut("sources")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+    {
+        source_to_snake_case(nested);
+    }
+}
+
+// Запрос — в ключи, которые ждёт dap. Трогаем только Source и SourceBreakpoint:
+// у остальных аргументов (например, FunctionBreakpoint.hitCondition) camelCase верный
+fn request_to_snake_case(frame: String) -> String {
+    let Ok(mut message) = serde_json::from_str::<Value>(&frame) else {
+        return frame;
+    };
+    let command = message
+        .get("command")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let set_breakpoints = command == "setBreakpoints";
+    if NO_ARGUMENTS_COMMANDS.contains(&command) {
+        let Some(fields) = message.as_object_mut() else {
+            return frame;
+        };
+        return match fields.remove("arguments") {
+            Some(_) => message.to_string(),
+            None => frame,
+        };
+    }
+    let Some(arguments) = message.get_mut("arguments").and_then(Value::as_object_mut) else {
+        return frame;
+    };
+    if let Some(source) = arguments.get_mut("source") {
+        source_to_snake_case(source);
+    }
+    if set_breakpoints {
+        for bp in arguments
+            .get_mut("breakpoints")
+            .and_then(Value::as_array_mut)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_object_mut)
+        {
+            rename_keys(bp, true);
+        }
+    }
+    message.to_string()
+}
+
+// Исходящее сообщение — обратно в camelCase, на любой глубине. Внутрь adapterData
+// не заходим: это данные клиента, их отдаём как получили
+fn to_camel_case(value: &mut Value) -> bool {
+    match value {
+        Value::Object(fields) => {
+            let mut renamed = rename_keys(fields, false);
+            for (key, nested) in fields.iter_mut() {
+                if key != "adapterData" {
+                    renamed |= to_camel_case(nested);
+                }
+            }
+            renamed
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .fold(false, |renamed, item| to_camel_case(item) | renamed),
+        _ => false,
+    }
+}
+
+// Над FrameCapture: переписывает фреймы запросов до разбора в Server
+// (FrameCapture и trace видят то, что прислал клиент)
+pub(crate) struct SnakeCaseReader<R: Read> {
+    inner: R,
+    splitter: FrameSplitter,
+    pending: VecDeque<u8>,
+}
+
+impl<R: Read> SnakeCaseReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            splitter: FrameSplitter::default(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for SnakeCaseReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pending.is_empty() {
+            let mut chunk = [0; 4096];
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            for frame in self.splitter.push(&chunk[..n]) {
+                let frame = request_to_snake_case(frame);
+                let header = format!("Content-Length: {}\r\n\r\n", frame.len());
+                self.pending.extend(header.bytes().chain(frame.bytes()));
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        for (slot, byte) in buf.iter_mut().zip(self.pending.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+// Под BufWriter Server'а: собирает фреймы целиком и переписывает ответы с патчами
+pub(crate) struct PatchWriter<W: Write> {
+    inner: W,
+    splitter: FrameSplitter,
+    patches: ResponsePatches,
+}
+
+impl<W: Write> PatchWriter<W> {
+    pub(crate) fn new(inner: W, patches: ResponsePatches) -> Self {
+        Self {
+            inner,
+            splitter: FrameSplitter::default(),
+            patches,
+        }
+    }
+
+    fn patched(&self, frame: String) -> String {
+        let Ok(mut message) = serde_json::from_str::<Value>(&frame) else {
+            return frame;
+        };
+        let is_response = message.get("type").and_then(Value::as_str) == Some("response");
+        let patch = message
+            .get("request_seq")
+            .and_then(Value::as_i64)
+            .filter(|_| is_response)
+            .and_then(|seq| self.patches.take(seq));
+        let renamed = to_camel_case(&mut message);
+        match (patch, message.as_object_mut()) {
+            (Some(patch), Some(fields)) => {
+                fields.extend(patch);
+                message.to_string()
+            }
+            _ if renamed => message.to_string(),
+            _ => frame,
+        }
+    }
+}
+
+impl<W: Write> Write for PatchWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for frame in self.splitter.push(buf) {
+            let frame = self.patched(frame);
+            write!(
+                self.inner,
+                "Content-Length: {}\r\n\r\n{frame}\r\n",
+                frame.len()
+            )?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &Value) -> String {
+        let body = body.to_string();
+        format!("Content-Length: {}\r\n\r\n{body}", body.len())
+    }
+
+    fn read_all(input: String) -> Value {
+        let mut translated = String::new();
+        SnakeCaseReader::new(input.as_bytes())
+            .read_to_string(&mut translated)
+            .unwrap();
+        let frames = FrameSplitter::default().push(translated.as_bytes());
+        assert_eq!(frames.len(), 1, "{translated}");
+        serde_json::from_str(&frames[0]).unwrap()
+    }
+
+    // Поток медленного клиента: данные кусками, между ними временные ошибки
+    struct Chunked(VecDeque<std::io::Result<Vec<u8>>>);
+
+    impl Read for Chunked {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.pop_front() {
+                None => Ok(0),
+                Some(Err(e)) => Err(e),
+                Some(Ok(bytes)) => {
+                    buf[..bytes.len()].copy_from_slice(&bytes);
+                    Ok(bytes.len())
+                }
+            }
+        }
+    }
+
+    fn split_frame() -> Chunked {
+        let bytes =
+            frame(&serde_json::json!({ "seq": 1, "type": "request", "command": "threads" }));
+        let (head, tail) = bytes.as_bytes().split_at(30);
+        Chunked(VecDeque::from([
+            Ok(head.to_vec()),
+            Err(ErrorKind::WouldBlock.into()),
+            Err(ErrorKind::Interrupted.into()),
+            Ok(tail.to_vec()),
+        ]))
+    }
+
+    #[test]
+    fn a_frame_split_across_reads_survives_temporary_errors() {
+        let input = std::io::BufReader::new(RetryReader::new(split_frame()));
+        let mut server = dap::server::Server::new(input, std::io::BufWriter::new(Vec::new()));
+        let request = server.poll_request().unwrap().unwrap();
+        assert!(matches!(request.command, dap::requests::Command::Threads));
+        assert!(server.poll_request().unwrap().is_none());
+
+        let input = std::io::BufReader::new(split_frame());
+        let mut server = dap::server::Server::new(input, std::io::BufWriter::new(Vec::new()));
+        assert!(server.poll_request().is_err());
+    }
+
+    #[test]
+    fn source_breakpoint_keys_reach_dap_in_snake_case() {
+        let request = read_all(frame(&serde_json::json!({
+            "seq": 1, "type": "request", "command": "setBreakpoints",
+            "arguments": {
+                "source": { "sourceReference": 7, "adapterData": { "keepMe": 1 } },
+                "breakpoints": [{ "line": 2, "hitCondition": "3", "logMessage": "x" }],
+            },
+        })));
+        let arguments = &request["arguments"];
+        assert_eq!(arguments["source"]["source_reference"], 7);
+        assert_eq!(arguments["source"]["adapter_data"]["keepMe"], 1);
+        assert_eq!(arguments["breakpoints"][0]["hit_condition"], "3");
+        assert_eq!(arguments["breakpoints"][0]["log_message"], "x");
+    }
+
+    #[test]
+    fn function_breakpoints_keep_their_camel_case() {
+        let request = read_all(frame(&serde_json::json!({
+            "seq": 1, "type": "request", "command": "setFunctionBreakpoints",
+            "arguments": { "breakpoints": [{ "name": "main", "hitCondition": "2" }] },
+        })));
+        assert_eq!(request["arguments"]["breakpoints"][0]["hitCondition"], "2");
+    }
+
+    #[test]
+    fn empty_arguments_are_dropped_for_commands_without_them() {
+        let request = read_all(frame(&serde_json::json!({
+            "seq": 1, "type": "request", "command": "loadedSources", "arguments": {},
+        })));
+        assert!(request.get("arguments").is_none(), "{request}");
+        let request = read_all(frame(&serde_json::json!({
+            "seq": 2, "type": "request", "command": "modules", "arguments": {},
+        })));
+        assert_eq!(request["arguments"], serde_json::json!({}));
+    }
+
+    #[test]
+    fn outgoing_sources_are_camel_case_but_adapter_data_is_untouched() {
+        let mut writer = PatchWriter::new(Vec::new(), ResponsePatches::default());
+        let event = serde_json::json!({
+            "seq": 1, "type": "event", "event": "breakpoint",
+            "body": { "breakpoint": {
+                "verified": true, "end_line": 3,
+                "source": { "source_reference": 7, "adapter_data": { "end_line": 1 } },
+            } },
+        });
+        writer.write_all(frame(&event).as_bytes()).unwrap();
+        let frames = FrameSplitter::default().push(&writer.inner);
+        let sent: Value = serde_json::from_str(&frames[0]).unwrap();
+        let breakpoint = &sent["body"]["breakpoint"];
+        assert_eq!(breakpoint["endLine"], 3);
+        assert_eq!(breakpoint["source"]["sourceReference"], 7);
+        assert_eq!(
+            breakpoint["source"]["adapterData"],
+            serde_json::json!({ "end_line": 1 })
+        );
+    }
+}
@@ -1 +1,15 @@
+use std::io::{Read, Write};
+
+use dap::server::Server;
+
+use crate::custom_request::FrameCapture;
+use crate::trace::{TraceReader, TraceWriter};
+use crate::transport::{PatchWriter, RetryReader, SnakeCaseReader};
+
 pub(crate) type DynResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+// Писатель — Box, а не Stdout: тесты подставляют свой буфер
+pub(crate) type DapServer = Server<
+    SnakeCaseReader<FrameCapture<TraceReader<RetryReader<Box<dyn Read>>>>>,
+    PatchWriter<TraceWriter<Box<dyn Write + Send>>>,
+>;
@@ -1,21 +1,342 @@
-use dap::requests::LaunchRequestArguments;
-use serde_json::json;
-
-pub(crate) fn extract_port_from_args(args: &LaunchRequestArguments) -> Option<u16> {
-    let additional_data = args.additional_data.clone().unwrap_or(json!({}));
-
-    if let Some(args) = additional_data.get("args").and_then(|v| v.as_array()) {
-        if args.len() >= 2 {
-            if let Some(arg0) = args[0].as_str() {
-                if arg0 == "--port" {
-                    if let Some(port_str) = args[1].as_str() {
-                        if let Ok(port) = port_str.parse::<u16>() {
-                            return Some(port);
-                        }
-                    }
+use dap::errors::ServerError;
+use dap::requests::Command;
+
+// Префикс совпадает только по границе компонента: "/app" не мапит "/apple"
+fn replace_prefix(path: &str, from: &str, to: &str) -> Option<String> {
+    let from = from.trim_end_matches(['/', '\\']);
+    let rest = path.strip_prefix(from)?;
+    if !rest.is_empty() && !rest.starts_with(['/', '\\']) {
+        return None;
+    }
+    Some(format!("{}{rest}", to.trim_end_matches(['/', '\\'])))
+}
+
+fn apply_mapping<'a, I: Iterator<Item = (&'a str, &'a str)>>(path: &str, pairs: I) -> String {
+    // Самый длинный подходящий префикс выигрывает
+    pairs
+        .filter_map(|(from, to)| Some((from.len(), replace_prefix(path, from, to)?)))
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, mapped)| mapped)
+        .unwrap_or_else(|| path.to_string())
+}
+
+// Hover считаем только для путей вида `a.b[0]` / `mod::X`: ни вызовов, ни операторов,
+// ни служебных `$`-команд — наведение мышью не должно ничего менять в программе
+pub(crate) fn is_hover_safe(expression: &str) -> bool {
+    let starts_ok = expression
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_');
+    starts_ok
+        && expression
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | ':' | '[' | ']'))
+}
+
+// Адреса клиенту — всегда hex с 0x, некоторые клиенты строги к формату
+pub(crate) fn format_address(address: u64) -> String {
+    format!("0x{address:016x}")
+}
+
+// Ключ для сравнения путей: на Windows `C:\A\b.rs` и `c:/a/B.rs` — один файл,
+// на macOS (ФС по умолчанию регистронезависима) — только регистр, на Linux путь как есть.
+pub(crate) fn normalize_path(path: &str) -> String {
+    if cfg!(windows) {
+        path.replace('\\', "/").to_lowercase()
+    } else if cfg!(target_os = "macos") {
+        path.to_lowercase()
+    } else {
+        path.to_string()
+    }
+}
+
+// Путь debuggee -> путь у клиента
+pub(crate) fn map_path(mappings: &[(String, String)], remote: &str) -> String {
+    apply_mapping(
+        remote,
+        mappings.iter().map(|(r, l)| (r.as_str(), l.as_str())),
+    )
+}
+
+// Путь у клиента -> путь debuggee
+pub(crate) fn reverse_map(mappings: &[(String, String)], local: &str) -> String {
+    apply_mapping(
+        local,
+        mappings.iter().map(|(r, l)| (l.as_str(), r.as_str())),
+    )
+}
+
+// pathFormat: uri. Всё, кроме unreserved-символов и разделителей, — в %XX по байтам UTF-8;
+// виндовый `C:\a` становится `file:///C:/a`
+pub(crate) fn path_to_file_uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+// Обратно в путь; не file:// (или битое %-кодирование) — None, путь остаётся как пришёл.
+// Хост (`file://server/share`) отбрасываем: UNC-пути клиенты так не шлют
+pub(crate) fn file_uri_to_path(uri: &str) -> Option<String> {
+    let rest = uri.strip_prefix("file://")?;
+    let rest = &rest[rest.find('/')?..];
+    let mut bytes = Vec::with_capacity(rest.len());
+    let mut iter = rest.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // `/C:/a` -> `C:/a`
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => Some(path[1..].to_string()),
+        _ => Some(path),
+    }
+}
+
+// Имя команды как в протоколе ("setBreakpoints"), из Debug-представления Command
+pub(crate) fn command_name(command: &Command) -> String {
+    let debug = format!("{command:?}");
+    let name = debug
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default();
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// dotenv: `KEY=value`, `export KEY=value`, # комментарии, значения в '...' или "..."
+pub(crate) fn parse_env_file(contents: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+    for (i, raw) in contents.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY=value", i + 1))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(format!("line {}: invalid variable name '{key}'", i + 1));
+        }
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(q @ ('"' | '\'')) => {
+                let (quoted, rest) = split_quoted(&value[1..], q)
+                    .ok_or_else(|| format!("line {}: unterminated quoted value", i + 1))?;
+                // После закрывающей кавычки — только комментарий
+                let rest = rest.trim_start();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(format!(
+                        "line {}: unexpected '{rest}' after the closing quote",
+                        i + 1
+                    ));
                 }
+                quoted
             }
+            // Без кавычек всё после " #" — комментарий
+            _ => value
+                .split_once(" #")
+                .map_or(value, |(v, _)| v)
+                .trim_end()
+                .to_string(),
+        };
+        vars.push((key.to_string(), value));
+    }
+    Ok(vars)
+}
+
+// Значение до закрывающей кавычки q и остаток строки после неё. В "..." работают
+// \n, \t, \r, \\ и \" (прочие `\x` остаются как есть), в '...' — никаких escape'ов
+fn split_quoted(text: &str, q: char) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == q => return Some((value, &text[i + c.len_utf8()..])),
+            '\\' if q == '"' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                escaped @ ('\\' | '"') => value.push(escaped),
+                other => {
+                    value.push('\\');
+                    value.push(other);
+                }
+            },
+            c => value.push(c),
         }
     }
     None
 }
+
+// Клиент закрыл наш stdout: писать больше некуда, сессия окончена
+pub(crate) fn is_broken_pipe(err: &(dyn std::error::Error + 'static)) -> bool {
+    let io = match err.downcast_ref::<ServerError>() {
+        Some(ServerError::IoError(io)) => Some(io),
+        _ => err.downcast_ref::<std::io::Error>(),
+    };
+    io.is_some_and(|io| io.kind() == std::io::ErrorKind::BrokenPipe)
+}
+
+// У ошибок dap Display общий ("Error while deserializing"), подробности — в source:
+// там serde_json пишет, какое поле не так
+pub(crate) fn error_detail(err: &(dyn std::error::Error + 'static)) -> String {
+    err.source()
+        .map_or_else(|| err.to_string(), |source| source.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(contents: &str) -> Vec<(String, String)> {
+        parse_env_file(contents).unwrap()
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn env_file_lines_comments_and_export() {
+        let vars = env("# comment\n\nA=1\nexport B = two words # tail\nC=\n");
+        assert_eq!(
+            vars,
+            vec![pair("A", "1"), pair("B", "two words"), pair("C", "")]
+        );
+    }
+
+    #[test]
+    fn env_file_double_quotes_handle_escapes() {
+        let vars = env(r#"A="say \"hi\" # not a comment"
+B="line\nnext\ttab \\ back"
+C="keep \x""#);
+        assert_eq!(
+            vars,
+            vec![
+                pair("A", "say \"hi\" # not a comment"),
+                pair("B", "line\nnext\ttab \\ back"),
+                pair("C", "keep \\x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn env_file_single_quotes_are_literal() {
+        assert_eq!(env(r"A='no \n escapes'"), vec![pair("A", r"no \n escapes")]);
+    }
+
+    #[test]
+    fn env_file_errors_name_the_line() {
+        let err = |contents| parse_env_file(contents).unwrap_err();
+        assert_eq!(err("A=1\nnot a pair"), "line 2: expected KEY=value");
+        assert_eq!(err("A-B=1"), "line 1: invalid variable name 'A-B'");
+        assert_eq!(err("A=\"open"), "line 1: unterminated quoted value");
+        assert_eq!(err(r#"A="ends \""#), "line 1: unterminated quoted value");
+        assert_eq!(
+            err("\nA=\"x\"junk"),
+            "line 2: unexpected 'junk' after the closing quote"
+        );
+        assert_eq!(env("A=\"x\"  # fine"), vec![pair("A", "x")]);
+    }
+
+    #[test]
+    fn hover_safety() {
+        assert!(is_hover_safe("point.x"));
+        assert!(is_hover_safe("std::env::args"));
+        assert!(is_hover_safe("items[0]"));
+        assert!(!is_hover_safe("f()"));
+        assert!(!is_hover_safe("a + 1"));
+        assert!(!is_hover_safe("$sym"));
+        assert!(!is_hover_safe(""));
+    }
+
+    fn mappings() -> Vec<(String, String)> {
+        vec![
+            pair("/app", "/home/me/proj"),
+            pair("/app/vendor", "/home/me/vendor"),
+        ]
+    }
+
+    #[test]
+    fn mapping_respects_component_boundaries() {
+        let m = mappings();
+        assert_eq!(
+            map_path(&m, "/app/src/main.rs"),
+            "/home/me/proj/src/main.rs"
+        );
+        assert_eq!(map_path(&m, "/app"), "/home/me/proj");
+        assert_eq!(map_path(&m, "/apple/x.rs"), "/apple/x.rs");
+        assert_eq!(map_path(&[pair("/app/", "/l/")], "/app/a.rs"), "/l/a.rs");
+    }
+
+    #[test]
+    fn normalize_path_follows_the_platform() {
+        let key = normalize_path(r"C:\Src\Main.rs");
+        if cfg!(windows) {
+            assert_eq!(key, "c:/src/main.rs");
+        } else if cfg!(target_os = "macos") {
+            assert_eq!(key, r"c:\src\main.rs");
+        } else {
+            assert_eq!(key, r"C:\Src\Main.rs");
+        }
+    }
+
+    #[test]
+    fn longest_prefix_wins_both_ways() {
+        let m = mappings();
+        assert_eq!(map_path(&m, "/app/vendor/x.rs"), "/home/me/vendor/x.rs");
+        assert_eq!(reverse_map(&m, "/home/me/vendor/x.rs"), "/app/vendor/x.rs");
+        assert_eq!(reverse_map(&m, "/home/me/proj/a.rs"), "/app/a.rs");
+        assert_eq!(reverse_map(&m, "/elsewhere/a.rs"), "/elsewhere/a.rs");
+    }
+
+    #[test]
+    fn addresses_are_zero_padded_hex() {
+        assert_eq!(format_address(0x1f), "0x000000000000001f");
+        assert_eq!(format_address(u64::MAX), "0xffffffffffffffff");
+    }
+
+    #[test]
+    fn file_uris_round_trip() {
+        for path in ["/tmp/a b/мир.rs", "C:/work/x#1.rs", "/100%/q?.rs"] {
+            let uri = path_to_file_uri(path);
+            assert!(uri.starts_with("file:///"), "{uri}");
+            assert_eq!(file_uri_to_path(&uri).as_deref(), Some(path), "{uri}");
+        }
+        assert_eq!(path_to_file_uri("/tmp/a b"), "file:///tmp/a%20b");
+        assert_eq!(path_to_file_uri(r"C:\a"), "file:///C:/a");
+    }
+
+    #[test]
+    fn bad_file_uris_are_rejected() {
+        assert_eq!(file_uri_to_path("/tmp/a.rs"), None);
+        assert_eq!(file_uri_to_path("http://host/a"), None);
+        assert_eq!(file_uri_to_path("file:///a%2"), None);
+        assert_eq!(file_uri_to_path("file:///a%zz"), None);
+        assert_eq!(
+            file_uri_to_path("file://server/share/a").as_deref(),
+            Some("/share/a")
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+
+use dap::types::Variable;
+
+pub(crate) const DEFAULT_VARIABLES_CACHE_SIZE: usize = 64;
+
+// Готовые ответы variables (уже отсортированные и отформатированные) по ссылке.
+// LRU: свежие в начале, при переполнении выпадает самый давний. capacity 0 — кэша нет.
+// Сбрасывается целиком на каждой остановке и после setVariable.
+#[derive(Debug)]
+pub(crate) struct VariablesCache {
+    capacity: usize,
+    entries: VecDeque<(i64, Vec<Variable>)>,
+}
+
+impl Default for VariablesCache {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_VARIABLES_CACHE_SIZE,
+            entries: VecDeque::new(),
+        }
+    }
+}
+
+impl VariablesCache {
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.entries.truncate(capacity);
+    }
+
+    pub(crate) fn get(&mut self, reference: i64) -> Option<Vec<Variable>> {
+        let pos = self.entries.iter().position(|(r, _)| *r == reference)?;
+        let entry = self.entries.remove(pos)?;
+        let vars = entry.1.clone();
+        self.entries.push_front(entry);
+        Some(vars)
+    }
+
+    pub(crate) fn insert(&mut self, reference: i64, vars: Vec<Variable>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.retain(|(r, _)| *r != reference);
+        self.entries.push_front((reference, vars));
+        self.entries.truncate(self.capacity);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(name: &str) -> Vec<Variable> {
+        vec![Variable {
+            name: name.to_string(),
+            ..Default::default()
+        }]
+    }
+
+    fn cache(capacity: usize) -> VariablesCache {
+        let mut cache = VariablesCache::default();
+        cache.set_capacity(capacity);
+        cache
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let mut cache = cache(2);
+        cache.insert(1, vars("a"));
+        cache.insert(2, vars("b"));
+        assert!(cache.get(1).is_some());
+        cache.insert(3, vars("c"));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(2).is_none());
+        assert_eq!(cache.get(1).unwrap()[0].name, "a");
+        assert_eq!(cache.get(3).unwrap()[0].name, "c");
+    }
+
+    #[test]
+    fn reinsert_replaces_the_entry() {
+        let mut cache = cache(2);
+        cache.insert(1, vars("old"));
+        cache.insert(1, vars("new"));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(1).unwrap()[0].name, "new");
+    }
+
+    #[test]
+    fn zero_capacity_disables_the_cache() {
+        let mut cache = cache(0);
+        cache.insert(1, vars("a"));
+        assert!(cache.get(1).is_none());
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn shrinking_and_clearing() {
+        let mut cache = cache(3);
+        for r in 1..=3 {
+            cache.insert(r, vars("x"));
+        }
+        cache.set_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(3).is_some());
+        cache.clear();
+        assert_eq!(cache.len(), 0);
+    }
+}